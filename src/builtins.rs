@@ -0,0 +1,364 @@
+use crate::error::{Error, Span};
+use crate::interpreter::{call, compare, equals, Closure, Options, Value};
+use crate::stream::{Piped, Stream};
+use crate::{convert, csv, datetime, files, json, math, strings, toml, yaml};
+use std::cmp::Ordering;
+use std::fs;
+use std::time::Instant;
+
+/// Commands that run in-process on what is piped into them, rather than as external programs.
+const BUILTINS: &[&str] = &[
+    "map", "where", "filter", "reduce", "sort-by", "group-by", "first", "last", "uniq", "count",
+    "get", "from", "to", "json", "math", "timeit", "date",
+];
+
+/// Builtins named after programs that are usually installed as well, which `^name` runs instead.
+const PROGRAMS: &[&str] = &[
+    "ls", "ps", "du", "stat", "mkdir", "rm", "cp", "mv", "uniq", "first", "last", "split", "join",
+    "lines", "date",
+];
+
+/// Whether the command `name` runs as a builtin. A builtin named after a program always does, and
+/// the program runs when the name is written as `^name`, as in `^ls -la`.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+        || files::COMMANDS.contains(&name)
+        || strings::COMMANDS.contains(&name)
+        || convert::COMMANDS.contains(&name)
+}
+
+/// The program the command `name` runs, which is `name` without the `^` that keeps it from
+/// running a builtin.
+pub fn program(name: &str) -> &str {
+    name.strip_prefix('^').unwrap_or(name)
+}
+
+/// Runs the builtin `name` on `input`, what was piped into it, where `spans` locates each of
+/// `args`.
+pub fn run(
+    name: &str,
+    input: Piped,
+    args: &[Value],
+    spans: &[Span],
+    opts: &Options,
+) -> Result<Piped, Error> {
+    match run_builtin(name, input, args, spans, opts) {
+        // Point those reaching for the program at how to run it.
+        Err(Error::Arg(msg, span)) if PROGRAMS.contains(&name) && msg.starts_with("usage:") => {
+            let hint = format!("{} (or `^{}` to run the program)", msg, name);
+            Err(Error::Arg(hint, span))
+        }
+        result => result,
+    }
+}
+
+fn run_builtin(
+    name: &str,
+    input: Piped,
+    args: &[Value],
+    spans: &[Span],
+    opts: &Options,
+) -> Result<Piped, Error> {
+    if files::COMMANDS.contains(&name) {
+        return files::run(name, input, args);
+    }
+    if strings::COMMANDS.contains(&name) {
+        return strings::run(name, input, args, spans);
+    }
+    if convert::COMMANDS.contains(&name) {
+        return convert::run(name, input, args);
+    }
+    if let ("get", [Value::Str(field)]) = (name, args) {
+        return get(input, field.clone());
+    }
+    match (name, args) {
+        ("from", [Value::Str(format), opts @ ..]) => return from(format, input, opts),
+        ("to", [Value::Str(format), opts @ ..]) => return to(format, input, opts),
+        ("math", _) => return math::run(input, args),
+        ("date", _) => return datetime::run(input, args, &opts.clock),
+        // How long the closure takes to run, which is all that is kept of it.
+        ("timeit", [Value::Closure(f)]) => {
+            let started = Instant::now();
+            return match call(f, vec![]) {
+                Ok(_) => Ok(Piped::Value(Value::Duration(
+                    started.elapsed().as_nanos() as i64
+                ))),
+                Err(err) => Err(err),
+            };
+        }
+        ("json", [Value::Str(path)]) => {
+            return match fs::read_to_string(path) {
+                Ok(text) => json::parse(&text).map(Piped::Value),
+                Err(err) => Err(Error::Io(err, None)),
+            };
+        }
+        _ => {}
+    }
+
+    let mut items = input.into_stream()?;
+    match (name, args) {
+        ("map", [Value::Closure(f)]) => {
+            let f = f.clone();
+            Ok(Piped::Stream(Box::new(items.map(move |item| match item {
+                Ok(item) => call(&f, vec![item]),
+                Err(err) => Err(err),
+            }))))
+        }
+        ("where", [Value::Closure(f)]) | ("filter", [Value::Closure(f)]) => {
+            let f = f.clone();
+            Ok(Piped::Stream(Box::new(items.filter_map(
+                move |item| match item {
+                    Ok(item) => match call(&f, vec![item.clone()]) {
+                        Ok(keep) if keep.is_truthy() => Some(Ok(item)),
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    },
+                    Err(err) => Some(Err(err)),
+                },
+            ))))
+        }
+        ("reduce", [Value::Closure(f)]) => match items.next() {
+            Some(Ok(first)) => reduce(items, first, f),
+            Some(Err(err)) => Err(err),
+            None => Err(Error::Arg(
+                String::from("Cannot reduce nothing without a starting value."),
+                None,
+            )),
+        },
+        ("reduce", [init, Value::Closure(f)]) => reduce(items, init.clone(), f),
+        ("sort-by", [key]) => sort_by(items, key, false),
+        ("sort-by", [key, Value::Str(flag)]) if flag == "-r" || flag == "--reverse" => {
+            sort_by(items, key, true)
+        }
+        ("group-by", [key]) => group_by(items, key),
+        ("first", []) => match items.next() {
+            Some(Ok(val)) => Ok(Piped::Value(val)),
+            Some(Err(err)) => Err(err),
+            None => Ok(Piped::Value(Value::Void)),
+        },
+        ("first", [Value::Int(n)]) if *n >= 0 => {
+            Ok(Piped::Stream(Box::new(items.take(*n as usize))))
+        }
+        ("last", []) => match collect(items) {
+            Ok(items) => Ok(Piped::Value(
+                items.into_iter().last().unwrap_or(Value::Void),
+            )),
+            Err(err) => Err(err),
+        },
+        ("last", [Value::Int(n)]) if *n >= 0 => match collect(items) {
+            Ok(items) => {
+                let skip = items.len().saturating_sub(*n as usize);
+                Ok(Piped::Value(Value::Arr(
+                    items.into_iter().skip(skip).collect(),
+                )))
+            }
+            Err(err) => Err(err),
+        },
+        ("uniq", []) => {
+            let mut seen: Vec<Value> = Vec::new();
+            Ok(Piped::Stream(Box::new(items.filter_map(
+                move |item| match item {
+                    Ok(item) if seen.iter().any(|v| equals(v, &item)) => None,
+                    Ok(item) => {
+                        seen.push(item.clone());
+                        Some(Ok(item))
+                    }
+                    Err(err) => Some(Err(err)),
+                },
+            ))))
+        }
+        ("count", []) => match collect(items) {
+            Ok(items) => Ok(Piped::Value(Value::Int(items.len() as i64))),
+            Err(err) => Err(err),
+        },
+        _ => Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+    }
+}
+
+fn usage(name: &str) -> &'static str {
+    match name {
+        "map" => "map { |$x| ... }",
+        "where" => "where { |$x| condition }, or where <condition on the fields of $it>",
+        "filter" => "filter { |$x| condition }",
+        "reduce" => "reduce [start] { |$acc, $x| ... }",
+        "sort-by" => "sort-by <field or closure> [--reverse]",
+        "group-by" => "group-by <field or closure>",
+        "first" => "first [count]",
+        "last" => "last [count]",
+        "uniq" => "uniq",
+        "get" => "get <field>",
+        "from" => "from json|toml|yaml, or from csv|tsv [--separator <char>] [--no-headers]",
+        "to" => {
+            "to json [--compact], to toml|yaml, or to csv|tsv [--separator <char>] [--no-headers]"
+        }
+        "json" => "json <path>",
+        "timeit" => "timeit { || ... }",
+        _ => "count",
+    }
+}
+
+fn collect(items: Stream) -> Result<Vec<Value>, Error> {
+    items.collect::<Result<Vec<Value>, Error>>()
+}
+
+fn reduce(items: Stream, init: Value, f: &Closure) -> Result<Piped, Error> {
+    let mut acc = init;
+    for item in items {
+        acc = call(f, vec![acc, item?])?;
+    }
+    Ok(Piped::Value(acc))
+}
+
+fn get(input: Piped, name: String) -> Result<Piped, Error> {
+    let field = move |val: Value| match val {
+        Value::Record(_) => Ok(val.field(&name).cloned().unwrap_or(Value::Void)),
+        val => Err(Error::Type(
+            format!("Cannot get field {} of {}.", name, val.a_type_name()),
+            None,
+        )),
+    };
+    match input {
+        Piped::Value(val @ Value::Record(_)) => field(val).map(Piped::Value),
+        input => match input.into_stream() {
+            Ok(items) => Ok(Piped::Stream(Box::new(items.map(move |item| match item {
+                Ok(item) => field(item),
+                Err(err) => Err(err),
+            })))),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn text_of(input: Piped) -> Result<String, Error> {
+    match input.into_bytes() {
+        Ok(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => Ok(text),
+            Err(_) => Err(Error::Format(
+                String::from("Cannot parse input that is not UTF-8."),
+                None,
+            )),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+fn from(format: &str, input: Piped, opts: &[Value]) -> Result<Piped, Error> {
+    let text = text_of(input)?;
+    parse_as(format, &text, opts).map(Piped::Value)
+}
+
+fn to(format: &str, input: Piped, opts: &[Value]) -> Result<Piped, Error> {
+    let val = input.into_value()?;
+    write_as(format, &val, opts).map(|text| Piped::Value(Value::Str(text)))
+}
+
+/// Whether `from` and `to` know `format`, such as the extension of a file to `open` or `save`.
+pub fn is_format(format: &str) -> bool {
+    matches!(format, "json" | "toml" | "yaml" | "yml" | "csv" | "tsv")
+}
+
+pub fn parse_as(format: &str, text: &str, opts: &[Value]) -> Result<Value, Error> {
+    match (format, opts) {
+        ("json", []) => json::parse(text),
+        ("toml", []) => toml::parse(text),
+        ("yaml", []) | ("yml", []) => yaml::parse(text),
+        _ => match csv_options(format, opts) {
+            Some((sep, headers)) => csv::parse(text, sep, headers),
+            None => Err(Error::Arg(format!("usage: {}", usage("from")), None)),
+        },
+    }
+}
+
+pub fn write_as(format: &str, val: &Value, opts: &[Value]) -> Result<String, Error> {
+    match (format, opts) {
+        ("json", []) => json::to_string(val, true),
+        ("json", [Value::Str(flag)]) if flag == "-c" || flag == "--compact" => {
+            json::to_string(val, false)
+        }
+        ("toml", []) => toml::to_string(val),
+        ("yaml", []) | ("yml", []) => yaml::to_string(val),
+        _ => match csv_options(format, opts) {
+            Some((sep, headers)) => csv::to_string(val, sep, headers),
+            None => Err(Error::Arg(format!("usage: {}", usage("to")), None)),
+        },
+    }
+}
+
+/// The separator and whether there is a header row, for `csv` or `tsv` and the options after it.
+fn csv_options(format: &str, opts: &[Value]) -> Option<(char, bool)> {
+    let mut sep = match format {
+        "csv" => ',',
+        "tsv" => '\t',
+        _ => return None,
+    };
+    let mut headers = true;
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match opt {
+            Value::Str(flag) if flag == "-n" || flag == "--no-headers" => headers = false,
+            Value::Str(flag) if flag == "-s" || flag == "--separator" => match opts.next() {
+                Some(Value::Str(s)) if s.chars().count() == 1 => sep = s.chars().next().unwrap(),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some((sep, headers))
+}
+
+/// The key of `item` picked out by `key`, which is either a closure or the name of a field.
+fn key_of(key: &Value, item: &Value) -> Result<Value, Error> {
+    match key {
+        Value::Closure(f) => call(f, vec![item.clone()]),
+        Value::Str(name) => match item.field(name) {
+            Some(val) => Ok(val.clone()),
+            None => Ok(Value::Void),
+        },
+        _ => Err(Error::Type(
+            format!("Cannot use {} as a key.", key.a_type_name()),
+            None,
+        )),
+    }
+}
+
+fn sort_by(items: Stream, key: &Value, reverse: bool) -> Result<Piped, Error> {
+    let mut keyed = Vec::new();
+    for item in items {
+        keyed.push(item.and_then(|item| key_of(key, &item).map(|k| (k, item)))?);
+    }
+
+    let mut unordered = None;
+    keyed.sort_by(|(l, _), (r, _)| match compare(l, r) {
+        Some(ordering) if reverse => ordering.reverse(),
+        Some(ordering) => ordering,
+        None => {
+            unordered = Some((l.a_type_name(), r.a_type_name()));
+            Ordering::Equal
+        }
+    });
+    match unordered {
+        Some((l, r)) => Err(Error::Type(
+            format!("Cannot sort {} against {}.", l, r),
+            None,
+        )),
+        None => Ok(Piped::Value(Value::Arr(
+            keyed.into_iter().map(|(_, item)| item).collect(),
+        ))),
+    }
+}
+
+/// A record from each key, as text, to the items with that key, in the order the keys first appear.
+fn group_by(items: Stream, key: &Value) -> Result<Piped, Error> {
+    let mut groups: Vec<(String, Value)> = Vec::new();
+    for item in items {
+        let (k, item) = match item.and_then(|item| key_of(key, &item).map(|k| (k, item))) {
+            Ok((k, item)) => (format!("{}", k), item),
+            Err(err) => return Err(err),
+        };
+        match groups.iter_mut().find(|(name, _)| *name == k) {
+            Some((_, Value::Arr(group))) => group.push(item),
+            _ => groups.push((k, Value::Arr(vec![item]))),
+        }
+    }
+    Ok(Piped::Value(Value::Record(groups)))
+}
@@ -0,0 +1,302 @@
+use crate::error::Span;
+use crate::parser::{BinOp, Expr, Load, Pattern, Prog, Stmt, UnOp};
+use crate::strings;
+use std::collections::HashMap;
+
+type Known = HashMap<String, &'static str>;
+
+/// Problems in `prog` that can be seen before it runs, each with where it is: for now, `match`es
+/// on a value of known type that leave some values of the type out.
+pub fn warnings(prog: &[Stmt]) -> Vec<(Span, String)> {
+    let mut found = Vec::new();
+    check_prog(prog, &mut Known::new(), &mut found);
+    found
+}
+
+fn check_prog(prog: &[Stmt], known: &mut Known, found: &mut Vec<(Span, String)>) {
+    for stmt in prog {
+        check_stmt(stmt, known, found);
+    }
+}
+
+fn check_stmt(stmt: &Stmt, known: &mut Known, found: &mut Vec<(Span, String)>) {
+    match stmt {
+        Stmt::Assign(name, box expr, _) => {
+            check_expr(expr, known, found);
+            match type_of(expr, known) {
+                Some(ty) => known.insert(name.clone(), ty),
+                None => known.remove(name),
+            };
+        }
+        Stmt::Update(box target, _, box expr) => {
+            check_expr(target, known, found);
+            check_expr(expr, known, found);
+            let mut root = target;
+            while let Expr::Field(box e, _) | Expr::Index(box e, _, _) = root {
+                root = e;
+            }
+            if let Expr::Var(name, _) = root {
+                known.remove(name);
+            }
+        }
+        Stmt::Let(_, Pattern::Bind(name), box expr, _) => {
+            check_expr(expr, known, found);
+            match type_of(expr, known) {
+                Some(ty) => known.insert(name.clone(), ty),
+                None => known.remove(name),
+            };
+        }
+        Stmt::Destructure(pattern, box expr, _) | Stmt::Let(_, pattern, box expr, _) => {
+            check_expr(expr, known, found);
+            let mut names = Vec::new();
+            bound(pattern, &mut names);
+            for name in names {
+                known.remove(&name);
+            }
+        }
+        Stmt::Expr(expr) | Stmt::Throw(expr, _) => check_expr(expr, known, found),
+        Stmt::Try(body, catch, finally) => {
+            check_block(body, None, known, found);
+            if let Some((name, handler)) = catch {
+                check_block(handler, name.as_deref(), known, found);
+            }
+            if let Some(cleanup) = finally {
+                check_block(cleanup, None, known, found);
+            }
+        }
+        Stmt::If(box cond, then, otherwise) => {
+            check_expr(cond, known, found);
+            check_block(then, None, known, found);
+            if let Some(otherwise) = otherwise {
+                check_block(otherwise, None, known, found);
+            }
+        }
+        Stmt::For(name, box values, body) => {
+            check_expr(values, known, found);
+            check_block(body, Some(name), known, found);
+        }
+        Stmt::Export(box stmt) => check_stmt(stmt, known, found),
+        Stmt::Load(Load::Import(Some(name)), _, _) => {
+            known.insert(name.clone(), "record");
+        }
+        // What `use` and `source` set isn't known without reading the script.
+        Stmt::Load(_, _, _) => known.clear(),
+    }
+}
+
+/// Checks a block that may or may not run, and which may first bind `name`. Variables it assigns
+/// are still seen after it, so afterwards only those it leaves alone keep a known type.
+fn check_block(
+    prog: &[Stmt],
+    name: Option<&str>,
+    known: &mut Known,
+    found: &mut Vec<(Span, String)>,
+) {
+    let mut inner = known.clone();
+    if let Some(name) = name {
+        inner.remove(name);
+    }
+    check_prog(prog, &mut inner, found);
+    known.retain(|name, ty| inner.get(name) == Some(ty));
+    if let Some(name) = name {
+        known.remove(name);
+    }
+}
+
+fn check_expr(expr: &Expr, known: &Known, found: &mut Vec<(Span, String)>) {
+    match expr {
+        Expr::Binary(_, box l, box r)
+        | Expr::Chain(_, box l, box r)
+        | Expr::Index(box l, box r, _) => {
+            check_expr(l, known, found);
+            check_expr(r, known, found);
+        }
+        Expr::Unary(_, box e) | Expr::Field(box e, _) => check_expr(e, known, found),
+        Expr::Arr(exprs, _) | Expr::Pipe(exprs) => {
+            for expr in exprs {
+                check_expr(expr, known, found);
+            }
+        }
+        Expr::Method(box e, _, args, _)
+        | Expr::Call(box e, args, _)
+        | Expr::Cmd(box e, args, _) => {
+            check_expr(e, known, found);
+            for arg in args {
+                check_expr(arg, known, found);
+            }
+        }
+        // A closure keeps the variables as they were when it was made.
+        Expr::Lambda(params, body, _) => {
+            let mut inner = known.clone();
+            for param in params {
+                inner.remove(param);
+            }
+            check_prog(body, &mut inner, found);
+        }
+        Expr::Match(box subject, arms, span) => {
+            check_expr(subject, known, found);
+            if let Some(msg) = type_of(subject, known).and_then(|ty| uncovered(ty, arms)) {
+                found.push((*span, msg));
+            }
+            for (pattern, guard, body) in arms {
+                let mut inner = known.clone();
+                let mut names = Vec::new();
+                bound(pattern, &mut names);
+                for name in names {
+                    inner.remove(&name);
+                }
+                if let Some(guard) = guard {
+                    check_expr(guard, &inner, found);
+                }
+                check_prog(body, &mut inner, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The type `expr` has whatever it evaluates to, if that can be told without running it.
+fn type_of(expr: &Expr, known: &Known) -> Option<&'static str> {
+    match expr {
+        Expr::Int(_, _) => Some("int"),
+        Expr::Num(_, _) => Some("float"),
+        Expr::Bool(_, _) => Some("bool"),
+        Expr::Null(_) => Some("null"),
+        Expr::Size(_, _) => Some("size"),
+        Expr::Duration(_, _) => Some("duration"),
+        Expr::Str(_, _) => Some("string"),
+        Expr::Regex(_, _) => Some("regex"),
+        Expr::Arr(_, _) => Some("array"),
+        Expr::Lambda(_, _, _) => Some("closure"),
+        Expr::Var(name, _) => known.get(name).copied(),
+        Expr::Unary(UnOp::Not, _) => Some("bool"),
+        Expr::Binary(op, _, _) => match op {
+            BinOp::Or
+            | BinOp::And
+            | BinOp::Eq
+            | BinOp::Ne
+            | BinOp::Lt
+            | BinOp::Le
+            | BinOp::Gt
+            | BinOp::Ge
+            | BinOp::In
+            | BinOp::Match
+            | BinOp::NotMatch => Some("bool"),
+            BinOp::Range | BinOp::RangeInclusive => Some("range"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// What a `match` on a value of type `ty` leaves out, if anything.
+fn uncovered(ty: &str, arms: &[(Pattern, Option<Expr>, Prog)]) -> Option<String> {
+    let patterns: Vec<&Pattern> = arms
+        .iter()
+        .filter(|(_, guard, _)| guard.is_none())
+        .map(|(pattern, _, _)| pattern)
+        .collect();
+    if patterns.iter().any(|pattern| covers(pattern, ty)) {
+        return None;
+    }
+    let literals: Vec<&Expr> = patterns
+        .iter()
+        .filter_map(|pattern| match pattern {
+            Pattern::Value(expr) => Some(expr),
+            _ => None,
+        })
+        .collect();
+    let has = |b: bool| {
+        literals
+            .iter()
+            .any(|lit| matches!(lit, Expr::Bool(v, _) if *v == b))
+    };
+    match ty {
+        "bool" => match (has(true), has(false)) {
+            (true, true) => None,
+            (true, false) => Some(String::from("This match on a bool does not cover false.")),
+            (false, true) => Some(String::from("This match on a bool does not cover true.")),
+            (false, false) => Some(String::from(
+                "This match on a bool covers neither true nor false.",
+            )),
+        },
+        "null" if literals.iter().any(|lit| matches!(lit, Expr::Null(_))) => None,
+        "null" => Some(String::from("This match does not cover null.")),
+        "array" if covers_lengths(&patterns) => None,
+        ty => Some(format!(
+            "This match does not cover every {}; add a `_` arm for the rest.",
+            ty
+        )),
+    }
+}
+
+fn covers(pattern: &Pattern, ty: &str) -> bool {
+    match pattern {
+        Pattern::Any | Pattern::Bind(_) => true,
+        Pattern::Type(name) => name == ty,
+        Pattern::Record(fields) => ty == "record" && fields.is_empty(),
+        Pattern::Arr(before, Some((_, after))) => {
+            ty == "array" && before.is_empty() && after.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Whether array patterns of items that fit anything together fit arrays of every length, as
+/// `[]`, `[$x]` and `[$x, $y, ..]` do.
+fn covers_lengths(patterns: &[&Pattern]) -> bool {
+    let mut exact = Vec::new();
+    let mut at_least = None;
+    for pattern in patterns {
+        if let Pattern::Arr(before, rest) = pattern {
+            let after = match rest {
+                Some((_, after)) => after.as_slice(),
+                None => &[],
+            };
+            if !before.iter().chain(after).all(|item| covers(item, "")) {
+                continue;
+            }
+            let len = before.len() + after.len();
+            match rest {
+                Some(_) => at_least = Some(at_least.map_or(len, |n: usize| n.min(len))),
+                None => exact.push(len),
+            }
+        }
+    }
+    match at_least {
+        Some(n) => (0..n).all(|len| exact.contains(&len)),
+        None => false,
+    }
+}
+
+fn bound(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Bind(name) => names.push(name.clone()),
+        Pattern::Regex(pattern, _) => {
+            if let Ok(re) = strings::compile(pattern) {
+                names.extend(
+                    re.capture_names()
+                        .flatten()
+                        .map(|name| format!("${}", name)),
+                );
+            }
+        }
+        Pattern::Arr(before, rest) => {
+            for item in before {
+                bound(item, names);
+            }
+            if let Some((name, after)) = rest {
+                names.extend(name.iter().cloned());
+                for item in after {
+                    bound(item, names);
+                }
+            }
+        }
+        Pattern::Record(fields) => {
+            for (_, field) in fields {
+                bound(field, names);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,213 @@
+use crate::datetime::{self, DateTime};
+use crate::error::Error;
+use crate::interpreter::Value;
+use crate::stream::Piped;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+pub const COMMANDS: &[&str] = &["into", "typeof", "describe"];
+
+const TYPES: &[&str] = &["int", "float", "string", "bool", "path", "date"];
+
+/// Runs `into <type>`, `typeof` or `describe` on what is piped in, or on the value given after them
+/// when nothing is.
+pub fn run(name: &str, input: Piped, args: &[Value]) -> Result<Piped, Error> {
+    let (target, args) = match (name, args) {
+        ("into", [Value::Str(target), args @ ..]) if TYPES.contains(&target.as_str()) => {
+            (target.clone(), args)
+        }
+        // `datetime` is what other shells call it.
+        ("into", [Value::Str(target), args @ ..]) if target == "datetime" => {
+            (String::from("date"), args)
+        }
+        ("into", _) => return Err(usage(name)),
+        (_, args) => (String::new(), args),
+    };
+    // `into date` may be given the format to read the text in.
+    let (format, args) = match (target.as_str(), args) {
+        ("date", [Value::Str(flag), Value::Str(format), args @ ..])
+            if flag == "-f" || flag == "--format" =>
+        {
+            (Some(format.clone()), args)
+        }
+        _ => (None, args),
+    };
+    let val = match (input, args) {
+        (Piped::Value(Value::Void), [val]) => val.clone(),
+        (input, []) => input.into_value()?,
+        _ => return Err(usage(name)),
+    };
+    match name {
+        "typeof" => Ok(Piped::Value(Value::Str(String::from(val.type_name())))),
+        "describe" => Ok(Piped::Value(Value::Str(describe(&val)))),
+        _ => match val {
+            Value::Arr(vals) => vals
+                .iter()
+                .map(|val| into(&target, val, format.as_deref()))
+                .collect::<Result<Vec<Value>, Error>>()
+                .map(|vals| Piped::Value(Value::Arr(vals))),
+            Value::Range(range) => range
+                .iter()
+                .map(|n| into(&target, &Value::Int(n), format.as_deref()))
+                .collect::<Result<Vec<Value>, Error>>()
+                .map(|vals| Piped::Value(Value::Arr(vals))),
+            val => into(&target, &val, format.as_deref()).map(Piped::Value),
+        },
+    }
+}
+
+fn usage(name: &str) -> Error {
+    let usage = match name {
+        "into" => "into int|float|string|bool|path, or into date [--format <format>]",
+        name => name,
+    };
+    Error::Arg(format!("usage: {}", usage), None)
+}
+
+/// The type of `val` with what it holds, such as `array<int>` or `record<name: string>`.
+pub fn describe(val: &Value) -> String {
+    match val {
+        Value::Arr(vals) if vals.is_empty() => String::from("array"),
+        Value::Arr(vals) => {
+            let items: Vec<String> = vals.iter().map(describe).collect();
+            match items.iter().all(|item| *item == items[0]) {
+                true => format!("array<{}>", items[0]),
+                false => String::from("array<any>"),
+            }
+        }
+        Value::Range(_) => String::from("range<int>"),
+        Value::Record(fields) => format!(
+            "record<{}>",
+            fields
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, describe(v)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        val => String::from(val.type_name()),
+    }
+}
+
+fn into(target: &str, val: &Value, format: Option<&str>) -> Result<Value, Error> {
+    let text = match val {
+        Value::Str(_) | Value::Pipeline(_) => Some(format!("{}", val).trim().to_string()),
+        _ => None,
+    };
+    let converted = match (target, val, &text) {
+        (_, _, Some(text)) if target != "string" && target != "date" => from_text(target, text),
+        ("int", Value::Int(_), _) => Some(val.clone()),
+        ("int", Value::Num(n), _) if n.is_finite() && n.abs() < i64::MAX as f64 => {
+            Some(Value::Int(n.trunc() as i64))
+        }
+        ("int", Value::Bool(b), _) => Some(Value::Int(*b as i64)),
+        ("int", Value::Size(n), _) | ("int", Value::Duration(n), _) => Some(Value::Int(*n)),
+        ("int", Value::Date(date), _) => Some(Value::Int(date.nanos.div_euclid(1_000_000_000))),
+        ("float", Value::Int(n), _) => Some(Value::Num(*n as f64)),
+        ("float", Value::Num(_), _) => Some(val.clone()),
+        ("float", Value::Bool(b), _) => Some(Value::Num(*b as i64 as f64)),
+        ("float", Value::Size(n), _) | ("float", Value::Duration(n), _) => {
+            Some(Value::Num(*n as f64))
+        }
+        ("string", Value::Void, _) => Some(Value::Str(String::new())),
+        ("string", val, _) => Some(Value::Str(format!("{}", val))),
+        ("bool", Value::Bool(_), _) => Some(val.clone()),
+        ("bool", Value::Int(_), _) | ("bool", Value::Num(_), _) | ("bool", Value::Void, _) => {
+            Some(Value::Bool(val.is_truthy()))
+        }
+        ("date", Value::Date(_), _) => Some(val.clone()),
+        ("date", Value::Int(secs), _) => Some(Value::Date(DateTime::from_secs(*secs))),
+        ("date", _, Some(text)) => {
+            return datetime::parse(text, format).map(Value::Date);
+        }
+        _ => None,
+    };
+    match converted {
+        Some(val) => Ok(val),
+        None => Err(Error::Type(
+            match &text {
+                Some(text) => format!("Cannot convert \"{}\" into {}.", text, article(target)),
+                None => format!(
+                    "Cannot convert {} into {}.",
+                    val.a_type_name(),
+                    article(target)
+                ),
+            },
+            None,
+        )),
+    }
+}
+
+fn article(target: &str) -> String {
+    match target {
+        "int" => String::from("an int"),
+        target => format!("a {}", target),
+    }
+}
+
+/// Text read as the type `target`: a number written as in a script, `true` or `false`, or a path.
+fn from_text(target: &str, text: &str) -> Option<Value> {
+    match target {
+        "int" => int(text).map(Value::Int),
+        "float" => match int(text) {
+            Some(n) => Some(Value::Num(n as f64)),
+            None => text.replace('_', "").parse::<f64>().ok().map(Value::Num),
+        },
+        "bool" => match text.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(Value::Bool(true)),
+            "false" | "no" | "0" | "" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        _ => path(text).map(Value::Str),
+    }
+}
+
+/// An int written in decimal, or in hex, octal or binary after `0x`, `0o` or `0b`, with an
+/// optional sign and `_`s between digits.
+fn int(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let digits = digits.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (16, &digits[2..]),
+        Some("0o") | Some("0O") => (8, &digits[2..]),
+        Some("0b") | Some("0B") => (2, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    // Reading the sign with the digits lets the most negative int through.
+    let signed = format!("{}{}", if negative { "-" } else { "" }, digits);
+    i64::from_str_radix(&signed, radix).ok()
+}
+
+/// A path made absolute, with `~` for the home directory and `.` and `..` worked out.
+fn path(text: &str) -> Option<String> {
+    let expanded = match (text.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home, rest)
+        }
+        _ => String::from(text),
+    };
+    if expanded.is_empty() {
+        return None;
+    }
+    let path = Path::new(&expanded);
+    let full = match path.is_absolute() {
+        true => path.to_path_buf(),
+        false => env::current_dir().ok()?.join(path),
+    };
+    let mut normal = PathBuf::new();
+    for part in full.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            part => normal.push(part),
+        }
+    }
+    Some(normal.display().to_string())
+}
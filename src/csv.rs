@@ -0,0 +1,208 @@
+use crate::data;
+use crate::error::Error;
+use crate::interpreter::Value;
+
+/// Parses delimited text, split on `sep`, into an array with a record per row.
+pub fn parse(text: &str, sep: char, headers: bool) -> Result<Value, Error> {
+    let mut parser = Parser { text, sep, pos: 0 };
+    let mut rows: Vec<(usize, Vec<Value>)> = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        if parser.peek().is_none() {
+            break;
+        }
+        let start = parser.pos;
+        rows.push((start, parser.row()?));
+    }
+
+    let mut rows = rows.into_iter();
+    let names: Vec<String> = match headers {
+        true => match rows.next() {
+            Some((_, row)) => row.iter().map(|cell| format!("{}", cell)).collect(),
+            None => return Ok(Value::Arr(Vec::new())),
+        },
+        false => Vec::new(),
+    };
+    let mut records = Vec::new();
+    for (start, row) in rows {
+        let names = match headers {
+            true => names.clone(),
+            false => (1..=row.len()).map(|i| format!("column{}", i)).collect(),
+        };
+        if row.len() != names.len() {
+            return Err(parser.error(
+                start,
+                &format!("expected {} fields but found {}", names.len(), row.len()),
+            ));
+        }
+        records.push(Value::Record(names.into_iter().zip(row).collect()));
+    }
+    Ok(Value::Arr(records))
+}
+
+/// Writes an array of records as delimited text with a header row of every field name, in the order
+/// they first appear.
+pub fn to_string(val: &Value, sep: char, headers: bool) -> Result<String, Error> {
+    let rows: Vec<&Value> = match val {
+        Value::Arr(vals) => vals.iter().collect(),
+        Value::Record(_) => vec![val],
+        _ => {
+            return Err(Error::Type(
+                format!("Cannot write {} as {}.", val.a_type_name(), name(sep)),
+                None,
+            ))
+        }
+    };
+
+    let mut names: Vec<&str> = Vec::new();
+    for row in rows.iter() {
+        if let Value::Record(fields) = row {
+            for (k, _) in fields {
+                if !names.contains(&k.as_str()) {
+                    names.push(k);
+                }
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    if headers && !names.is_empty() {
+        let header: Vec<String> = names.iter().map(|k| quote(k, sep)).collect();
+        lines.push(header.join(&sep.to_string()));
+    }
+    for row in rows {
+        let cells: Vec<&Value> = match row {
+            Value::Record(_) => names
+                .iter()
+                .map(|k| row.field(k).unwrap_or(&Value::Void))
+                .collect(),
+            Value::Arr(vals) => vals.iter().collect(),
+            _ => vec![row],
+        };
+        let mut line = Vec::new();
+        for cell in cells {
+            line.push(cell_text(cell, sep)?);
+        }
+        lines.push(line.join(&sep.to_string()));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn name(sep: char) -> &'static str {
+    match sep {
+        '\t' => "TSV",
+        _ => "CSV",
+    }
+}
+
+fn cell_text(val: &Value, sep: char) -> Result<String, Error> {
+    match val {
+        Value::Void => Ok(String::new()),
+        Value::Str(s) => Ok(quote(s, sep)),
+        Value::Date(_) => Ok(format!("{}", val)),
+        Value::Int(_)
+        | Value::Num(_)
+        | Value::Size(_)
+        | Value::Duration(_)
+        | Value::Bool(_)
+        | Value::Pipeline(_)
+        | Value::Regex(_) => match data::number_text(val) {
+            Some(text) => Ok(text),
+            None => Ok(quote(&format!("{}", val), sep)),
+        },
+        _ => Err(Error::Type(
+            format!(
+                "Cannot write {} in a {} cell.",
+                val.a_type_name(),
+                name(sep)
+            ),
+            None,
+        )),
+    }
+}
+
+/// `s` in quotes if it would otherwise be split, trimmed or read back as a number or date.
+fn quote(s: &str, sep: char) -> String {
+    let special = |c: char| c == sep || c == '"' || c == '\n' || c == '\r';
+    let typed = data::number(s).is_some() || data::date(s).is_some();
+    match s.contains(special) || s.trim() != s || typed {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => String::from(s),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    sep: char,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, pos: usize, msg: &str) -> Error {
+        data::error(name(self.sep), self.text, pos, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_blank_lines(&mut self) {
+        while let Some('\n') | Some('\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// The cells up to the end of the line, not counting line breaks inside quotes.
+    fn row(&mut self) -> Result<Vec<Value>, Error> {
+        let mut cells = Vec::new();
+        loop {
+            let cell = match self.peek() {
+                Some('"') => self.quoted(),
+                _ => Ok(self.unquoted()),
+            };
+            cells.push(cell?);
+            match self.peek() {
+                Some(c) if c == self.sep => self.pos += c.len_utf8(),
+                Some('\r') | Some('\n') | None => return Ok(cells),
+                Some(_) => return Err(self.error(self.pos, "expected a separator after a quote")),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> Value {
+        let start = self.pos;
+        let sep = self.sep;
+        let len = self.text[start..]
+            .find([sep, '\r', '\n'])
+            .unwrap_or(self.text.len() - start);
+        self.pos += len;
+        let cell = &self.text[start..self.pos];
+        match data::number(cell).or_else(|| data::date(cell)) {
+            Some(val) => val,
+            None => Value::Str(String::from(cell)),
+        }
+    }
+
+    fn quoted(&mut self) -> Result<Value, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut cell = String::new();
+        loop {
+            match self.text[self.pos..].find('"') {
+                Some(i) => {
+                    cell.push_str(&self.text[self.pos..self.pos + i]);
+                    self.pos += i + 1;
+                    // A doubled quote stands for one quote inside the cell.
+                    match self.peek() {
+                        Some('"') => {
+                            cell.push('"');
+                            self.pos += 1;
+                        }
+                        _ => return Ok(Value::Str(cell)),
+                    }
+                }
+                None => return Err(self.error(start, "unterminated quoted cell")),
+            }
+        }
+    }
+}
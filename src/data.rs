@@ -0,0 +1,59 @@
+use crate::datetime;
+use crate::error::{Error, Span};
+use crate::interpreter::Value;
+
+pub fn error(format: &str, text: &str, pos: usize, msg: &str) -> Error {
+    let (line, col) = Span::new(pos, pos).line_col(text);
+    Error::Format(
+        format!(
+            "Invalid {} at line {}, column {}: {}.",
+            format, line, col, msg
+        ),
+        None,
+    )
+}
+
+/// The number written in untyped text such as a CSV cell or a YAML scalar, if it is one. Ints
+/// with leading zeros are left alone, so codes like `007` stay strings, and dates are never numbers.
+pub fn number(text: &str) -> Option<Value> {
+    let digits = text.trim_start_matches(['-', '+']);
+    if digits.len() + 1 < text.len() || !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return None;
+    }
+    match text.parse::<i64>() {
+        Ok(n) => Some(Value::Int(n)),
+        Err(_) => match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Some(Value::Num(n)),
+            _ => None,
+        },
+    }
+}
+
+/// The date written in untyped text, if it is an ISO 8601 one such as `2024-01-05` or
+/// `2024-01-05T09:30:00Z`.
+pub fn date(text: &str) -> Option<Value> {
+    let is_day = |b: &[u8]| {
+        b.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+    };
+    match text.get(..10) {
+        Some(day) if is_day(day.as_bytes()) => datetime::parse(&text.to_ascii_uppercase(), None)
+            .ok()
+            .map(Value::Date),
+        _ => None,
+    }
+}
+
+/// A number as written by the text formats, which keep a float's fraction so it reads back as one.
+pub fn number_text(val: &Value) -> Option<String> {
+    match val {
+        Value::Int(n) | Value::Size(n) | Value::Duration(n) => Some(format!("{}", n)),
+        Value::Num(n) if n.is_finite() => Some(format!("{:?}", n)),
+        _ => None,
+    }
+}
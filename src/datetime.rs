@@ -0,0 +1,740 @@
+use crate::error::Error;
+use crate::interpreter::Value;
+use crate::stream::Piped;
+use std::convert::TryInto;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SEC: i64 = 1_000_000_000;
+
+const MONTHS: &[&str] = &[
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: &[&str] = &[
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// The ISO 8601 layouts `date parse` tries when it is given no format.
+const ISO_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M%z",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d",
+];
+
+/// An instant, as nanoseconds since the Unix epoch, and the offset from UTC in seconds that it is
+/// shown in. Dates from 1677 to 2262 fit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub nanos: i64,
+    pub offset: i32,
+}
+
+/// Where `date now` reads the time, and the offset `date to-local` moves dates to. A fixed clock
+/// makes both repeatable, so scripts that use them can be tested.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    /// Always this time, whose offset is taken as the local one.
+    Fixed(DateTime),
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime {
+        match self {
+            Clock::System => {
+                let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+                    Ok(since) => since.as_nanos() as i64,
+                    Err(before) => -(before.duration().as_nanos() as i64),
+                };
+                DateTime {
+                    nanos,
+                    offset: self.local_offset(nanos),
+                }
+            }
+            Clock::Fixed(now) => *now,
+        }
+    }
+
+    pub fn local_offset(&self, nanos: i64) -> i32 {
+        match self {
+            Clock::System => system_offset(nanos.div_euclid(SEC)),
+            Clock::Fixed(now) => now.offset,
+        }
+    }
+}
+
+struct Parts {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    nanos: i64,
+    /// From 0 for Sunday.
+    weekday: usize,
+    /// From 1 for the first of January.
+    yday: i64,
+}
+
+impl DateTime {
+    pub fn from_secs(secs: i64) -> DateTime {
+        DateTime {
+            nanos: secs.saturating_mul(SEC),
+            offset: 0,
+        }
+    }
+
+    pub fn with_offset(self, offset: i32) -> DateTime {
+        DateTime { offset, ..self }
+    }
+
+    /// The date of a calendar date and a time of day at `offset`, or None when there is no such
+    /// date or it does not fit.
+    fn from_parts(
+        (year, month, day): (i64, i64, i64),
+        (hour, minute, second, nanos): (i64, i64, i64, i64),
+        offset: i32,
+    ) -> Option<DateTime> {
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+            || !(0..24).contains(&hour)
+            || !(0..60).contains(&minute)
+            || !(0..60).contains(&second)
+        {
+            return None;
+        }
+        let secs = days_from_civil(year, month, day)
+            .checked_mul(86_400)?
+            .checked_add(hour * 3600 + minute * 60 + second - offset as i64)?;
+        Some(DateTime {
+            nanos: secs.checked_mul(SEC)?.checked_add(nanos)?,
+            offset,
+        })
+    }
+
+    fn parts(&self) -> Parts {
+        let local = self.nanos as i128 + self.offset as i128 * SEC as i128;
+        let secs = local.div_euclid(SEC as i128) as i64;
+        let (days, time) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        let (year, month, day) = civil_from_days(days);
+        Parts {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time % 3600 / 60,
+            second: time % 60,
+            nanos: local.rem_euclid(SEC as i128) as i64,
+            // The epoch fell on a Thursday.
+            weekday: (days + 4).rem_euclid(7) as usize,
+            yday: days - days_from_civil(year, 1, 1) + 1,
+        }
+    }
+
+    pub fn format(&self, format: &str) -> Result<String, Error> {
+        let items = items(format)?;
+        let p = self.parts();
+        let mut text = String::new();
+        for item in items {
+            let spec = match item {
+                Item::Lit(c) => {
+                    text.push(c);
+                    continue;
+                }
+                Item::Space => {
+                    text.push(' ');
+                    continue;
+                }
+                Item::Spec(spec) => spec,
+            };
+            let field = match spec {
+                "Y" => format!("{:04}", p.year),
+                "y" => format!("{:02}", p.year.rem_euclid(100)),
+                "m" => format!("{:02}", p.month),
+                "d" => format!("{:02}", p.day),
+                "e" => format!("{:>2}", p.day),
+                "j" => format!("{:03}", p.yday),
+                "H" => format!("{:02}", p.hour),
+                "I" => format!("{:02}", (p.hour + 11) % 12 + 1),
+                "p" => String::from(if p.hour < 12 { "AM" } else { "PM" }),
+                "M" => format!("{:02}", p.minute),
+                "S" => format!("{:02}", p.second),
+                "f" => format!("{:09}", p.nanos),
+                ".f" => fraction(p.nanos),
+                "b" | "h" => String::from(&MONTHS[p.month as usize - 1][..3]),
+                "B" => String::from(MONTHS[p.month as usize - 1]),
+                "a" => String::from(&WEEKDAYS[p.weekday][..3]),
+                "A" => String::from(WEEKDAYS[p.weekday]),
+                "z" => offset_text(self.offset, ""),
+                ":z" => offset_text(self.offset, ":"),
+                "Z" if self.offset == 0 => String::from("UTC"),
+                "Z" => offset_text(self.offset, ":"),
+                _ => format!("{}", self.nanos.div_euclid(SEC)),
+            };
+            text.push_str(&field);
+        }
+        Ok(text)
+    }
+
+    pub fn to_record(self) -> Value {
+        let p = self.parts();
+        let fields = vec![
+            ("year", Value::Int(p.year)),
+            ("month", Value::Int(p.month)),
+            ("day", Value::Int(p.day)),
+            ("hour", Value::Int(p.hour)),
+            ("minute", Value::Int(p.minute)),
+            ("second", Value::Int(p.second)),
+            ("nanosecond", Value::Int(p.nanos)),
+            ("weekday", Value::Str(String::from(WEEKDAYS[p.weekday]))),
+            ("offset", Value::Str(offset_text(self.offset, ":"))),
+        ];
+        Value::Record(
+            fields
+                .into_iter()
+                .map(|(k, v)| (String::from(k), v))
+                .collect(),
+        )
+    }
+}
+
+/// ISO 8601 with the offset, as in `2024-01-05T09:30:00Z` or `2024-01-05T10:30:00.250+01:00`.
+impl Display for DateTime {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let p = self.parts();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{}",
+            p.year,
+            p.month,
+            p.day,
+            p.hour,
+            p.minute,
+            p.second,
+            fraction(p.nanos),
+            match self.offset {
+                0 => String::from("Z"),
+                offset => offset_text(offset, ":"),
+            }
+        )
+    }
+}
+
+/// A fraction of a second as `.250`, `.250100` or `.250100001`, or nothing for none.
+fn fraction(nanos: i64) -> String {
+    let digits = format!("{:09}", nanos);
+    match (nanos, nanos % 1_000_000, nanos % 1000) {
+        (0, _, _) => String::new(),
+        (_, 0, _) => format!(".{}", &digits[..3]),
+        (_, _, 0) => format!(".{}", &digits[..6]),
+        _ => format!(".{}", digits),
+    }
+}
+
+fn offset_text(offset: i32, sep: &str) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs() / 60;
+    format!("{}{:02}{}{:02}", sign, minutes / 60, sep, minutes % 60)
+}
+
+fn is_leap(year: i64) -> bool {
+    year.rem_euclid(4) == 0 && (year.rem_euclid(100) != 0 || year.rem_euclid(400) == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The civil date from a count of days since the epoch, after Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let (era, doe) = (z.div_euclid(146_097), z.rem_euclid(146_097));
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, yoe) = (year.div_euclid(400), year.rem_euclid(400));
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+enum Item {
+    Lit(char),
+    /// Whitespace, which any amount of whitespace matches when parsing.
+    Space,
+    Spec(&'static str),
+}
+
+/// The directives a date format may use after `%`.
+const SPECS: &[&str] = &[
+    "Y", "y", "m", "d", "e", "j", "H", "I", "p", "M", "S", "f", ".f", "b", "h", "B", "a", "A", "z",
+    ":z", "Z", "s",
+];
+
+fn items(format: &str) -> Result<Vec<Item>, Error> {
+    let invalid =
+        |msg: String| Error::Format(format!("Invalid date format {}: {}.", format, msg), None);
+    let mut found = Vec::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            found.push(Item::Space);
+            continue;
+        }
+        if c != '%' {
+            found.push(Item::Lit(c));
+            continue;
+        }
+        let spec = match chars.next() {
+            Some(c @ (':' | '.')) => match chars.next() {
+                Some(d) => format!("{}{}", c, d),
+                None => format!("{}", c),
+            },
+            Some(c) => format!("{}", c),
+            None => return Err(invalid(String::from("it ends with %"))),
+        };
+        let expanded = match spec.as_str() {
+            "%" => vec![Item::Lit('%')],
+            "n" => vec![Item::Lit('\n')],
+            "t" => vec![Item::Lit('\t')],
+            "T" => items("%H:%M:%S").unwrap_or_default(),
+            "F" => items("%Y-%m-%d").unwrap_or_default(),
+            "D" => items("%m/%d/%y").unwrap_or_default(),
+            "R" => items("%H:%M").unwrap_or_default(),
+            spec => match SPECS.iter().find(|s| **s == spec) {
+                Some(spec) => vec![Item::Spec(spec)],
+                None => return Err(invalid(format!("%{} is not a directive", spec))),
+            },
+        };
+        found.extend(expanded);
+    }
+    Ok(found)
+}
+
+pub fn parse(text: &str, format: Option<&str>) -> Result<DateTime, Error> {
+    match format {
+        Some(format) => match items(format) {
+            Ok(items) => match parse_items(text, &items) {
+                Some(date) => Ok(date),
+                None => Err(Error::Format(
+                    format!(
+                        "Cannot read \"{}\" as a date in the format {}.",
+                        text, format
+                    ),
+                    None,
+                )),
+            },
+            Err(err) => Err(err),
+        },
+        None => {
+            let iso = ISO_FORMATS.iter().find_map(|format| {
+                items(format)
+                    .ok()
+                    .and_then(|items| parse_items(text, &items))
+            });
+            match iso {
+                Some(date) => Ok(date),
+                None => Err(Error::Format(
+                    format!("Cannot read \"{}\" as an ISO 8601 date.", text),
+                    None,
+                )),
+            }
+        }
+    }
+}
+
+fn digits(text: &str, min: usize, max: usize) -> Option<(i64, usize, &str)> {
+    let len = text
+        .bytes()
+        .take(max)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    match len >= min {
+        true => Some((text[..len].parse().ok()?, len, &text[len..])),
+        false => None,
+    }
+}
+
+/// Takes the name in `names`, or its first three letters, that `text` starts with.
+fn name<'t>(text: &'t str, names: &[&str]) -> Option<(usize, &'t str)> {
+    let starts = |prefix: &str| {
+        text.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    };
+    for (i, name) in names.iter().enumerate() {
+        if starts(name) {
+            return Some((i, &text[name.len()..]));
+        }
+    }
+    for (i, name) in names.iter().enumerate() {
+        if starts(&name[..3]) {
+            return Some((i, &text[3..]));
+        }
+    }
+    None
+}
+
+/// Takes an offset such as `Z`, `UTC`, `+01:00`, `-0800` or `+05` from the start of `text`.
+fn offset(text: &str) -> Option<(i32, &str)> {
+    for utc in ["Z", "z", "UTC", "GMT"] {
+        if let Some(rest) = text.strip_prefix(utc) {
+            return Some((0, rest));
+        }
+    }
+    let sign = match text.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return None,
+    };
+    let (hours, _, rest) = digits(&text[1..], 2, 2)?;
+    let rest = rest.strip_prefix(':').unwrap_or(rest);
+    let (minutes, rest) = match digits(rest, 2, 2) {
+        Some((minutes, _, rest)) => (minutes, rest),
+        None => (0, rest),
+    };
+    match hours < 24 && minutes < 60 {
+        true => Some((sign * (hours * 3600 + minutes * 60) as i32, rest)),
+        false => None,
+    }
+}
+
+pub fn parse_offset(text: &str) -> Result<i32, Error> {
+    match offset(text) {
+        Some((offset, "")) => Ok(offset),
+        _ => Err(Error::Arg(
+            format!(
+                "Invalid UTC offset {}: expected one such as +01:00 or Z.",
+                text
+            ),
+            None,
+        )),
+    }
+}
+
+fn parse_items(text: &str, items: &[Item]) -> Option<DateTime> {
+    let (mut year, mut month, mut day, mut yday) = (1970, 1, 1, None);
+    let (mut hour, mut minute, mut second, mut nanos) = (0, 0, 0, 0);
+    let (mut hour12, mut pm, mut offset_secs, mut unix) = (None, None, 0, None);
+    let mut rest = text;
+    for item in items {
+        rest = match item {
+            Item::Lit(c) => rest.strip_prefix(*c)?,
+            Item::Space => rest.trim_start(),
+            Item::Spec("Y") => {
+                let (sign, unsigned) = match rest.strip_prefix('-') {
+                    Some(unsigned) => (-1, unsigned),
+                    None => (1, rest),
+                };
+                let (n, _, rest) = digits(unsigned, 4, 4)?;
+                year = sign * n;
+                rest
+            }
+            Item::Spec("y") => {
+                let (n, _, rest) = digits(rest, 2, 2)?;
+                // As POSIX has it, 69 to 99 are in the 1900s and the rest in the 2000s.
+                year = if n < 69 { 2000 + n } else { 1900 + n };
+                rest
+            }
+            Item::Spec(spec @ ("m" | "d" | "e" | "H" | "I" | "M" | "S")) => {
+                let text = match *spec {
+                    "e" => rest.trim_start(),
+                    _ => rest,
+                };
+                let (n, _, rest) = digits(text, 1, 2)?;
+                match *spec {
+                    "m" => month = n,
+                    "d" | "e" => day = n,
+                    "H" => hour = n,
+                    "I" => hour12 = Some(n),
+                    "M" => minute = n,
+                    _ => second = n,
+                }
+                rest
+            }
+            Item::Spec("j") => {
+                let (n, _, rest) = digits(rest, 1, 3)?;
+                yday = Some(n);
+                rest
+            }
+            Item::Spec("p") => match rest.get(..2).map(|s| s.to_ascii_uppercase()) {
+                Some(ampm) if ampm == "AM" || ampm == "PM" => {
+                    pm = Some(ampm == "PM");
+                    &rest[2..]
+                }
+                _ => return None,
+            },
+            Item::Spec(spec @ ("f" | ".f")) => {
+                let text = match (*spec, rest.strip_prefix('.')) {
+                    (".f", Some(text)) => text,
+                    (".f", None) => continue,
+                    _ => rest,
+                };
+                let (n, len, rest) = digits(text, 1, 9)?;
+                nanos = n * 10i64.pow(9 - len as u32);
+                // Digits past nanoseconds are dropped.
+                rest.trim_start_matches(|c: char| c.is_ascii_digit())
+            }
+            Item::Spec("b" | "h" | "B") => {
+                let (i, rest) = name(rest, MONTHS)?;
+                month = i as i64 + 1;
+                rest
+            }
+            Item::Spec("a" | "A") => name(rest, WEEKDAYS)?.1,
+            Item::Spec("z" | ":z" | "Z") => {
+                let (offset, rest) = offset(rest)?;
+                offset_secs = offset;
+                rest
+            }
+            Item::Spec(_) => {
+                let (sign, unsigned) = match rest.strip_prefix('-') {
+                    Some(unsigned) => (-1, unsigned),
+                    None => (1, rest),
+                };
+                let (n, _, rest) = digits(unsigned, 1, 18)?;
+                unix = Some(sign * n);
+                rest
+            }
+        };
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    if let Some(secs) = unix {
+        let nanos = secs.checked_mul(SEC)?.checked_add(nanos)?;
+        return Some(DateTime {
+            nanos,
+            offset: offset_secs,
+        });
+    }
+    if let Some(h) = hour12 {
+        if !(1..=12).contains(&h) {
+            return None;
+        }
+        hour = h % 12 + if pm == Some(true) { 12 } else { 0 };
+    }
+    if let Some(yday) = yday {
+        let days = if is_leap(year) { 366 } else { 365 };
+        if !(1..=days).contains(&yday) {
+            return None;
+        }
+        let (_, m, d) = civil_from_days(days_from_civil(year, 1, 1) + yday - 1);
+        month = m;
+        day = d;
+    }
+    DateTime::from_parts(
+        (year, month, day),
+        (hour, minute, second, nanos),
+        offset_secs,
+    )
+}
+
+/// The local offset from UTC at `secs`, from the time zone file `TZ` names or else
+/// `/etc/localtime`.
+fn system_offset(secs: i64) -> i32 {
+    let path = match env::var("TZ") {
+        Ok(tz) if tz.is_empty() || tz == "UTC" || tz == "UTC0" => return 0,
+        Ok(tz) => match tz.trim_start_matches(':') {
+            tz if tz.starts_with('/') => PathBuf::from(tz),
+            tz => Path::new("/usr/share/zoneinfo").join(tz),
+        },
+        Err(_) => PathBuf::from("/etc/localtime"),
+    };
+    match fs::read(path) {
+        Ok(data) => tzif_offset(&data, secs).unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+
+/// The offset in effect at `secs` in a compiled time zone file, as `man tzfile` describes them.
+fn tzif_offset(data: &[u8], secs: i64) -> Option<i32> {
+    let int = |at: usize, size: usize| -> Option<i64> {
+        let bytes = data.get(at..at + size)?;
+        match size {
+            4 => Some(i32::from_be_bytes(bytes.try_into().ok()?) as i64),
+            _ => Some(i64::from_be_bytes(bytes.try_into().ok()?)),
+        }
+    };
+    // isutcnt, isstdcnt, leapcnt, timecnt, typecnt and charcnt.
+    let counts = |at: usize| -> Option<Vec<usize>> {
+        (0..6)
+            .map(|i| int(at + 20 + 4 * i, 4).map(|n| n as usize))
+            .collect()
+    };
+    if data.get(..4)? != b"TZif" {
+        return None;
+    }
+    let mut c = counts(0)?;
+    let (mut at, mut size) = (44, 4);
+    // Version 2 and later repeat the data with 64-bit times after the 32-bit version.
+    if *data.get(4)? >= b'2' {
+        at += c[3] * 5 + c[4] * 6 + c[5] + c[2] * 8 + c[1] + c[0];
+        c = counts(at)?;
+        at += 44;
+        size = 8;
+    }
+    let (times, typecnt) = (c[3], c[4]);
+    let mut kind = 0;
+    for i in 0..times {
+        match int(at + i * size, size)? <= secs {
+            true => kind = *data.get(at + times * size + i)? as usize,
+            false => break,
+        }
+    }
+    match kind < typecnt {
+        true => int(at + times * (size + 1) + kind * 6, 4).map(|offset| offset as i32),
+        false => None,
+    }
+}
+
+/// Runs `date <command>`, where `clock` gives the time for `date now` and the offset for
+/// `date to-local`. Bare `date` is `date now`, and `date +<format>` formats the time now, as the
+/// program does.
+pub fn run(input: Piped, args: &[Value], clock: &Clock) -> Result<Piped, Error> {
+    let (command, args) = match (args, &input) {
+        ([], Piped::Value(Value::Void)) => return Ok(Piped::Value(Value::Date(clock.now()))),
+        ([Value::Str(spec)], Piped::Value(Value::Void)) if spec.starts_with('+') => {
+            return clock
+                .now()
+                .format(&spec[1..])
+                .map(|s| Piped::Value(Value::Str(s)))
+        }
+        ([Value::Str(command), args @ ..], _) => (command.as_str(), args),
+        _ => return Err(usage()),
+    };
+    let (spec, rest): (Option<String>, Vec<Value>) = match (command, args) {
+        ("now", []) => return Ok(Piped::Value(Value::Date(clock.now()))),
+        ("parse", args) => {
+            let mut spec = None;
+            let mut rest = Vec::new();
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                match (arg, args.as_slice()) {
+                    (Value::Str(flag), [Value::Str(format), ..])
+                        if flag == "-f" || flag == "--format" =>
+                    {
+                        spec = Some(format.clone());
+                        args.next();
+                    }
+                    (Value::Str(flag), _) if flag.starts_with('-') && flag.len() > 1 => {
+                        return Err(usage())
+                    }
+                    (arg, _) => rest.push(arg.clone()),
+                }
+            }
+            (spec, rest)
+        }
+        ("format", [Value::Str(spec), rest @ ..])
+        | ("to-offset", [Value::Str(spec), rest @ ..]) => (Some(spec.clone()), rest.to_vec()),
+        ("to-utc", rest) | ("to-local", rest) | ("parts", rest) => (None, rest.to_vec()),
+        _ => return Err(usage()),
+    };
+    let offset = match (command, &spec) {
+        ("to-offset", Some(spec)) => parse_offset(spec)?,
+        _ => 0,
+    };
+    let (command, clock) = (String::from(command), *clock);
+    let apply = move |item: &Value| apply(&command, spec.as_deref(), offset, &clock, item);
+    match input {
+        Piped::Value(Value::Void) => match rest.as_slice() {
+            [val] => apply(val).map(Piped::Value),
+            _ => Err(usage()),
+        },
+        _ if !rest.is_empty() => Err(usage()),
+        Piped::Value(val) if !matches!(val, Value::Arr(_) | Value::Range(_)) => {
+            apply(&val).map(Piped::Value)
+        }
+        input => match input.into_stream() {
+            Ok(items) => Ok(Piped::Stream(Box::new(items.map(move |item| match item {
+                Ok(item) => apply(&item),
+                Err(err) => Err(err),
+            })))),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn apply(
+    command: &str,
+    spec: Option<&str>,
+    offset: i32,
+    clock: &Clock,
+    item: &Value,
+) -> Result<Value, Error> {
+    match (command, item) {
+        ("parse", Value::Str(_)) | ("parse", Value::Pipeline(_)) => {
+            parse(&format!("{}", item), spec).map(Value::Date)
+        }
+        ("parse", Value::Date(_)) => Ok(item.clone()),
+        ("parse", _) => Err(Error::Type(
+            format!("date parse works on text, not {}.", item.a_type_name()),
+            None,
+        )),
+        (_, Value::Date(date)) => match command {
+            "format" => date.format(spec.unwrap_or_default()).map(Value::Str),
+            "to-utc" => Ok(Value::Date(date.with_offset(0))),
+            "to-local" => Ok(Value::Date(
+                date.with_offset(clock.local_offset(date.nanos)),
+            )),
+            "to-offset" => Ok(Value::Date(date.with_offset(offset))),
+            _ => Ok(date.to_record()),
+        },
+        _ => Err(Error::Type(
+            format!(
+                "date {} works on dates, not {}.",
+                command,
+                item.a_type_name()
+            ),
+            None,
+        )),
+    }
+}
+
+fn usage() -> Error {
+    Error::Arg(
+        String::from(
+            "usage: date [now], date +<format>, date parse [--format <format>], \
+             date format <format>, date to-utc|to-local|parts, or date to-offset <offset>",
+        ),
+        None,
+    )
+}
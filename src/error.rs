@@ -0,0 +1,169 @@
+use crate::interpreter::Value;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// A byte range into the source text that produced a token, expression or statement.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// The 1-based line and column of the start of this span in `src`.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let start = self.start.min(src.len());
+        let before = &src[..start];
+        let line = before.matches('\n').count() + 1;
+        let col = match before.rfind('\n') {
+            Some(i) => before[i + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        (line, col)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Lex(String, Span),
+    Parse(String, Option<Span>),
+    Type(String, Option<Span>),
+    Name(String, Option<Span>),
+    /// An assignment to a variable bound with `let` or `const`, or a `let` hiding a constant.
+    Immutable(String, Option<Span>),
+    CommandNotFound(String, io::Error, Option<Span>),
+    Io(io::Error, Option<Span>),
+    ExitStatus(String, i32, Option<Span>),
+    Arith(String, Option<Span>),
+    Arg(String, Option<Span>),
+    Index(String, Option<Span>),
+    Format(String, Option<Span>),
+    Thrown(Value, Option<Span>),
+    /// A script to import that cannot be found, or that is already being imported.
+    Import(String, Option<Span>),
+    /// An error in an imported script, with the file, line and column it happened at there.
+    Module(String, Box<Error>, Option<Span>),
+}
+
+impl Error {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Lex(_, span) => Some(*span),
+            Error::Parse(_, span)
+            | Error::Type(_, span)
+            | Error::Name(_, span)
+            | Error::Immutable(_, span)
+            | Error::CommandNotFound(_, _, span)
+            | Error::Io(_, span)
+            | Error::ExitStatus(_, _, span)
+            | Error::Arith(_, span)
+            | Error::Arg(_, span)
+            | Error::Index(_, span)
+            | Error::Format(_, span)
+            | Error::Thrown(_, span)
+            | Error::Import(_, span)
+            | Error::Module(_, _, span) => *span,
+        }
+    }
+
+    /// The error that started it all, looking through imports to the script it happened in.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Module(_, err, _) => err.root(),
+            err => err,
+        }
+    }
+
+    /// The category of the error, as seen by scripts in a `catch` block.
+    pub fn kind(&self) -> &'static str {
+        match self.root() {
+            Error::Lex(_, _) => "lex",
+            Error::Parse(_, _) => "parse",
+            Error::Type(_, _) => "type",
+            Error::Name(_, _) => "name",
+            Error::Immutable(_, _) => "immutable",
+            Error::CommandNotFound(_, _, _) => "command-not-found",
+            Error::Io(_, _) => "io",
+            Error::ExitStatus(_, _, _) => "exit-status",
+            Error::Arith(_, _) => "arithmetic",
+            Error::Arg(_, _) => "argument",
+            Error::Index(_, _) => "index",
+            Error::Format(_, _) => "format",
+            Error::Thrown(_, _) => "thrown",
+            Error::Import(_, _) => "import",
+            Error::Module(_, _, _) => "module",
+        }
+    }
+
+    /// Attaches `span` to the error unless it already knows where it came from.
+    pub fn or_span(self, span: Span) -> Error {
+        match self {
+            Error::Parse(msg, None) => Error::Parse(msg, Some(span)),
+            Error::Type(msg, None) => Error::Type(msg, Some(span)),
+            Error::Name(name, None) => Error::Name(name, Some(span)),
+            Error::Immutable(msg, None) => Error::Immutable(msg, Some(span)),
+            Error::CommandNotFound(name, err, None) => {
+                Error::CommandNotFound(name, err, Some(span))
+            }
+            Error::Io(err, None) => Error::Io(err, Some(span)),
+            Error::ExitStatus(name, code, None) => Error::ExitStatus(name, code, Some(span)),
+            Error::Arith(msg, None) => Error::Arith(msg, Some(span)),
+            Error::Arg(msg, None) => Error::Arg(msg, Some(span)),
+            Error::Index(msg, None) => Error::Index(msg, Some(span)),
+            Error::Format(msg, None) => Error::Format(msg, Some(span)),
+            Error::Thrown(val, None) => Error::Thrown(val, Some(span)),
+            Error::Import(msg, None) => Error::Import(msg, Some(span)),
+            Error::Module(place, err, None) => Error::Module(place, err, Some(span)),
+            err => err,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(raw, _) => write!(f, "Unexpected token {}...", raw),
+            Error::Parse(msg, _) => write!(f, "{}", msg),
+            Error::Type(msg, _) => write!(f, "{}", msg),
+            Error::Name(name, _) => write!(f, "Undefined variable {}.", name),
+            Error::Immutable(msg, _) => write!(f, "{}", msg),
+            Error::CommandNotFound(name, _, _) => write!(f, "Command not found: {}.", name),
+            Error::Io(err, _) => write!(f, "{}", err),
+            Error::ExitStatus(name, code, _) => write!(f, "{} exited with status {}.", name, code),
+            Error::Arith(msg, _) => write!(f, "{}", msg),
+            Error::Arg(msg, _) => write!(f, "{}", msg),
+            Error::Index(msg, _) => write!(f, "{}", msg),
+            Error::Format(msg, _) => write!(f, "{}", msg),
+            Error::Thrown(val, _) => match val.field("message") {
+                Some(msg) => write!(f, "{}", msg),
+                None => write!(f, "{}", val),
+            },
+            Error::Import(msg, _) => write!(f, "{}", msg),
+            Error::Module(place, err, _) => write!(f, "{}: {}", place, err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CommandNotFound(_, err, _) | Error::Io(err, _) => Some(err),
+            Error::Module(_, err, _) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err, None)
+    }
+}
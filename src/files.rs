@@ -0,0 +1,513 @@
+use crate::builtins;
+use crate::datetime::DateTime;
+use crate::error::Error;
+use crate::interpreter::Value;
+use crate::stream::Piped;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File-system builtins, which give records in place of the text of the programs they stand in for.
+pub const COMMANDS: &[&str] = &[
+    "ls", "ps", "du", "stat", "open", "save", "mkdir", "rm", "cp", "mv",
+];
+
+/// Runs the file-system builtin `name`.
+pub fn run(name: &str, input: Piped, args: &[Value]) -> Result<Piped, Error> {
+    let (paths, flags) = split_args(name, args)?;
+    let flag = |long: &str| flags.contains(&long);
+    let done = |result: Result<(), Error>| result.map(|_| Piped::Value(Value::Void));
+    match (name, paths.as_slice()) {
+        ("ls", []) => ls(Path::new("."), flag("--all")),
+        ("ls", [path]) => ls(Path::new(path), flag("--all")),
+        ("ps", []) => ps(),
+        ("du", []) => du(Path::new(".")),
+        ("du", [path]) => du(Path::new(path)),
+        ("stat", [path]) => stat(Path::new(path)),
+        ("open", [path]) => open(Path::new(path), flag("--raw")),
+        ("save", [path]) => done(save(Path::new(path), input, flag("--raw"), flag("--force"))),
+        ("mkdir", paths) if !paths.is_empty() => {
+            done(each(paths, |path| match flag("--parents") {
+                true => fs::create_dir_all(path).map_err(|err| io_error(path, err)),
+                false => fs::create_dir(path).map_err(|err| io_error(path, err)),
+            }))
+        }
+        ("rm", paths) if !paths.is_empty() => done(each(paths, |path| {
+            rm(path, flag("--recursive"), flag("--force"))
+        })),
+        ("cp", [sources @ .., dest]) if !sources.is_empty() => done(each(sources, |path| {
+            match target(path, Path::new(dest), sources.len(), flag("--force")) {
+                Ok(target) => cp(path, &target, flag("--recursive")),
+                Err(err) => Err(err),
+            }
+        })),
+        ("mv", [sources @ .., dest]) if !sources.is_empty() => done(each(sources, |path| {
+            match target(path, Path::new(dest), sources.len(), flag("--force")) {
+                Ok(target) => fs::rename(path, &target).map_err(|err| io_error(path, err)),
+                Err(err) => Err(err),
+            }
+        })),
+        _ => Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+    }
+}
+
+fn usage(name: &str) -> &'static str {
+    match name {
+        "ls" => "ls [path] [--all]",
+        "ps" => "ps",
+        "du" => "du [path]",
+        "stat" => "stat <path>",
+        "open" => "open <path> [--raw]",
+        "save" => "save <path> [--raw] [--force]",
+        "mkdir" => "mkdir <path>... [--parents]",
+        "rm" => "rm <path>... [--recursive] [--force]",
+        "cp" => "cp <source>... <dest> [--recursive] [--force]",
+        _ => "mv <source>... <dest> [--force]",
+    }
+}
+
+fn known_flags(name: &str) -> &'static [(&'static str, &'static str)] {
+    match name {
+        "ls" => &[("-a", "--all")],
+        "open" => &[("-r", "--raw")],
+        "save" => &[("-r", "--raw"), ("-f", "--force")],
+        "mkdir" => &[("-p", "--parents")],
+        "rm" => &[("-r", "--recursive"), ("-f", "--force")],
+        "cp" => &[("-r", "--recursive"), ("-f", "--force")],
+        "mv" => &[("-f", "--force")],
+        _ => &[],
+    }
+}
+
+/// Splits `args` into paths and the long names of the flags given. Arrays stand for each path in
+/// them, so the names picked out of `ls` can be passed straight on.
+fn split_args(name: &str, args: &[Value]) -> Result<(Vec<String>, Vec<&'static str>), Error> {
+    let mut paths = Vec::new();
+    let mut flags = Vec::new();
+    let mut args: Vec<&Value> = args.iter().collect();
+    args.reverse();
+    while let Some(arg) = args.pop() {
+        match arg {
+            Value::Str(s) if s.starts_with('-') && s.len() > 1 => {
+                for s in short_flags(s) {
+                    match known_flags(name)
+                        .iter()
+                        .find(|(short, long)| s == *short || s == *long)
+                    {
+                        Some((_, long)) => flags.push(*long),
+                        None => return Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+                    }
+                }
+            }
+            Value::Str(s) => paths.push(s.clone()),
+            Value::Int(_) | Value::Num(_) => paths.push(format!("{}", arg)),
+            Value::Arr(vals) => args.extend(vals.iter().rev()),
+            _ => {
+                return Err(Error::Type(
+                    format!("Cannot use {} as a path.", arg.a_type_name()),
+                    None,
+                ))
+            }
+        }
+    }
+    Ok((paths, flags))
+}
+
+/// Short flags given together, as in `-rf`, stand for each of them.
+fn short_flags(arg: &str) -> Vec<String> {
+    match arg.strip_prefix('-') {
+        Some(letters) if !letters.starts_with('-') && letters.len() > 1 => {
+            letters.chars().map(|c| format!("-{}", c)).collect()
+        }
+        _ => vec![String::from(arg)],
+    }
+}
+
+fn each<F>(paths: &[String], mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&Path) -> Result<(), Error>,
+{
+    for path in paths {
+        f(Path::new(path))?;
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, err: io::Error) -> Error {
+    Error::Io(
+        io::Error::new(err.kind(), format!("{}: {}", path.display(), err)),
+        None,
+    )
+}
+
+fn exists_error(path: &Path) -> Error {
+    io_error(
+        path,
+        io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "already exists (use --force to replace it)",
+        ),
+    )
+}
+
+fn is_dir_error(path: &Path) -> Error {
+    io_error(
+        path,
+        io::Error::other("is a directory (use --recursive to include what is in it)"),
+    )
+}
+
+/// The contents of a directory, a record per entry sorted by name, or a record for a single file.
+fn ls(path: &Path, all: bool) -> Result<Piped, Error> {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(err) => return Err(io_error(path, err)),
+    };
+    if !meta.is_dir() {
+        let name = format!("{}", path.display());
+        return Ok(Piped::Value(Value::Arr(vec![entry(name, &meta)])));
+    }
+    let mut records = Vec::new();
+    for (name, meta) in entries(path)? {
+        if all || !name.starts_with('.') {
+            records.push(entry(name, &meta));
+        }
+    }
+    Ok(Piped::Value(Value::Arr(records)))
+}
+
+/// The names and metadata of what is in the directory at `path`, sorted by name.
+fn entries(path: &Path) -> Result<Vec<(String, fs::Metadata)>, Error> {
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(err) => return Err(io_error(path, err)),
+    };
+    let mut entries = Vec::new();
+    for entry in dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => return Err(io_error(path, err)),
+        };
+        match fs::symlink_metadata(entry.path()) {
+            Ok(meta) => entries.push((entry.file_name().to_string_lossy().into_owned(), meta)),
+            Err(err) => return Err(io_error(&entry.path(), err)),
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+fn entry(name: String, meta: &fs::Metadata) -> Value {
+    Value::Record(vec![
+        (String::from("name"), Value::Str(name)),
+        (
+            String::from("type"),
+            Value::Str(String::from(file_type(meta))),
+        ),
+        (String::from("size"), Value::Size(meta.len() as i64)),
+        (String::from("modified"), time(meta.modified())),
+        (String::from("permissions"), Value::Str(permissions(meta))),
+    ])
+}
+
+fn file_type(meta: &fs::Metadata) -> &'static str {
+    let file_type = meta.file_type();
+    match (file_type.is_symlink(), file_type.is_dir()) {
+        (true, _) => "symlink",
+        (false, true) => "dir",
+        (false, false) => "file",
+    }
+}
+
+/// A file time to the second, or void where the platform does not keep it.
+fn time(time: io::Result<SystemTime>) -> Value {
+    let secs = match time {
+        Ok(time) => match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        },
+        Err(_) => return Value::Void,
+    };
+    Value::Date(DateTime::from_secs(secs))
+}
+
+#[cfg(unix)]
+fn permissions(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = meta.permissions().mode();
+    "rwxrwxrwx"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match mode & (1 << (8 - i)) {
+            0 => '-',
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn permissions(meta: &fs::Metadata) -> String {
+    match meta.permissions().readonly() {
+        true => String::from("r--r--r--"),
+        false => String::from("rw-rw-rw-"),
+    }
+}
+
+fn stat(path: &Path) -> Result<Piped, Error> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(err) => return Err(io_error(path, err)),
+    };
+    let full = match fs::canonicalize(path) {
+        Ok(full) => full,
+        Err(_) => PathBuf::from(path),
+    };
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => format!("{}", path.display()),
+    };
+    let mut fields = match entry(name, &meta) {
+        Value::Record(fields) => fields,
+        _ => Vec::new(),
+    };
+    fields.insert(
+        1,
+        (
+            String::from("path"),
+            Value::Str(format!("{}", full.display())),
+        ),
+    );
+    fields.push((String::from("accessed"), time(meta.accessed())));
+    fields.push((String::from("created"), time(meta.created())));
+    Ok(Piped::Value(Value::Record(fields)))
+}
+
+/// The space taken by each entry of a directory, counting everything inside subdirectories.
+fn du(path: &Path) -> Result<Piped, Error> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(err) => return Err(io_error(path, err)),
+    };
+    let name = |name: String, meta: &fs::Metadata, size: u64| {
+        Value::Record(vec![
+            (String::from("name"), Value::Str(name)),
+            (
+                String::from("type"),
+                Value::Str(String::from(file_type(meta))),
+            ),
+            (String::from("size"), Value::Size(size as i64)),
+        ])
+    };
+    if !meta.is_dir() {
+        let record = name(format!("{}", path.display()), &meta, meta.len());
+        return Ok(Piped::Value(Value::Arr(vec![record])));
+    }
+    let mut records = Vec::new();
+    for (entry, meta) in entries(path)? {
+        let size = total_size(&path.join(&entry), &meta)?;
+        records.push(name(entry, &meta, size));
+    }
+    Ok(Piped::Value(Value::Arr(records)))
+}
+
+fn total_size(path: &Path, meta: &fs::Metadata) -> Result<u64, Error> {
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut size = 0;
+    for (entry, meta) in entries(path)? {
+        size += total_size(&path.join(entry), &meta)?;
+    }
+    Ok(size)
+}
+
+/// The running processes, read from `/proc`.
+#[cfg(target_os = "linux")]
+fn ps() -> Result<Piped, Error> {
+    let dir = match fs::read_dir("/proc") {
+        Ok(dir) => dir,
+        Err(err) => return Err(io_error(Path::new("/proc"), err)),
+    };
+    let mut procs: Vec<(i64, Value)> = dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i64>().ok())
+        // A process may exit between listing and reading it, and is then left out.
+        .filter_map(|pid| Some((pid, process(pid)?)))
+        .collect();
+    procs.sort_by_key(|(pid, _)| *pid);
+    Ok(Piped::Value(Value::Arr(
+        procs.into_iter().map(|(_, record)| record).collect(),
+    )))
+}
+
+#[cfg(target_os = "linux")]
+fn process(pid: i64) -> Option<Value> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The name is in parentheses and may itself hold spaces and parentheses.
+    let (open, close) = (stat.find('(')?, stat.rfind(')')?);
+    let name = &stat[open + 1..close];
+    let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+    let status = match *fields.first()? {
+        "R" => "running",
+        "S" => "sleeping",
+        "D" => "waiting",
+        "Z" => "zombie",
+        "T" | "t" => "stopped",
+        "I" => "idle",
+        _ => "dead",
+    };
+    let ppid = fields.get(1)?.parse::<i64>().ok()?;
+    // Resident memory in bytes. Kernel threads have none.
+    let mem = fs::read_to_string(format!("/proc/{}/status", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<i64>().ok())
+        .map_or(0, |kb| kb * 1024);
+    Some(Value::Record(vec![
+        (String::from("pid"), Value::Int(pid)),
+        (String::from("ppid"), Value::Int(ppid)),
+        (String::from("name"), Value::Str(String::from(name))),
+        (String::from("status"), Value::Str(String::from(status))),
+        (String::from("mem"), Value::Size(mem)),
+    ]))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ps() -> Result<Piped, Error> {
+    Err(Error::Arg(
+        String::from("ps is only available on Linux."),
+        None,
+    ))
+}
+
+fn format_of(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match builtins::is_format(&ext) {
+        true => Some(ext),
+        false => None,
+    }
+}
+
+/// A file's contents, read as data when its extension names a format, and as text otherwise or
+/// with `raw`.
+fn open(path: &Path, raw: bool) -> Result<Piped, Error> {
+    let text = match fs::read(path) {
+        Ok(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(_) => {
+                return Err(Error::Format(
+                    format!("Cannot open {}: it is not UTF-8 text.", path.display()),
+                    None,
+                ))
+            }
+        },
+        Err(err) => return Err(io_error(path, err)),
+    };
+    match format_of(path) {
+        Some(format) if !raw => builtins::parse_as(&format, &text, &[]).map(Piped::Value),
+        // Text ends without the newline that `save` adds to it.
+        _ => Ok(Piped::Value(Value::Str(match text.strip_suffix('\n') {
+            Some(line) => String::from(line),
+            None => text,
+        }))),
+    }
+}
+
+/// Writes what was piped in to a file, in the format its extension names unless it is already text
+/// or `raw` is given.
+fn save(path: &Path, input: Piped, raw: bool, force: bool) -> Result<(), Error> {
+    if !force && fs::symlink_metadata(path).is_ok() {
+        return Err(exists_error(path));
+    }
+    let val = input.into_value()?;
+    let bytes = match (format_of(path), &val) {
+        (_, Value::Str(_)) | (_, Value::Pipeline(_)) | (None, _) => Piped::Value(val).into_bytes(),
+        (Some(_), _) if raw => Piped::Value(val).into_bytes(),
+        (Some(format), _) => {
+            builtins::write_as(&format, &val, &[]).map(|text| (text + "\n").into_bytes())
+        }
+    };
+    match bytes {
+        Ok(bytes) => fs::write(path, bytes).map_err(|err| io_error(path, err)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Removes a file, or a directory and everything in it with `recursive`. `/`, `.` and `..` are
+/// never removed.
+fn rm(path: &Path, recursive: bool, force: bool) -> Result<(), Error> {
+    if path.file_name().is_none() {
+        return Err(Error::Arg(
+            format!("Refusing to remove {}.", path.display()),
+            None,
+        ));
+    }
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(err) if force && err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(io_error(path, err)),
+    };
+    let removed = match (meta.is_dir(), recursive) {
+        (true, true) => fs::remove_dir_all(path),
+        (true, false) => return Err(is_dir_error(path)),
+        (false, _) => fs::remove_file(path),
+    };
+    removed.map_err(|err| io_error(path, err))
+}
+
+/// Where `source` goes when copied or moved to `dest`: inside it when it is a directory, which it
+/// must be when there are several sources.
+fn target(source: &Path, dest: &Path, sources: usize, force: bool) -> Result<PathBuf, Error> {
+    let target = match (fs::metadata(dest), source.file_name()) {
+        (Ok(meta), Some(name)) if meta.is_dir() => dest.join(name),
+        _ if sources > 1 => {
+            return Err(io_error(
+                dest,
+                io::Error::new(io::ErrorKind::NotFound, "is not a directory"),
+            ))
+        }
+        _ => PathBuf::from(dest),
+    };
+    match fs::symlink_metadata(&target) {
+        Ok(_) if !force => Err(exists_error(&target)),
+        _ => Ok(target),
+    }
+}
+
+fn cp(source: &Path, target: &Path, recursive: bool) -> Result<(), Error> {
+    let meta = match fs::metadata(source) {
+        Ok(meta) => meta,
+        Err(err) => return Err(io_error(source, err)),
+    };
+    if !meta.is_dir() {
+        return match fs::copy(source, target) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(io_error(source, err)),
+        };
+    }
+    if !recursive {
+        return Err(is_dir_error(source));
+    }
+    let inside = match (
+        fs::canonicalize(source),
+        target.parent().map(fs::canonicalize),
+    ) {
+        (Ok(source), Some(Ok(parent))) => parent.starts_with(source),
+        _ => false,
+    };
+    if inside {
+        return Err(Error::Arg(
+            format!("Cannot copy {} into itself.", source.display()),
+            None,
+        ));
+    }
+    if let Err(err) = fs::create_dir_all(target) {
+        return Err(io_error(target, err));
+    }
+    for (name, _) in entries(source)? {
+        cp(&source.join(&name), &target.join(&name), true)?;
+    }
+    Ok(())
+}
@@ -1,197 +1,2206 @@
+use crate::builtins;
+use crate::check;
+use crate::datetime::{self, Clock, DateTime};
+use crate::error::{Error, Span};
 use crate::parser::*;
+use crate::range::Range;
+use crate::stream::Piped;
+use crate::strings;
+use crate::table;
+use crate::tokens;
+use crate::trace;
+use crate::units;
+use regex::Regex;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
 
-type Env = HashMap<String, Value>;
+/// The variables in scope, looked up by name from the innermost scope outwards. Reading one
+/// copies its value, unless it is looked into through `with`.
+#[derive(Clone, Default)]
+pub struct Env {
+    /// The innermost scope's variables, copied only when a closure holds them and they change.
+    vars: Rc<HashMap<String, Binding>>,
+    /// The scopes around it, which are read but never changed from here.
+    outer: Option<Rc<Scope>>,
+    pub opts: Options,
+    source: Rc<str>,
+    /// The scripts loaded so far, shared by every environment in the run.
+    modules: Rc<RefCell<Modules>>,
+}
+
+/// The variables a closure was written among, or the process's environment variables.
+struct Scope {
+    vars: Rc<HashMap<String, Binding>>,
+    outer: Option<Rc<Scope>>,
+}
+
+#[derive(Default)]
+struct Modules {
+    /// The record of each imported script's exports by its canonical path, so that it runs once.
+    loaded: HashMap<PathBuf, Value>,
+    /// The scripts being run, each loaded by the one before it, for finding circular imports.
+    loading: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+enum Binding {
+    /// Assigned with `=`, or a parameter or loop variable. Closures take a copy.
+    Var(Value),
+    Let(Value),
+    Const(Value),
+    /// Bound with `let mut`. Closures share it with the block that bound it, so changes made by
+    /// either are seen by both.
+    Shared(Rc<RefCell<Value>>),
+}
+
+/// The variables a scope has bound, each with the binding it hid, if there was one.
+type Hidden = Vec<(String, Option<Binding>)>;
+
+impl Env {
+    /// An environment for running a script, which sees the process's environment variables.
+    fn process(opts: Options, modules: Rc<RefCell<Modules>>) -> Env {
+        let vars = std::env::vars()
+            .map(|(k, v)| (format!("${}", k), Binding::Var(Value::Str(v))))
+            .collect();
+        let scope = Scope {
+            vars: Rc::new(vars),
+            outer: None,
+        };
+        Env {
+            outer: Some(Rc::new(scope)),
+            opts,
+            modules,
+            ..Env::default()
+        }
+    }
+
+    /// An environment for running another script of the same run, with the same options and
+    /// loaded scripts.
+    fn fresh(&self) -> Env {
+        Env::process(self.opts.clone(), self.modules.clone())
+    }
+
+    /// An environment for a closure written here, which binds its own variables in a new scope.
+    fn enclose(&self) -> Env {
+        let scope = Scope {
+            vars: self.vars.clone(),
+            outer: self.outer.clone(),
+        };
+        Env {
+            vars: Rc::default(),
+            outer: Some(Rc::new(scope)),
+            ..self.clone()
+        }
+    }
+
+    fn binding(&self, name: &str) -> Option<&Binding> {
+        if let Some(binding) = self.vars.get(name) {
+            return Some(binding);
+        }
+        let mut scope = self.outer.as_deref();
+        while let Some(outer) = scope {
+            if let Some(binding) = outer.vars.get(name) {
+                return Some(binding);
+            }
+            scope = outer.outer.as_deref();
+        }
+        None
+    }
+
+    fn local(&mut self) -> &mut HashMap<String, Binding> {
+        Rc::make_mut(&mut self.vars)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.binding(name) {
+            Some(Binding::Var(val)) | Some(Binding::Let(val)) | Some(Binding::Const(val)) => {
+                Some(val.clone())
+            }
+            Some(Binding::Shared(cell)) => Some(cell.borrow().clone()),
+            None => None,
+        }
+    }
+
+    /// Calls `f` with the value of the variable `name`, or null when it isn't set, without copying
+    /// the value.
+    fn with<T>(&self, name: &str, f: impl FnOnce(&Value) -> T) -> T {
+        match self.binding(name) {
+            Some(Binding::Var(val)) | Some(Binding::Let(val)) | Some(Binding::Const(val)) => f(val),
+            Some(Binding::Shared(cell)) => f(&cell.borrow()),
+            None => f(&Value::Void),
+        }
+    }
+
+    pub fn insert(&mut self, name: String, val: Value) {
+        self.local().insert(name, Binding::Var(val));
+    }
+
+    pub fn assign(&mut self, name: &str, val: Value) -> Result<(), Error> {
+        let msg = match self.binding(name) {
+            Some(Binding::Shared(cell)) => {
+                *cell.borrow_mut() = val;
+                return Ok(());
+            }
+            Some(Binding::Let(_)) => format!(
+                "Cannot assign to {}, which was bound with `let`. Bind it with `let mut` to change it.",
+                name
+            ),
+            Some(Binding::Const(_)) => format!("Cannot assign to the constant {}.", name),
+            Some(Binding::Var(_)) | None => {
+                self.insert(String::from(name), val);
+                return Ok(());
+            }
+        };
+        Err(Error::Immutable(msg, None))
+    }
+
+    /// Lets `change` change the value of the variable `name` where it is, unless it was bound with
+    /// `let` or `const`.
+    fn change(
+        &mut self,
+        name: &str,
+        change: impl FnOnce(&mut Value) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if let Some(Binding::Var(_)) = self.vars.get(name) {
+            if let Some(Binding::Var(val)) = self.local().get_mut(name) {
+                return change(val);
+            }
+        }
+        let mut val = match self.binding(name) {
+            Some(Binding::Shared(cell)) => return change(&mut cell.borrow_mut()),
+            Some(Binding::Let(_)) | Some(Binding::Const(_)) => {
+                return self.assign(name, Value::Void)
+            }
+            // A variable of an outer scope changes in a copy of it in this one.
+            Some(Binding::Var(val)) => val.clone(),
+            None => Value::Void,
+        };
+        change(&mut val)?;
+        self.insert(String::from(name), val);
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &str, val: Value, decl: Decl) -> Result<(), Error> {
+        if let Some(Binding::Const(_)) = self.binding(name) {
+            return Err(Error::Immutable(
+                format!("Cannot bind {} again, since it is a constant.", name),
+                None,
+            ));
+        }
+        let binding = match decl {
+            Decl::Let => Binding::Let(val),
+            Decl::Mut => Binding::Shared(Rc::new(RefCell::new(val))),
+            Decl::Const => Binding::Const(val),
+        };
+        self.local().insert(String::from(name), binding);
+        Ok(())
+    }
+
+    /// Notes the binding `name` has now, unless it is noted already, for `restore` to put back.
+    fn remember(&self, name: &str, hidden: &mut Hidden) {
+        if !hidden.iter().any(|(hid, _)| hid == name) {
+            hidden.push((String::from(name), self.vars.get(name).cloned()));
+        }
+    }
+
+    /// Ends the scope of variables bound in a block, uncovering the ones they hid.
+    fn restore(&mut self, hidden: Hidden) {
+        for (name, binding) in hidden {
+            match binding {
+                Some(binding) => self.local().insert(name, binding),
+                None => self.local().remove(&name),
+            };
+        }
+    }
+
+    fn line(&self, span: Span) -> Option<usize> {
+        match self.source.is_empty() {
+            true => None,
+            false => Some(span.line_col(&self.source).0),
+        }
+    }
+}
+
+/// Interpreter switches toggled by the `set` builtin or on the command line.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Raise an error when an external command exits with a non-zero status.
+    pub errexit: bool,
+    /// Raise an error when reading a variable that was never assigned.
+    pub nounset: bool,
+    /// Give a pipeline the status of its last failing command rather than of its last command.
+    pub pipefail: bool,
+    /// Print each external command with its arguments before running it.
+    pub xtrace: bool,
+    /// Add timestamps to traced commands and report how long each one took.
+    pub trace_timing: bool,
+    /// Where traced commands are appended, instead of stderr.
+    pub trace_file: Option<PathBuf>,
+    /// Draw arrays of records as tables this many characters wide, as the REPL does on a terminal.
+    pub table_width: Option<usize>,
+    /// Where `date now` reads the time.
+    pub clock: Clock,
+    /// The script being run, beside which the scripts it imports are looked for first.
+    pub script: Option<PathBuf>,
+    /// Directories given with `--module-path` to look for imported scripts in, before those in
+    /// `$OYSTER_PATH`.
+    pub module_path: Vec<PathBuf>,
+}
+
+impl Options {
+    /// Strict mode is `errexit`, `nounset` and `pipefail` together.
+    pub fn set_strict(&mut self, on: bool) {
+        self.errexit = on;
+        self.nounset = on;
+        self.pipefail = on;
+    }
+
+    /// Applies one flag such as `--strict` or `-x`, taking the flag's value from `rest` when it
+    /// needs one.
+    pub fn apply(
+        &mut self,
+        flag: &str,
+        rest: &mut dyn Iterator<Item = String>,
+    ) -> Result<(), String> {
+        match flag {
+            "-e" | "--errexit" => self.errexit = true,
+            "--no-errexit" => self.errexit = false,
+            "-u" | "--nounset" => self.nounset = true,
+            "--no-nounset" => self.nounset = false,
+            "--pipefail" => self.pipefail = true,
+            "--no-pipefail" => self.pipefail = false,
+            "--strict" => self.set_strict(true),
+            "--no-strict" => self.set_strict(false),
+            "-x" | "--xtrace" => self.xtrace = true,
+            "--no-xtrace" => self.xtrace = false,
+            "--trace-timing" => self.trace_timing = true,
+            "--no-trace-timing" => self.trace_timing = false,
+            "--trace-file" => match rest.next() {
+                Some(path) => self.trace_file = Some(PathBuf::from(path)),
+                None => return Err(String::from("Option --trace-file needs a path.")),
+            },
+            "--no-trace-file" => self.trace_file = None,
+            "--now" => match rest.next().map(|now| datetime::parse(&now, None)) {
+                Some(Ok(now)) => self.clock = Clock::Fixed(now),
+                Some(Err(err)) => return Err(format!("Option --now: {}", err)),
+                None => return Err(String::from("Option --now needs a date.")),
+            },
+            "--no-now" => self.clock = Clock::System,
+            "--module-path" => match rest.next() {
+                Some(dir) => self.module_path.push(PathBuf::from(dir)),
+                None => return Err(String::from("Option --module-path needs a directory.")),
+            },
+            "--no-module-path" => self.module_path.clear(),
+            _ => return Err(format!("Unknown option {}.", flag)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Num(f64),
+    /// A number of bytes.
+    Size(i64),
+    /// A number of nanoseconds.
+    Duration(i64),
+    Bool(bool),
+    Arr(Vec<Value>),
+    Range(Range),
+    Record(Vec<(String, Value)>),
+    Closure(Rc<Closure>),
+    Regex(Regex),
+    Date(DateTime),
+    Pipeline(process::Output),
+    Void,
+}
+
+pub struct Closure {
+    params: Vec<String>,
+    body: Rc<Prog>,
+    env: Env,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) => "int",
+            Value::Num(_) => "float",
+            Value::Size(_) => "size",
+            Value::Duration(_) => "duration",
+            Value::Bool(_) => "bool",
+            Value::Arr(_) => "array",
+            Value::Range(_) => "range",
+            Value::Record(_) => "record",
+            Value::Closure(_) => "closure",
+            Value::Regex(_) => "regex",
+            Value::Date(_) => "date",
+            Value::Pipeline(_) => "pipeline",
+            Value::Void => "null",
+        }
+    }
+
+    pub fn a_type_name(&self) -> String {
+        match self {
+            Value::Void => String::from("null"),
+            _ => article(self.type_name()),
+        }
+    }
+
+    /// Whether the value counts as true for `if`.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::Int(n) => *n != 0,
+            Value::Num(n) => *n != 0.0,
+            Value::Size(n) | Value::Duration(n) => *n != 0,
+            Value::Bool(b) => *b,
+            Value::Arr(vals) => !vals.is_empty(),
+            Value::Range(range) => !range.is_empty(),
+            Value::Record(_) | Value::Closure(_) | Value::Regex(_) | Value::Date(_) => true,
+            Value::Pipeline(out) => out.status.success(),
+            Value::Void => false,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Record(fields) => fields.iter().find(|(k, _)| k == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+fn article(name: &str) -> String {
+    match name.starts_with(|c| "aeiou".contains(c)) {
+        true => format!("an {}", name),
+        false => format!("a {}", name),
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Size(n) => write!(f, "{}", units::format_size(*n)),
+            Value::Duration(n) => write!(f, "{}", units::format_duration(*n)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Arr(vals) => {
+                write!(f, "[")?;
+                for (i, v) in vals.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Closure(closure) => write!(f, "{{ |{}| ... }}", closure.params.join(", ")),
+            Value::Regex(re) => write!(f, "{}", re.as_str()),
+            Value::Date(date) => write!(f, "{}", date),
+            Value::Pipeline(out) => write!(
+                f,
+                "{}",
+                match String::from_utf8(out.clone().stdout) {
+                    Ok(out) => String::from(out.trim_end()),
+                    Err(err) => format!("{}", err),
+                }
+            ),
+            Value::Void => write!(f, "null"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn interpret(prog: &[Stmt]) -> Result<String, Error> {
+    interpret_with_options(prog, "", Options::default())
+}
+
+pub fn interpret_with_options(prog: &[Stmt], src: &str, opts: Options) -> Result<String, Error> {
+    let mut env = Env::process(opts, Rc::default());
+    env.source = Rc::from(src);
+
+    // A script that imports itself, however indirectly, is a circular import too.
+    if let Some(Ok(path)) = env.opts.script.as_ref().map(|path| path.canonicalize()) {
+        env.modules.borrow_mut().loading.push(path);
+    }
+
+    let mut out = String::new();
+    match exec_block(prog, &mut env, &mut out) {
+        Ok(()) => Ok(out),
+        Err(err) => Err(err),
+    }
+}
+
+/// Runs each statement in turn, adding what each one outputs to `out`.
+fn exec_block(prog: &[Stmt], env: &mut Env, out: &mut String) -> Result<(), Error> {
+    let mut hidden = Hidden::new();
+    let mut res = Ok(());
+    for stmt in prog {
+        let stmt_out = match stmt {
+            Stmt::Let(decl, pattern, box expr, span) => {
+                match exec_let(*decl, pattern, expr, *span, env, &mut hidden) {
+                    Ok(()) => Ok(String::new()),
+                    Err(err) => Err(err),
+                }
+            }
+            stmt => exec_stmt(stmt, env),
+        };
+        match stmt_out {
+            Ok(stmt_out) => {
+                if !stmt_out.is_empty() {
+                    out.push_str(&stmt_out);
+                    out.push('\n');
+                }
+            }
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        }
+    }
+    env.restore(hidden);
+    res
+}
+
+fn exec_stmt(stmt: &Stmt, env: &mut Env) -> Result<String, Error> {
+    match stmt {
+        Stmt::Assign(name, box expr, span) => match eval_expr(expr, env) {
+            Ok(val) => match env.assign(name, val) {
+                Ok(()) => Ok(String::new()),
+                Err(err) => Err(err.or_span(*span)),
+            },
+            Err(err) => Err(locate(err, expr)),
+        },
+        Stmt::Update(box target, op, box expr) => exec_update(target, *op, expr, env),
+        Stmt::Destructure(pattern, box expr, span) => match eval_expr(expr, env) {
+            Ok(val) => match unpack(pattern, &val, env, *span) {
+                Ok(bound) => {
+                    for (name, val) in bound {
+                        if let Err(err) = env.assign(&name, val) {
+                            return Err(err.or_span(*span));
+                        }
+                    }
+                    Ok(String::new())
+                }
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(locate(err, expr)),
+        },
+        // Outside a block, as at the top of an imported script, the variables stay bound.
+        Stmt::Let(decl, pattern, box expr, span) => {
+            match exec_let(*decl, pattern, expr, *span, env, &mut Hidden::new()) {
+                Ok(()) => Ok(String::new()),
+                Err(err) => Err(err),
+            }
+        }
+        Stmt::Expr(expr) => match eval_expr(expr, env) {
+            Ok(val) => Ok(table::output(&val, &env.opts)),
+            Err(err) => Err(locate(err, expr)),
+        },
+        Stmt::Try(body, catch, finally) => exec_try(body, catch, finally, env),
+        Stmt::If(box cond, then, otherwise) => exec_if(cond, then, otherwise, env),
+        Stmt::For(name, box values, body) => exec_for(name, values, body, env),
+        Stmt::Throw(expr, span) => match eval_expr(expr, env) {
+            Ok(val) => Err(Error::Thrown(val, Some(*span))),
+            Err(err) => Err(locate(err, expr)),
+        },
+        Stmt::Load(load, path, span) => match exec_load(load, path, env) {
+            Ok(out) => Ok(out),
+            Err(err) => Err(err.or_span(*span)),
+        },
+        // Exporting only matters to a script importing this one; see exec_script.
+        Stmt::Export(box stmt) => exec_stmt(stmt, env),
+    }
+}
+
+fn exec_load(load: &Load, path: &str, env: &mut Env) -> Result<String, Error> {
+    let found = find_script(path, env)?;
+    if let Load::Source = load {
+        let (script, source) = (env.opts.script.clone(), env.source.clone());
+        let res = run_script(&found, env);
+        env.opts.script = script;
+        env.source = source;
+        return match res {
+            Ok((out, _exported)) => Ok(nested_output(out)),
+            Err(err) => Err(err),
+        };
+    }
+    let key = match found.canonicalize() {
+        Ok(key) => key,
+        Err(err) => return Err(Error::Io(err, None)),
+    };
+    let cached = env.modules.borrow().loaded.get(&key).cloned();
+    let (out, exports) = match cached {
+        Some(exports) => (String::new(), exports),
+        None => {
+            let mut module = env.fresh();
+            match run_script(&found, &mut module) {
+                Ok((out, exported)) => {
+                    let mut fields: Vec<(String, Value)> = Vec::new();
+                    for name in exported {
+                        let field = String::from(name.trim_start_matches('$'));
+                        if !fields.iter().any(|(k, _)| *k == field) {
+                            fields.push((field, module.get(&name).unwrap_or(Value::Void)));
+                        }
+                    }
+                    let exports = Value::Record(fields);
+                    env.modules.borrow_mut().loaded.insert(key, exports.clone());
+                    (nested_output(out), exports)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    };
+    let res = match (load, exports) {
+        (Load::Import(Some(name)), exports) => env.assign(name, exports),
+        (Load::Import(None), exports) => env.assign(&namespace(&found), exports),
+        (_, Value::Record(fields)) => fields
+            .into_iter()
+            .try_for_each(|(k, v)| env.assign(&format!("${}", k), v)),
+        _ => Ok(()),
+    };
+    match res {
+        Ok(()) => Ok(out),
+        Err(err) => Err(err),
+    }
+}
+
+/// Where the script written as `path` is. A path starting with `./` or `../` is relative to the
+/// script loading it, or to the working directory when there is none; other relative paths are
+/// looked for beside the loading script and then in the `--module-path` and `$OYSTER_PATH`
+/// directories. A name without an extension may leave out `.oy`.
+fn find_script(path: &str, env: &Env) -> Result<PathBuf, Error> {
+    let given = Path::new(path);
+    let here = match env.opts.script.as_ref().and_then(|script| script.parent()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::new(),
+    };
+    let mut dirs = vec![here];
+    if !given.is_absolute() && !path.starts_with("./") && !path.starts_with("../") {
+        dirs.extend(env.opts.module_path.iter().cloned());
+        if let Some(Value::Str(search)) = env.get("$OYSTER_PATH") {
+            dirs.extend(std::env::split_paths(&search));
+        }
+    }
+    let mut names = vec![given.to_path_buf()];
+    if given.extension().is_none() {
+        names.push(given.with_extension("oy"));
+    }
+    for dir in &dirs {
+        for name in &names {
+            let candidate = dir.join(name);
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+    }
+    Err(Error::Import(
+        format!("Cannot find the script \"{}\".", path),
+        None,
+    ))
+}
+
+/// The variable `import` binds when not given one with `as`: `$util` for `lib/util.oy`.
+fn namespace(path: &Path) -> String {
+    let stem = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy(),
+        None => return String::from("$module"),
+    };
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("${}", name)
+}
+
+/// Reads, parses and runs the script at `path` in `env`, giving its output and the variables it
+/// exports.
+fn run_script(path: &Path, env: &mut Env) -> Result<(String, Vec<String>), Error> {
+    let key = match path.canonicalize() {
+        Ok(key) => key,
+        Err(err) => return Err(Error::Io(err, None)),
+    };
+    if let Some(msg) = circular(&env.modules.borrow().loading, &key) {
+        return Err(Error::Import(msg, None));
+    }
+    let mut text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => return Err(Error::Io(err, None)),
+    };
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    let prog = match tokens::tokenize_with_spans(&text) {
+        Ok((ts, spans)) => match parse_with_spans(&ts, &spans) {
+            Ok(prog) => prog,
+            Err(err) => return Err(in_script(err, path, &text)),
+        },
+        Err(err) => return Err(in_script(err, path, &text)),
+    };
+    for (span, msg) in check::warnings(&prog) {
+        let (line, col) = span.line_col(&text);
+        eprintln!("{}:{}:{}: warning: {}", path.display(), line, col, msg);
+    }
+    env.opts.script = Some(path.to_path_buf());
+    env.source = Rc::from(text.as_str());
+    env.modules.borrow_mut().loading.push(key);
+    let res = exec_script(&prog, env);
+    env.modules.borrow_mut().loading.pop();
+    match res {
+        Ok(some) => Ok(some),
+        Err(err) => Err(in_script(err, path, &text)),
+    }
+}
+
+fn circular(loading: &[PathBuf], key: &Path) -> Option<String> {
+    let start = loading.iter().position(|path| path == key)?;
+    let names: Vec<String> = loading[start..]
+        .iter()
+        .map(|path| path.as_path())
+        .chain(Some(key))
+        .map(|path| match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => path.display().to_string(),
+        })
+        .collect();
+    let mut msg = format!("Circular import: {} imports {}", names[0], names[1]);
+    for name in &names[2..] {
+        msg.push_str(&format!(", which imports {}", name));
+    }
+    msg.push('.');
+    Some(msg)
+}
+
+fn in_script(err: Error, path: &Path, text: &str) -> Error {
+    let place = match err.span() {
+        Some(span) => {
+            let (line, col) = span.line_col(text);
+            format!("{}:{}:{}", path.display(), line, col)
+        }
+        None => path.display().to_string(),
+    };
+    Error::Module(place, Box::new(err), None)
+}
+
+/// Runs the statements of a loaded script, giving its output and the variables it exports.
+fn exec_script(prog: &[Stmt], env: &mut Env) -> Result<(String, Vec<String>), Error> {
+    let mut out = String::new();
+    let mut exported = Vec::new();
+    for stmt in prog {
+        let res = match stmt {
+            Stmt::Export(box Stmt::Let(decl, pattern, box expr, span)) => {
+                let mut bound = Hidden::new();
+                match exec_let(*decl, pattern, expr, *span, env, &mut bound) {
+                    Ok(()) => {
+                        exported.extend(bound.into_iter().map(|(name, _)| name));
+                        Ok(String::new())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            Stmt::Export(box stmt @ Stmt::Assign(name, _, _)) => {
+                exported.push(name.clone());
+                exec_stmt(stmt, env)
+            }
+            stmt => exec_stmt(stmt, env),
+        };
+        let stmt_out = res?;
+        if !stmt_out.is_empty() {
+            out.push_str(&stmt_out);
+            out.push('\n');
+        }
+    }
+    Ok((out, exported))
+}
+
+fn exec_let(
+    decl: Decl,
+    pattern: &Pattern,
+    expr: &Expr,
+    span: Span,
+    env: &mut Env,
+    hidden: &mut Hidden,
+) -> Result<(), Error> {
+    let val = match eval_expr(expr, env) {
+        Ok(val) => val,
+        Err(err) => return Err(locate(err, expr)),
+    };
+    let bound = unpack(pattern, &val, env, span)?;
+    for (name, val) in bound {
+        env.remember(&name, hidden);
+        if let Err(err) = env.declare(&name, val, decl) {
+            return Err(err.or_span(span));
+        }
+    }
+    Ok(())
+}
+
+fn unpack(
+    pattern: &Pattern,
+    val: &Value,
+    env: &mut Env,
+    span: Span,
+) -> Result<Vec<(String, Value)>, Error> {
+    let mut bound = Vec::new();
+    match bind_pattern(pattern, val, env, &mut bound) {
+        Ok(true) => Ok(bound),
+        Ok(false) => Err(Error::Type(
+            format!("The pattern on the left does not fit {}.", the_value(val)),
+            Some(span),
+        )),
+        Err(err) => Err(err.or_span(span)),
+    }
+}
+
+fn exec_try(
+    body: &[Stmt],
+    catch: &Option<(Option<String>, Prog)>,
+    finally: &Option<Prog>,
+    env: &mut Env,
+) -> Result<String, Error> {
+    let mut out = String::new();
+    let res = match (exec_block(body, env, &mut out), catch) {
+        (Err(err), Some((name, handler))) => {
+            // The error is only bound inside the catch block.
+            let mut hidden = Hidden::new();
+            if let Some(name) = name {
+                env.remember(name, &mut hidden);
+                env.insert(name.clone(), error_value(err));
+            }
+            let res = exec_block(handler, env, &mut out);
+            env.restore(hidden);
+            res
+        }
+        (res, _) => res,
+    };
+
+    let res = match finally {
+        Some(cleanup) => match exec_block(cleanup, env, &mut out) {
+            Ok(()) => res,
+            Err(err) => Err(err),
+        },
+        None => res,
+    };
+
+    match res {
+        Ok(()) => Ok(nested_output(out)),
+        Err(err) => Err(err),
+    }
+}
+
+fn exec_if(
+    cond: &Expr,
+    then: &[Stmt],
+    otherwise: &Option<Prog>,
+    env: &mut Env,
+) -> Result<String, Error> {
+    // A failing command is an answer here rather than an error, so errexit doesn't apply.
+    let errexit = env.opts.errexit;
+    env.opts.errexit = false;
+    let holds = eval_expr(cond, env);
+    env.opts.errexit = errexit;
+    let branch = match holds {
+        Ok(val) if val.is_truthy() => Some(then),
+        Ok(_) => otherwise.as_deref(),
+        Err(err) => return Err(locate(err, cond)),
+    };
+    let mut out = String::new();
+    match branch.map(|prog| exec_block(prog, env, &mut out)) {
+        Some(Err(err)) => Err(err),
+        _ => Ok(nested_output(out)),
+    }
+}
+
+enum Part<'e> {
+    Field(&'e str),
+    Index(Value),
+}
+
+/// `$cfg.port = 80` or `$xs[0] = 1`: the part of the variable's value is replaced where it is.
+fn exec_update(
+    target: &Expr,
+    op: Option<BinOp>,
+    expr: &Expr,
+    env: &mut Env,
+) -> Result<String, Error> {
+    let val = match eval_expr(expr, env) {
+        Ok(val) => val,
+        Err(err) => return Err(locate(err, expr)),
+    };
+    let mut parts = Vec::new();
+    let mut inner = target;
+    let name = loop {
+        match inner {
+            Expr::Var(name, _) => break name,
+            Expr::Field(box e, field) => {
+                parts.push(Part::Field(field));
+                inner = e;
+            }
+            Expr::Index(box e, box index, _) => match eval_expr(index, env) {
+                Ok(index) => {
+                    parts.push(Part::Index(index));
+                    inner = e;
+                }
+                Err(err) => return Err(locate(err, index)),
+            },
+            _ => unreachable!("the parser only assigns to fields and indexes of a variable"),
+        }
+    };
+    parts.reverse();
+    let change = |old: &mut Value| match op {
+        Some(op) => {
+            let new = compound(op, part_of(old, &parts)?, val)?;
+            set_part(old, &parts, new)
+        }
+        None => set_part(old, &parts, val),
+    };
+    match env.change(name, change) {
+        Ok(()) => Ok(String::new()),
+        Err(err) => Err(locate(err, target)),
+    }
+}
+
+fn part_of(val: &Value, parts: &[Part]) -> Result<Value, Error> {
+    match parts.split_first() {
+        Some((Part::Field(name), rest)) => part_of(&field_of(val, name)?, rest),
+        Some((Part::Index(index), rest)) => part_of(&eval_index(val, index)?, rest),
+        None => Ok(val.clone()),
+    }
+}
+
+fn set_part(val: &mut Value, parts: &[Part], new: Value) -> Result<(), Error> {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => {
+            *val = new;
+            return Ok(());
+        }
+    };
+    match (part, val) {
+        (Part::Field(name), Value::Record(fields)) => {
+            match fields.iter().position(|(k, _)| k == name) {
+                Some(i) => set_part(&mut fields[i].1, rest, new),
+                None => {
+                    let mut field = Value::Void;
+                    match set_part(&mut field, rest, new) {
+                        Ok(()) => {
+                            fields.push((String::from(*name), field));
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+            }
+        }
+        (Part::Field(name), val) => match val {
+            Value::Void => {
+                let mut field = Value::Void;
+                match set_part(&mut field, rest, new) {
+                    Ok(()) => {
+                        *val = Value::Record(vec![(String::from(*name), field)]);
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            val => Err(Error::Type(
+                format!("Cannot set field {} of {}.", name, val.a_type_name()),
+                None,
+            )),
+        },
+        (Part::Index(index), Value::Arr(vals)) => {
+            let i = match index {
+                Value::Int(n) if *n < 0 => n + vals.len() as i64,
+                Value::Int(n) => *n,
+                index => {
+                    return Err(Error::Type(
+                        format!("Cannot index with {}.", index.a_type_name()),
+                        None,
+                    ))
+                }
+            };
+            if i < 0 || i >= vals.len() as i64 {
+                return Err(Error::Index(
+                    format!("Index {} is out of range for length {}.", index, vals.len()),
+                    None,
+                ));
+            }
+            set_part(&mut vals[i as usize], rest, new)
+        }
+        (Part::Index(_), val) => Err(Error::Type(
+            format!("Cannot set an item of {}.", val.a_type_name()),
+            None,
+        )),
+    }
+}
+
+fn exec_for(name: &str, values: &Expr, body: &[Stmt], env: &mut Env) -> Result<String, Error> {
+    let items: Box<dyn Iterator<Item = Value>> = match eval_expr(values, env) {
+        // Ranges are walked lazily rather than turned into an array first.
+        Ok(Value::Range(range)) => Box::new(range.iter().map(Value::Int)),
+        Ok(Value::Arr(vals)) => Box::new(vals.into_iter()),
+        Ok(val @ Value::Str(_)) | Ok(val @ Value::Pipeline(_)) => {
+            let lines: Vec<Value> = format!("{}", val)
+                .lines()
+                .map(|line| Value::Str(String::from(line)))
+                .collect();
+            Box::new(lines.into_iter())
+        }
+        Ok(val) => {
+            let msg = format!("Cannot loop over {}.", val.a_type_name());
+            return Err(locate(Error::Type(msg, None), values));
+        }
+        Err(err) => return Err(locate(err, values)),
+    };
+
+    // The loop variable is only bound inside the loop.
+    let mut hidden = Hidden::new();
+    env.remember(name, &mut hidden);
+    let mut out = String::new();
+    let mut res = Ok(());
+    for item in items {
+        env.insert(String::from(name), item);
+        res = exec_block(body, env, &mut out);
+        if res.is_err() {
+            break;
+        }
+    }
+    env.restore(hidden);
+    res.map(|_| nested_output(out))
+}
+
+pub fn call(closure: &Closure, args: Vec<Value>) -> Result<Value, Error> {
+    if args.len() != closure.params.len() {
+        return Err(Error::Arg(
+            format!(
+                "The closure takes {} arguments but was given {}.",
+                closure.params.len(),
+                args.len()
+            ),
+            None,
+        ));
+    }
+    let mut env = closure.env.clone();
+    for (name, arg) in closure.params.iter().zip(args) {
+        env.insert(name.clone(), arg);
+    }
+    eval_body(&closure.body, &mut env)
+}
+
+/// Runs `prog` for the value of its last statement, which is null unless it is an expression.
+fn eval_body(prog: &[Stmt], env: &mut Env) -> Result<Value, Error> {
+    let mut hidden = Hidden::new();
+    let mut res = Ok(Value::Void);
+    for stmt in prog {
+        res = match stmt {
+            Stmt::Expr(expr) => match eval_expr(expr, env) {
+                Ok(val) => Ok(val),
+                Err(err) => Err(locate(err, expr)),
+            },
+            Stmt::Let(decl, pattern, box expr, span) => {
+                match exec_let(*decl, pattern, expr, *span, env, &mut hidden) {
+                    Ok(()) => Ok(Value::Void),
+                    Err(err) => Err(err),
+                }
+            }
+            stmt => match exec_stmt(stmt, env) {
+                Ok(_out) => Ok(Value::Void),
+                Err(err) => Err(err),
+            },
+        };
+        if res.is_err() {
+            break;
+        }
+    }
+    env.restore(hidden);
+    res
+}
+
+/// The output of a block as the output of the statement containing it; exec_block puts the
+/// newline back after each statement.
+fn nested_output(out: String) -> String {
+    String::from(out.trim_end_matches('\n'))
+}
+
+/// The record a `catch` block binds for `err`, with `message`, `kind` and `code` fields.
+fn error_value(err: Error) -> Value {
+    match err.root() {
+        // A record thrown in an imported script is caught as it was thrown.
+        Error::Thrown(val @ Value::Record(_), _) => val.clone(),
+        _ => Value::Record(vec![
+            (String::from("message"), Value::Str(format!("{}", err))),
+            (String::from("kind"), Value::Str(String::from(err.kind()))),
+            (
+                String::from("code"),
+                match err.root() {
+                    Error::ExitStatus(_, code, _) => Value::Int(*code as i64),
+                    _ => Value::Void,
+                },
+            ),
+        ]),
+    }
+}
+
+fn locate(err: Error, expr: &Expr) -> Error {
+    match expr.span() {
+        Some(span) => err.or_span(span),
+        None => err,
+    }
+}
+
+fn eval_expr(expr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match expr {
+        // Errors such as overflow or bad operands point at the operation.
+        Expr::Binary(op, box lexpr, box rexpr) => match eval_binary(*op, lexpr, rexpr, env) {
+            Ok(val) => Ok(val),
+            Err(err) => Err(locate(err, expr)),
+        },
+        Expr::Unary(op, box operand) => match eval_unary(*op, operand, env) {
+            Ok(val) => Ok(val),
+            Err(err) => Err(locate(err, expr)),
+        },
+        Expr::Int(n, _) => Ok(Value::Int(*n)),
+        Expr::Num(n, _) => Ok(Value::Num(*n)),
+        Expr::Bool(b, _) => Ok(Value::Bool(*b)),
+        Expr::Null(_) => Ok(Value::Void),
+        Expr::Size(n, _) => Ok(Value::Size(*n)),
+        Expr::Duration(n, _) => Ok(Value::Duration(*n)),
+        Expr::Str(s, _) => Ok(Value::Str(String::from(s))),
+        Expr::Regex(pattern, span) => match strings::compile(pattern) {
+            Ok(re) => Ok(Value::Regex(re)),
+            Err(err) => Err(err.or_span(*span)),
+        },
+        Expr::Arr(exprs, _) => match eval_args(exprs, env) {
+            Ok(vals) => Ok(Value::Arr(vals)),
+            Err(err) => Err(err),
+        },
+        Expr::Param(s, _) => Ok(Value::Str(String::from(s))),
+        Expr::Path(s, _) => Ok(Value::Str(String::from(s))),
+        Expr::Var(s, span) => match env.get(s) {
+            Some(val) => Ok(val),
+            None if env.opts.nounset => Err(Error::Name(s.clone(), Some(*span))),
+            None => Ok(Value::Void),
+        },
+        // A set variable is looked into where it is, without copying its whole value.
+        Expr::Field(box Expr::Var(var, _), name) if env.binding(var).is_some() => {
+            env.with(var, |val| field_of(val, name))
+        }
+        Expr::Field(box expr, name) => match eval_expr(expr, env) {
+            Ok(val) => field_of(&val, name),
+            Err(err) => Err(err),
+        },
+        Expr::Index(box Expr::Var(var, _), box index, span) if env.binding(var).is_some() => {
+            match eval_expr(index, env) {
+                Ok(index) => match env.with(var, |val| eval_index(val, &index)) {
+                    Ok(val) => Ok(val),
+                    Err(err) => Err(err.or_span(*span)),
+                },
+                Err(err) => Err(err),
+            }
+        }
+        Expr::Index(box expr, box index, span) => match eval_expr2(expr, index, env) {
+            Ok((val, index)) => match eval_index(&val, &index) {
+                Ok(val) => Ok(val),
+                Err(err) => Err(err.or_span(*span)),
+            },
+            Err(err) => Err(err),
+        },
+        Expr::Method(box expr, name, args, span) => match eval_expr(expr, env) {
+            Ok(val) => match eval_args(args, env) {
+                Ok(args) => match eval_method(val, name, &args) {
+                    Ok(val) => Ok(val),
+                    Err(err) => Err(err.or_span(*span)),
+                },
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err),
+        },
+        Expr::Call(box callee, args, span) => match eval_expr(callee, env) {
+            Ok(Value::Closure(f)) => match eval_args(args, env) {
+                Ok(args) => match call(&f, args) {
+                    Ok(val) => Ok(val),
+                    Err(err) => Err(err.or_span(*span)),
+                },
+                Err(err) => Err(err),
+            },
+            Ok(val) => Err(Error::Type(
+                format!("Cannot call {}.", val.a_type_name()),
+                Some(*span),
+            )),
+            Err(err) => Err(err),
+        },
+        Expr::Cmd(_, _, _) => eval_command(expr, env),
+        Expr::Lambda(params, body, _) => {
+            let closure = Closure {
+                params: params.clone(),
+                body: body.clone(),
+                env: env.enclose(),
+            };
+            Ok(Value::Closure(Rc::new(closure)))
+        }
+        Expr::Pipe(stages) => eval_pipe(stages, env),
+        Expr::Chain(op, box lexpr, box rexpr) => eval_chain(*op, lexpr, rexpr, env),
+        Expr::Match(box subject, arms, span) => eval_match(subject, arms, *span, env),
+        // Expr::UnaryOp(_, expr) => match eval_expr(*expr) {
+        //     Ok(_) => unimplemented!(),
+        //     Err(err) => Err(err),
+        // },
+    }
+}
 
-#[derive(Clone)]
-pub enum Value {
-    Str(String),
-    Num(f64),
-    Arr(),
-    Pipeline(process::Output),
-    Void,
+fn eval_expr2(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<(Value, Value), Error> {
+    match eval_expr(lexpr, env) {
+        Ok(lval) => match eval_expr(rexpr, env) {
+            Ok(rval) => Ok((lval, rval)),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    }
 }
 
-impl Display for Value {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Value::Str(s) => write!(f, "{}", s),
-            Value::Num(n) => write!(f, "{}", n),
-            Value::Arr() => write!(f, "[]"),
-            Value::Pipeline(out) => write!(
-                f,
-                "{}",
-                match String::from_utf8(out.clone().stdout) {
-                    Ok(out) => String::from(out.trim_end()),
-                    Err(err) => format!("{}", err),
-                }
-            ),
-            Value::Void => write!(f, ""),
+fn field_of(val: &Value, name: &str) -> Result<Value, Error> {
+    match val {
+        Value::Record(_) => match val.field(name) {
+            Some(field) => Ok(field.clone()),
+            None => Ok(Value::Void),
+        },
+        val => Err(Error::Type(
+            format!("Cannot get field {} of {}.", name, val.a_type_name()),
+            None,
+        )),
+    }
+}
+
+fn eval_binary(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match op {
+        BinOp::Coalesce => eval_expr_coalesce(lexpr, rexpr, env),
+        BinOp::Or | BinOp::And => eval_expr_logic(op, lexpr, rexpr, env),
+        BinOp::Eq | BinOp::Ne => eval_expr_eq(op, lexpr, rexpr, env),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => eval_expr_cmp(op, lexpr, rexpr, env),
+        BinOp::In => eval_expr_in(lexpr, rexpr, env),
+        BinOp::Match | BinOp::NotMatch => eval_expr_match(op, lexpr, rexpr, env),
+        BinOp::Range | BinOp::RangeInclusive => eval_expr_range(op, lexpr, rexpr, env),
+        BinOp::BitOr | BinOp::BitXor | BinOp::BitAnd | BinOp::Shl | BinOp::Shr => {
+            eval_expr_bits(op, lexpr, rexpr, env)
         }
+        BinOp::Add => eval_expr_add(lexpr, rexpr, env),
+        BinOp::Sub => eval_expr_sub(lexpr, rexpr, env),
+        BinOp::Mul => eval_expr_mul(lexpr, rexpr, env),
+        BinOp::Div => eval_expr_div(lexpr, rexpr, env),
+        BinOp::IntDiv => eval_expr_int_div(lexpr, rexpr, env),
+        BinOp::Mod => eval_expr_mod(lexpr, rexpr, env),
+        BinOp::Pow => eval_expr_pow(lexpr, rexpr, env),
     }
 }
 
-pub fn interpret(prog: &Prog) -> Result<String, String> {
-    let mut env = Env::new();
+fn eval_unary(op: UnOp, expr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match (op, eval_expr(expr, env)) {
+        (UnOp::Neg, Ok(Value::Int(n))) => checked(n.checked_neg()),
+        (UnOp::Neg, Ok(Value::Num(n))) => Ok(Value::Num(-n)),
+        (UnOp::Neg, Ok(Value::Size(n))) => match n.checked_neg() {
+            Some(n) => Ok(Value::Size(n)),
+            None => checked(None),
+        },
+        (UnOp::Neg, Ok(Value::Duration(n))) => match n.checked_neg() {
+            Some(n) => Ok(Value::Duration(n)),
+            None => checked(None),
+        },
+        (UnOp::Neg, Ok(val)) => Err(Error::Type(
+            format!("Cannot negate {}.", val.a_type_name()),
+            None,
+        )),
+        (UnOp::Not, Ok(val)) => Ok(Value::Bool(!val.is_truthy())),
+        (_, Err(err)) => Err(err),
+    }
+}
 
-    for (k, v) in std::env::vars() {
-        env.insert(k, Value::Str(v));
+/// `??`, which only evaluates its right side when the left is null. A variable that was never set
+/// is null here, even under `nounset`.
+fn eval_expr_coalesce(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr(lexpr, env) {
+        Ok(Value::Void) => eval_expr(rexpr, env),
+        Err(Error::Name(..)) if matches!(lexpr, Expr::Var(..)) => eval_expr(rexpr, env),
+        result => result,
     }
+}
 
-    exec_prog(&prog, &env)
+/// `a && b` or `a || b` between commands, which gives the value of the last one run. A failing
+/// command on the left is an answer rather than an error, so errexit doesn't apply to it.
+fn eval_chain(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    let errexit = env.opts.errexit;
+    env.opts.errexit = false;
+    let left = eval_expr(lexpr, env);
+    env.opts.errexit = errexit;
+    let succeeded = match &left {
+        Ok(Value::Pipeline(out)) => out.status.success(),
+        Ok(_) => true,
+        Err(_) => false,
+    };
+    match (op, succeeded) {
+        (BinOp::And, true) | (BinOp::Or, false) => eval_expr(rexpr, env),
+        _ => left,
+    }
 }
 
-fn exec_prog(prog: &Prog, env: &Env) -> Result<String, String> {
-    match prog {
-        Prog::Stmt(box stmt, box next) => match exec_stmt(stmt, env) {
-            Ok((vcur, env)) => match exec_prog(next, &env) {
-                Ok(vnext) => match vcur.is_empty() {
-                    true => Ok(format!("{}", vnext)),
-                    false => Ok(format!("{}\n{}", vcur, vnext)),
-                },
+fn eval_expr_logic(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr(lexpr, env) {
+        Ok(lval) => match (op, lval.is_truthy()) {
+            (BinOp::And, false) => Ok(Value::Bool(false)),
+            (BinOp::Or, true) => Ok(Value::Bool(true)),
+            _ => match eval_expr(rexpr, env) {
+                Ok(rval) => Ok(Value::Bool(rval.is_truthy())),
                 Err(err) => Err(err),
             },
-            Err(err) => Err(err),
         },
-        Prog::End => Ok(String::from("")),
+        Err(err) => Err(err),
     }
 }
 
-fn exec_stmt(stmt: &Stmt, env: &Env) -> Result<(String, Env), String> {
-    match stmt {
-        Stmt::Assign(box name, box expr) => match eval_expr(expr, env) {
-            Ok((val, env)) => {
-                let mut env = env.clone();
-                env.insert(String::from(name), val);
-                Ok((String::from(""), env))
-            }
-            Err(err) => Err(err),
+/// Whether two values are equal.
+pub fn equals(lval: &Value, rval: &Value) -> bool {
+    match (lval, rval) {
+        (Value::Int(l), Value::Int(r)) => l == r,
+        (Value::Size(l), Value::Size(r)) | (Value::Duration(l), Value::Duration(r)) => l == r,
+        (Value::Date(l), Value::Date(r)) => l.nanos == r.nanos,
+        (Value::Str(l), Value::Str(r)) => l == r,
+        (Value::Bool(l), Value::Bool(r)) => l == r,
+        (Value::Void, Value::Void) => true,
+        (Value::Arr(l), Value::Arr(r)) => {
+            l.len() == r.len() && l.iter().zip(r).all(|(lv, rv)| equals(lv, rv))
+        }
+        // Two ranges with the same elements, however they were written.
+        (Value::Range(l), Value::Range(r)) => {
+            l.len() == r.len()
+                && (l.is_empty() || l.start == r.start && (l.len() == 1 || l.step == r.step))
+        }
+        (Value::Record(l), Value::Record(r)) => {
+            l.len() == r.len()
+                && l.iter()
+                    .zip(r)
+                    .all(|((lk, lv), (rk, rv))| lk == rk && equals(lv, rv))
+        }
+        (Value::Closure(l), Value::Closure(r)) => Rc::ptr_eq(l, r),
+        (Value::Regex(l), Value::Regex(r)) => l.as_str() == r.as_str(),
+        (Value::Pipeline(_), Value::Pipeline(_) | Value::Str(_))
+        | (Value::Str(_), Value::Pipeline(_)) => format!("{}", lval) == format!("{}", rval),
+        _ => match floats(lval, rval) {
+            Some((l, r)) => l == r,
+            None => false,
         },
-        Stmt::Expr(expr) => match eval_expr(expr, &env) {
-            Ok((val, env)) => Ok((format!("{}", val), env.clone())),
-            Err(err) => Err(err),
+    }
+}
+
+fn eval_expr_eq(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((lval, rval)) => {
+            let eq = equals(&lval, &rval);
+            Ok(Value::Bool(eq == (op == BinOp::Eq)))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// How two values order: numbers by value and strings by text.
+pub fn compare(lval: &Value, rval: &Value) -> Option<Ordering> {
+    match (lval, rval) {
+        (Value::Int(l), Value::Int(r)) => l.partial_cmp(r),
+        (Value::Size(l), Value::Size(r)) | (Value::Duration(l), Value::Duration(r)) => {
+            l.partial_cmp(r)
+        }
+        (Value::Date(l), Value::Date(r)) => l.nanos.partial_cmp(&r.nanos),
+        (Value::Str(l), Value::Str(r)) => l.partial_cmp(r),
+        _ => match floats(lval, rval) {
+            Some((l, r)) => l.partial_cmp(&r),
+            None => None,
         },
     }
 }
 
-fn eval_expr(expr: &Expr, env: &Env) -> Result<(Value, Env), String> {
-    match expr {
-        Expr::Add(box lexpr, box rexpr) => eval_expr_add(lexpr, rexpr, env),
-        Expr::Sub(box lexpr, box rexpr) => eval_expr_sub(lexpr, rexpr, env),
-        Expr::Mul(box lexpr, box rexpr) => eval_expr_mul(lexpr, rexpr, env),
-        Expr::Div(box lexpr, box rexpr) => eval_expr_div(lexpr, rexpr, env),
-        Expr::Mod(box lexpr, box rexpr) => eval_expr_mod(lexpr, rexpr, env),
-        Expr::Num(n) => Ok((Value::Num(*n),env.clone())),
-        Expr::Str(s) => Ok((Value::Str(String::from(s)), env.clone())),
-        Expr::Arr() => Ok((Value::Arr(), env.clone())),
-        Expr::Param(s) => Ok((Value::Str(String::from(s)), env.clone())),
-        Expr::Path(s) => Ok((Value::Str(String::from(s)), env.clone())),
-        Expr::Var(s) =>
-            match env.get(s) {
-                Some(val) => Ok((val.clone(), env.clone())),
-                None => Ok((Value::Void, env.clone())),
-            }
-        Expr::Cmd(_, _) => match eval_command(expr, env) {
-            Ok((output, env)) => return Ok((output, env)),
-            Err(err) => Err(err),
+fn eval_expr_cmp(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    let ordering = match eval_expr2(lexpr, rexpr, env) {
+        Ok((lval, rval)) => match (compare(&lval, &rval), floats(&lval, &rval)) {
+            (Some(ordering), _) => Some(ordering),
+            // NaN is not ordered against anything.
+            (None, Some(_)) => None,
+            (None, None) => return Err(operand_error(op, &lval, &rval)),
+        },
+        Err(err) => return Err(err),
+    };
+    let holds = match ordering {
+        Some(ordering) => match op {
+            BinOp::Lt => ordering == Ordering::Less,
+            BinOp::Le => ordering != Ordering::Greater,
+            BinOp::Gt => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less,
+        },
+        None => false,
+    };
+    Ok(Value::Bool(holds))
+}
+
+/// `x in values`: an element of an array or range, a substring of a string, or a field of a record.
+fn eval_expr_in(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    let found = match eval_expr2(lexpr, rexpr, env) {
+        Ok((Value::Int(n), Value::Range(range))) => range.contains(n),
+        Ok((_, Value::Range(_))) => false,
+        Ok((val, Value::Arr(vals))) => vals.iter().any(|v| equals(&val, v)),
+        Ok((Value::Str(s), Value::Str(text))) => text.contains(&s),
+        Ok((Value::Str(s), val @ Value::Record(_))) => val.field(&s).is_some(),
+        Ok((lval, rval)) => {
+            return Err(Error::Type(
+                format!(
+                    "Cannot look for {} in {}.",
+                    lval.a_type_name(),
+                    rval.a_type_name()
+                ),
+                None,
+            ))
         }
+        Err(err) => return Err(err),
+    };
+    Ok(Value::Bool(found))
+}
 
-        // Expr::UnaryOp(_, expr) => match eval_expr(*expr) {
-        //     Ok(_) => unimplemented!(),
-        //     Err(err) => Err(err),
-        // },
+fn eval_expr_match(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((text @ Value::Str(_), pattern)) | Ok((text @ Value::Pipeline(_), pattern)) => {
+            match strings::pattern(&pattern) {
+                Ok(re) => {
+                    let found = re.is_match(&format!("{}", text));
+                    Ok(Value::Bool(found == (op == BinOp::Match)))
+                }
+                Err(err) => Err(err),
+            }
+        }
+        Ok((val, _)) => Err(Error::Type(
+            format!("Cannot match {} against a pattern.", val.a_type_name()),
+            None,
+        )),
+        Err(err) => Err(err),
     }
 }
 
-fn eval_expr2(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Value), String> {
-    match eval_expr(lexpr, env) {
-        Ok((lval, env)) => match eval_expr(rexpr, &env) {
-            Ok((rval, _env)) => Ok((lval, rval)),
+fn eval_expr_range(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((Value::Int(start), Value::Int(end))) => {
+            let range = Range::new(start, end, op == BinOp::RangeInclusive);
+            Ok(Value::Range(range))
+        }
+        Ok(_) => Err(Error::Type(
+            String::from("Range bounds must be ints."),
+            None,
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+/// The result of the first arm whose pattern fits the subject and whose guard, if any, holds.
+fn eval_match(
+    subject: &Expr,
+    arms: &[(Pattern, Option<Expr>, Prog)],
+    span: Span,
+    env: &mut Env,
+) -> Result<Value, Error> {
+    let val = match eval_expr(subject, env) {
+        Ok(val) => val,
+        Err(err) => return Err(locate(err, subject)),
+    };
+    for (pattern, guard, body) in arms {
+        let mut vars = Vec::new();
+        match bind_pattern(pattern, &val, env, &mut vars) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => return Err(err.or_span(span)),
+        }
+        let mut hidden = Hidden::new();
+        for (name, val) in vars {
+            env.remember(&name, &mut hidden);
+            env.insert(name, val);
+        }
+        let holds = match guard {
+            Some(guard) => match eval_expr(guard, env) {
+                Ok(holds) => Ok(holds.is_truthy()),
+                Err(err) => Err(locate(err, guard)),
+            },
+            None => Ok(true),
+        };
+        let res = match holds {
+            Ok(true) => Some(eval_body(body, env)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        };
+        env.restore(hidden);
+        if let Some(res) = res {
+            return res;
+        }
+    }
+    let msg = format!("No arm of the match fits {}.", the_value(&val));
+    Err(Error::Type(msg, Some(span)))
+}
+
+fn the_value(val: &Value) -> String {
+    match val {
+        Value::Void => String::from("null"),
+        Value::Str(s) => format!("the string \"{}\"", s),
+        val => format!("the {} {}", val.type_name(), val),
+    }
+}
+
+/// Whether `val` fits `pattern`, adding the variables the pattern names to `bound` when it does.
+fn bind_pattern(
+    pattern: &Pattern,
+    val: &Value,
+    env: &mut Env,
+    bound: &mut Vec<(String, Value)>,
+) -> Result<bool, Error> {
+    match (pattern, val) {
+        (Pattern::Any, _) => Ok(true),
+        (Pattern::Bind(name), val) => {
+            bound.push((name.clone(), val.clone()));
+            Ok(true)
+        }
+        (Pattern::Value(expr), val) => match eval_expr(expr, env) {
+            Ok(lit) => Ok(equals(&lit, val)),
             Err(err) => Err(err),
         },
+        (Pattern::Range(lo, hi, inclusive), val) => {
+            // A bound that is left out doesn't limit that side.
+            let above = match lo.as_ref().map(|lo| eval_expr(lo, env)) {
+                Some(Ok(lo)) => compare(&lo, val),
+                Some(Err(err)) => return Err(err),
+                None => Some(Ordering::Less),
+            };
+            let below = match hi.as_ref().map(|hi| eval_expr(hi, env)) {
+                Some(Ok(hi)) => compare(val, &hi),
+                Some(Err(err)) => return Err(err),
+                None => Some(Ordering::Less),
+            };
+            Ok(match (above, below) {
+                (Some(Ordering::Less | Ordering::Equal), Some(Ordering::Less)) => true,
+                (Some(Ordering::Less | Ordering::Equal), Some(Ordering::Equal)) => *inclusive,
+                _ => false,
+            })
+        }
+        (Pattern::Regex(pattern, span), Value::Str(_) | Value::Pipeline(_)) => {
+            let re = match strings::compile(pattern) {
+                Ok(re) => re,
+                Err(err) => return Err(err.or_span(*span)),
+            };
+            let text = format!("{}", val);
+            match re.captures(&text) {
+                Some(caps) => {
+                    for name in re.capture_names().flatten() {
+                        let group = match caps.name(name) {
+                            Some(group) => Value::Str(String::from(group.as_str())),
+                            None => Value::Void,
+                        };
+                        bound.push((format!("${}", name), group));
+                    }
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+        (Pattern::Type(name), val) => Ok(val.type_name() == name),
+        (Pattern::Arr(before, rest), Value::Arr(vals)) => {
+            let after = match rest {
+                Some((_, after)) => after.as_slice(),
+                None => &[],
+            };
+            let fits = match rest {
+                Some(_) => vals.len() >= before.len() + after.len(),
+                None => vals.len() == before.len(),
+            };
+            if !fits {
+                return Ok(false);
+            }
+            let tail = vals.len() - after.len();
+            let items = before
+                .iter()
+                .zip(vals)
+                .chain(after.iter().zip(&vals[tail..]));
+            for (pattern, val) in items {
+                match bind_pattern(pattern, val, env, bound) {
+                    Ok(true) => {}
+                    other => return other,
+                }
+            }
+            if let Some((Some(name), _)) = rest {
+                bound.push((name.clone(), Value::Arr(vals[before.len()..tail].to_vec())));
+            }
+            Ok(true)
+        }
+        (Pattern::Record(fields), Value::Record(_)) => {
+            for (name, pattern) in fields {
+                match val.field(name) {
+                    Some(field) => match bind_pattern(pattern, field, env, bound) {
+                        Ok(true) => {}
+                        other => return other,
+                    },
+                    None => return Ok(false),
+                }
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// `val[index]`, where an int picks one element (counting back from the end when negative) and a
+/// range picks a slice. Strings are indexed by character.
+fn eval_index(val: &Value, index: &Value) -> Result<Value, Error> {
+    let len = match val {
+        Value::Arr(vals) => vals.len(),
+        Value::Str(s) => s.chars().count(),
+        Value::Range(range) => range.len() as usize,
+        _ => {
+            return Err(Error::Type(
+                format!("Cannot index into {}.", val.a_type_name()),
+                None,
+            ))
+        }
+    };
+    let single = matches!(index, Value::Int(_));
+    let indices = match index {
+        Value::Int(n) => {
+            let i = match *n < 0 {
+                true => n + len as i64,
+                false => *n,
+            };
+            if i < 0 || i >= len as i64 {
+                return Err(Error::Index(
+                    format!("Index {} is out of range for length {}.", n, len),
+                    None,
+                ));
+            }
+            vec![i as usize]
+        }
+        Value::Range(range) => range.indices(len),
+        index => {
+            return Err(Error::Type(
+                format!("Cannot index with {}.", index.a_type_name()),
+                None,
+            ))
+        }
+    };
+
+    match val {
+        Value::Arr(vals) => {
+            let mut picked: Vec<Value> = indices.iter().map(|i| vals[*i].clone()).collect();
+            match single {
+                true => Ok(picked.remove(0)),
+                false => Ok(Value::Arr(picked)),
+            }
+        }
+        Value::Str(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            Ok(Value::Str(indices.iter().map(|i| chars[*i]).collect()))
+        }
+        Value::Range(range) => {
+            let mut picked: Vec<Value> = indices
+                .iter()
+                .filter_map(|i| range.get(*i as u64))
+                .map(Value::Int)
+                .collect();
+            match single {
+                true => Ok(picked.remove(0)),
+                false => Ok(Value::Arr(picked)),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn eval_method(val: Value, name: &str, args: &[Value]) -> Result<Value, Error> {
+    // A closure in a field, such as a function of an imported script, is called with the args.
+    if let Some(Value::Closure(f)) = val.field(name) {
+        return call(f, args.to_vec());
+    }
+    match (val, name, args) {
+        (Value::Range(range), "rev", []) => match range.rev() {
+            Some(range) => Ok(Value::Range(range)),
+            None => Err(Error::Arith(String::from("Integer overflow."), None)),
+        },
+        (Value::Arr(mut vals), "rev", []) => {
+            vals.reverse();
+            Ok(Value::Arr(vals))
+        }
+        (Value::Range(range), "step", [Value::Int(step)]) => match range.with_step(*step) {
+            Some(range) => Ok(Value::Range(range)),
+            None => Err(Error::Arg(
+                String::from("A range's step cannot be 0."),
+                None,
+            )),
+        },
+        (val, name, args) => {
+            let types: Vec<&str> = args.iter().map(Value::type_name).collect();
+            Err(Error::Type(
+                format!(
+                    "There is no method {}({}) on {}.",
+                    name,
+                    types.join(", "),
+                    val.a_type_name()
+                ),
+                None,
+            ))
+        }
+    }
+}
+
+fn eval_expr_bits(op: BinOp, lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((Value::Int(l), Value::Int(r))) => match op {
+            BinOp::BitOr => Ok(Value::Int(l | r)),
+            BinOp::BitXor => Ok(Value::Int(l ^ r)),
+            BinOp::BitAnd => Ok(Value::Int(l & r)),
+            _ if !(0..64).contains(&r) => {
+                Err(Error::Arith(format!("Cannot shift by {} bits.", r), None))
+            }
+            BinOp::Shl => Ok(Value::Int(l << r)),
+            _ => Ok(Value::Int(l >> r)),
+        },
+        Ok(_) => Err(Error::Type(
+            String::from("Bitwise operators only apply to ints."),
+            None,
+        )),
         Err(err) => Err(err),
     }
 }
 
-fn eval_expr_div(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Env), String> {
+fn eval_expr_pow(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
     match eval_expr2(lexpr, rexpr, env) {
-        Ok((Value::Num(ln), Value::Num(rn))) => Ok((Value::Num(ln / rn), env.clone())),
-        Ok(_) => Err(String::from("Can only divide numers.")),
+        Ok((Value::Int(l), Value::Int(r))) if r >= 0 => match u32::try_from(r) {
+            Ok(r) => checked(l.checked_pow(r)),
+            Err(_) => checked(None),
+        },
+        // A negative power of an int is a fraction.
+        Ok((Value::Int(l), Value::Int(r))) => Ok(Value::Num((l as f64).powf(r as f64))),
+        Ok((lval, rval)) => match floats(&lval, &rval) {
+            Some((l, r)) => Ok(Value::Num(l.powf(r))),
+            None => Err(operand_error(BinOp::Pow, &lval, &rval)),
+        },
         Err(err) => Err(err),
     }
 }
 
-fn eval_expr_mul(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Env), String> {
+fn floats(lval: &Value, rval: &Value) -> Option<(f64, f64)> {
+    match (lval, rval) {
+        (Value::Num(l), Value::Num(r)) => Some((*l, *r)),
+        (Value::Int(l), Value::Num(r)) => Some((*l as f64, *r)),
+        (Value::Num(l), Value::Int(r)) => Some((*l, *r as f64)),
+        _ => None,
+    }
+}
+
+/// Text, which a command's output counts as when it is added to text.
+fn is_text(val: &Value) -> bool {
+    matches!(val, Value::Str(_) | Value::Pipeline(_))
+}
+
+/// The error for an operator given values it does not apply to, such as `null + 1`.
+pub fn operand_error(op: BinOp, lval: &Value, rval: &Value) -> Error {
+    let verb = match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "subtract",
+        BinOp::Mul => "multiply",
+        BinOp::Div | BinOp::IntDiv => "divide",
+        BinOp::Mod => "take the remainder of",
+        BinOp::Pow => "raise",
+        _ => "compare",
+    };
+    let number = |val: &Value| matches!(val, Value::Int(_) | Value::Num(_));
+    let hint = match (lval, rval) {
+        (Value::Str(_), val) | (val, Value::Str(_)) if number(val) => {
+            " Use `into` to convert one of them, as in `\"42\" | into int`."
+        }
+        _ => "",
+    };
+    Error::Type(
+        format!(
+            "Cannot {} {} {} {}.{}",
+            verb,
+            lval.a_type_name(),
+            if op == BinOp::Pow { "to" } else { "and" },
+            rval.a_type_name(),
+            hint
+        ),
+        None,
+    )
+}
+
+fn checked(n: Option<i64>) -> Result<Value, Error> {
+    match n {
+        Some(n) => Ok(Value::Int(n)),
+        None => Err(Error::Arith(String::from("Integer overflow."), None)),
+    }
+}
+
+fn division_by_zero() -> Error {
+    Error::Arith(String::from("Integer division by zero."), None)
+}
+
+fn eval_expr_div(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    // Dividing ints gives a float; `//` is integer division.
     match eval_expr2(lexpr, rexpr, env) {
-        Ok((Value::Num(ln), Value::Num(rn))) => Ok((Value::Num(ln * rn), env.clone())),
-        Ok(_) => Err(String::from("Can only multiply numers.")),
+        Ok((Value::Int(ln), Value::Int(rn))) => Ok(Value::Num(ln as f64 / rn as f64)),
+        Ok((lval, rval)) if units::applies(&lval, &rval) => units::arith(BinOp::Div, &lval, &rval),
+        Ok((lval, rval)) => match floats(&lval, &rval) {
+            Some((ln, rn)) => Ok(Value::Num(ln / rn)),
+            None => Err(operand_error(BinOp::Div, &lval, &rval)),
+        },
         Err(err) => Err(err),
     }
 }
 
-fn eval_expr_add(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Env), String> {
+fn eval_expr_int_div(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
     match eval_expr2(lexpr, rexpr, env) {
-        Ok((Value::Num(ln), Value::Num(rn))) => Ok((Value::Num(ln + rn), env.clone())),
-        Ok((Value::Str(ls), Value::Str(rs))) => {
-            Ok((Value::Str(format!("{}{}", ls, rs)), env.clone()))
+        Ok((Value::Int(_), Value::Int(0))) => Err(division_by_zero()),
+        Ok((Value::Int(ln), Value::Int(rn))) => match (ln.checked_div(rn), ln.wrapping_rem(rn)) {
+            // Dividing rounds towards zero, so negative results with a remainder step down one.
+            (Some(q), r) if r != 0 && (r < 0) != (rn < 0) => checked(q.checked_sub(1)),
+            (q, _) => checked(q),
+        },
+        Ok((lval, rval)) if units::applies(&lval, &rval) => {
+            units::arith(BinOp::IntDiv, &lval, &rval)
         }
-        Ok(_) => Err(String::from("Can only add values of the same type.")),
+        Ok((lval, rval)) => match floats(&lval, &rval) {
+            Some((ln, rn)) => Ok(Value::Num((ln / rn).floor())),
+            None => Err(operand_error(BinOp::IntDiv, &lval, &rval)),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+fn compound(op: BinOp, lval: Value, rval: Value) -> Result<Value, Error> {
+    match op {
+        BinOp::Add => add(lval, rval),
+        BinOp::Sub => sub(lval, rval),
+        BinOp::Mul => mul(lval, rval),
+        _ => unreachable!("the parser only makes +=, -= and *="),
+    }
+}
+
+fn eval_expr_mul(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((lval, rval)) => mul(lval, rval),
         Err(err) => Err(err),
     }
 }
 
-fn eval_expr_sub(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Env), String> {
+fn mul(lval: Value, rval: Value) -> Result<Value, Error> {
+    match (lval, rval) {
+        (Value::Int(ln), Value::Int(rn)) => checked(ln.checked_mul(rn)),
+        (lval, rval) if units::applies(&lval, &rval) => units::arith(BinOp::Mul, &lval, &rval),
+        (lval, rval) => match floats(&lval, &rval) {
+            Some((ln, rn)) => Ok(Value::Num(ln * rn)),
+            None => Err(operand_error(BinOp::Mul, &lval, &rval)),
+        },
+    }
+}
+
+fn eval_expr_add(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
     match eval_expr2(lexpr, rexpr, env) {
-        Ok((Value::Num(ln), Value::Num(rn))) => Ok((Value::Num(ln - rn), env.clone())),
-        Ok(_) => Err(String::from("Can only subtract numbers.")),
+        Ok((lval, rval)) => add(lval, rval),
         Err(err) => Err(err),
     }
 }
 
-fn eval_expr_mod(lexpr: &Expr, rexpr: &Expr, env: &Env) -> Result<(Value, Env), String> {
+fn add(lval: Value, rval: Value) -> Result<Value, Error> {
+    match (lval, rval) {
+        (Value::Int(ln), Value::Int(rn)) => checked(ln.checked_add(rn)),
+        (Value::Str(ls), Value::Str(rs)) => Ok(Value::Str(format!("{}{}", ls, rs))),
+        (lval, rval) if is_text(&lval) && is_text(&rval) => {
+            Ok(Value::Str(format!("{}{}", lval, rval)))
+        }
+        (lval, rval) if units::applies(&lval, &rval) => units::arith(BinOp::Add, &lval, &rval),
+        (lval, rval) => match floats(&lval, &rval) {
+            Some((ln, rn)) => Ok(Value::Num(ln + rn)),
+            None => Err(operand_error(BinOp::Add, &lval, &rval)),
+        },
+    }
+}
+
+fn eval_expr_sub(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
     match eval_expr2(lexpr, rexpr, env) {
-        Ok((Value::Num(ln), Value::Num(rn))) => Ok((Value::Num(ln % rn), env.clone())),
-        Ok(_) => Err(String::from("Can only mod numbers.")),
+        Ok((lval, rval)) => sub(lval, rval),
         Err(err) => Err(err),
     }
 }
 
-fn eval_command(expr: &Expr, env: &Env) -> Result<(Value, Env), String> {
-    if let Expr::Cmd(box Expr::Path(s), args) = expr {
-        match args
-            .iter()
-            .map(|a| {
-                match eval_expr(a, env) {
-                    Ok((val, _env)) => Ok(val), //throwing away any env changes that occur here.
-                    Err(err) => Err(err),
-                }
-            })
-            .collect::<Result<Vec<Value>, String>>()
-        {
+fn sub(lval: Value, rval: Value) -> Result<Value, Error> {
+    match (lval, rval) {
+        (Value::Int(ln), Value::Int(rn)) => checked(ln.checked_sub(rn)),
+        (lval, rval) if units::applies(&lval, &rval) => units::arith(BinOp::Sub, &lval, &rval),
+        (lval, rval) => match floats(&lval, &rval) {
+            Some((ln, rn)) => Ok(Value::Num(ln - rn)),
+            None => Err(operand_error(BinOp::Sub, &lval, &rval)),
+        },
+    }
+}
+
+fn eval_expr_mod(lexpr: &Expr, rexpr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    match eval_expr2(lexpr, rexpr, env) {
+        Ok((Value::Int(_), Value::Int(0))) => Err(division_by_zero()),
+        // Every int divides evenly by -1, even the one whose quotient overflows.
+        Ok((Value::Int(_), Value::Int(-1))) => Ok(Value::Int(0)),
+        // The remainder takes the sign of the divisor, to go with `//` rounding down.
+        Ok((Value::Int(ln), Value::Int(rn))) => match ln.checked_rem(rn) {
+            Some(r) if r != 0 && (r < 0) != (rn < 0) => Ok(Value::Int(r + rn)),
+            r => checked(r),
+        },
+        Ok((lval, rval)) if units::applies(&lval, &rval) => units::arith(BinOp::Mod, &lval, &rval),
+        Ok((lval, rval)) => match floats(&lval, &rval) {
+            Some((ln, rn)) => match ln % rn {
+                r if r != 0.0 && (r < 0.0) != (rn < 0.0) => Ok(Value::Num(r + rn)),
+                r => Ok(Value::Num(r)),
+            },
+            None => Err(operand_error(BinOp::Mod, &lval, &rval)),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+fn eval_args(args: &[Expr], env: &mut Env) -> Result<Vec<Value>, Error> {
+    args.iter()
+        .map(|arg| eval_expr(arg, env))
+        .collect::<Result<Vec<Value>, Error>>()
+}
+
+/// Where each of `args` is, falling back on `span`, that of the whole command.
+fn arg_spans(args: &[Expr], span: Span) -> Vec<Span> {
+    args.iter().map(|arg| arg.span().unwrap_or(span)).collect()
+}
+
+fn eval_command(expr: &Expr, env: &mut Env) -> Result<Value, Error> {
+    if let Expr::Cmd(box Expr::Path(s, _), args, span) = expr {
+        match eval_args(args, env) {
+            Ok(vals) if s == "set" => return eval_set(&vals, env),
+            Ok(vals) if builtins::is_builtin(s) => {
+                let spans = arg_spans(args, *span);
+                let input = Piped::Value(Value::Void);
+                return match builtins::run(s, input, &vals, &spans, &env.opts) {
+                    Ok(output) => match output.into_value() {
+                        Ok(val) => Ok(val),
+                        Err(err) => Err(err.or_span(*span)),
+                    },
+                    Err(err) => Err(err.or_span(*span)),
+                };
+            }
             Ok(vals) => {
+                let s = builtins::program(s);
+                let argv = to_argv(&vals);
+                trace_before(env, *span, || trace::command_line(s, &argv))?;
+                let started = Instant::now();
                 match Command::new(s)
-                    .args(vals.iter().map(|a| format!("{}", a)))
+                    .args(argv)
                     .stdin(Stdio::inherit())
                     .stdout(Stdio::inherit()) //if stdout is inherited output stream will be empty, if it is piped, we don't have a prompt for input.
                     .stderr(Stdio::inherit())
                     .output()
                 {
                     Ok(out) => {
-                        let mut env = env.clone();
+                        trace_after(env, *span, &out, started)?;
                         if let Some(code) = out.status.code() {
-                            env.insert(String::from("$?"), Value::Num(code as f64));
+                            env.insert(String::from("$?"), Value::Int(code as i64));
+                            if code != 0 && env.opts.errexit {
+                                return Err(Error::ExitStatus(String::from(s), code, Some(*span)));
+                            }
                         }
-                        return Ok((Value::Pipeline(out), env));
+                        return Ok(Value::Pipeline(out));
                     }
                     Err(err) => {
-                        return Err(format!("{}", err));
+                        return Err(spawn_error(s, err, *span));
                     }
                 }
             }
             Err(err) => return Err(err),
         }
     }
-    return Err(String::from("Failed to evaluate command."));
+    Err(Error::Type(
+        String::from("Failed to evaluate command."),
+        None,
+    ))
+}
+
+/// Runs a pipeline. Runs of external commands pass bytes to each other through OS pipes, and
+/// builtins pass values in-process. A value crossing into a command is written to it as lines, and
+/// a command's output reaches the next builtin as its lines of text.
+fn eval_pipe(stages: &[Expr], env: &mut Env) -> Result<Value, Error> {
+    let mut input: Option<Piped> = None;
+    let mut i = 0;
+    while i < stages.len() {
+        let stage = &stages[i];
+        if is_external(stage) {
+            let n = stages[i..].iter().take_while(|s| is_external(s)).count();
+            let stdin = match input.take().map(Piped::into_bytes) {
+                Some(Ok(bytes)) => Some(bytes),
+                Some(Err(err)) => return Err(locate(err, stage)),
+                None => None,
+            };
+            let capture = i + n < stages.len();
+            let val = eval_byte_pipe(&stages[i..i + n], env, stdin, capture)?;
+            input = Some(Piped::Value(val));
+            i += n;
+            continue;
+        }
+
+        input = match (input.take(), stage) {
+            (None, _) => match eval_expr(stage, env) {
+                Ok(val) => Some(Piped::Value(val)),
+                Err(err) => return Err(locate(err, stage)),
+            },
+            (Some(piped), Expr::Cmd(box Expr::Path(s, _), args, span))
+                if builtins::is_builtin(s) =>
+            {
+                let spans = arg_spans(args, *span);
+                let args = eval_args(args, env)?;
+                match builtins::run(s, piped, &args, &spans, &env.opts) {
+                    Ok(output) => Some(output),
+                    Err(err) => return Err(err.or_span(*span)),
+                }
+            }
+            (Some(_), _) => {
+                let msg = String::from("Cannot pipe into this command.");
+                return Err(locate(Error::Type(msg, None), stage));
+            }
+        };
+        i += 1;
+    }
+
+    match input.map(Piped::into_value) {
+        Some(Ok(val)) => Ok(val),
+        Some(Err(err)) => Err(err),
+        None => Ok(Value::Void),
+    }
+}
+
+fn is_external(expr: &Expr) -> bool {
+    match expr {
+        Expr::Cmd(box Expr::Path(s, _), _, _) => !builtins::is_builtin(s) && s != "set",
+        _ => false,
+    }
+}
+
+/// Runs external commands joined by OS pipes, writing `input` to the first one when given.
+fn eval_byte_pipe(
+    cmds: &[Expr],
+    env: &mut Env,
+    input: Option<Vec<u8>>,
+    capture: bool,
+) -> Result<Value, Error> {
+    let mut stages: Vec<(&str, Span, Vec<String>)> = Vec::new();
+    for cmd in cmds {
+        if let Expr::Cmd(box Expr::Path(s, _), args, span) = cmd {
+            let argv = to_argv(&eval_args(args, env)?);
+            stages.push((builtins::program(s), *span, argv));
+        }
+    }
+
+    let span = match (stages.first(), stages.last()) {
+        (Some((_, first, _)), Some((_, last, _))) => first.to(*last),
+        _ => Span::default(),
+    };
+    let traced = trace_before(env, span, || {
+        let cmds: Vec<String> = stages
+            .iter()
+            .map(|(s, _, argv)| trace::command_line(s, argv))
+            .collect();
+        cmds.join(" | ")
+    });
+    traced?;
+
+    let started = Instant::now();
+    let mut children: Vec<(&str, Span, process::Child)> = Vec::new();
+    let mut writer = None;
+    let mut stdin = match input {
+        Some(_) => Stdio::piped(),
+        None => Stdio::inherit(),
+    };
+    for (i, (s, span, argv)) in stages.iter().enumerate() {
+        let stdout = match i + 1 == stages.len() && !capture {
+            true => Stdio::inherit(),
+            false => Stdio::piped(),
+        };
+        match Command::new(s)
+            .args(argv)
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(Stdio::inherit())
+            .spawn()
+        {
+            Ok(mut child) => {
+                // Written from another thread so that a command filling its output pipe before
+                // reading all of its input can't deadlock with us.
+                if let (Some(mut pipe), Some(bytes)) = (child.stdin.take(), input.as_ref()) {
+                    let bytes = bytes.clone();
+                    writer = Some(thread::spawn(move || {
+                        // The command may stop reading early, as `head` does.
+                        let _ = pipe.write_all(&bytes);
+                    }));
+                }
+                // The last command keeps its output, to be collected when it finishes.
+                stdin = match child.stdout.take() {
+                    Some(out) if i + 1 < stages.len() => Stdio::from(out),
+                    Some(out) => {
+                        child.stdout = Some(out);
+                        Stdio::inherit()
+                    }
+                    None => Stdio::inherit(),
+                };
+                children.push((s, *span, child));
+            }
+            Err(err) => return Err(abandon(children, spawn_error(s, err, *span))),
+        }
+    }
+
+    let mut outs = Vec::new();
+    for (s, span, child) in children {
+        match child.wait_with_output() {
+            Ok(out) => outs.push((s, span, out)),
+            Err(err) => return Err(Error::Io(err, Some(span))),
+        }
+    }
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+
+    if let Some((_, _, out)) = outs.last() {
+        trace_after(env, span, out, started)?;
+    }
+
+    // The stage whose status becomes the pipeline's.
+    let status = match env.opts.pipefail {
+        true => outs.iter().rev().find(|(_, _, out)| !out.status.success()),
+        false => None,
+    };
+    let (s, span, code) = match status.or(outs.last()) {
+        Some((s, span, out)) => (*s, *span, out.status.code()),
+        None => {
+            return Err(Error::Type(
+                String::from("Failed to evaluate pipeline."),
+                None,
+            ))
+        }
+    };
+
+    if let Some(code) = code {
+        env.insert(String::from("$?"), Value::Int(code as i64));
+        if code != 0 && env.opts.errexit {
+            return Err(Error::ExitStatus(String::from(s), code, Some(span)));
+        }
+    }
+    match outs.pop() {
+        Some((_, _, out)) => Ok(Value::Pipeline(out)),
+        None => Err(Error::Type(
+            String::from("Failed to evaluate pipeline."),
+            None,
+        )),
+    }
+}
+
+/// The arguments to an external command, where each element of an array or range is an argument
+/// of its own.
+fn to_argv(vals: &[Value]) -> Vec<String> {
+    let mut argv = Vec::new();
+    for val in vals {
+        match val {
+            Value::Arr(vals) => argv.extend(to_argv(vals)),
+            Value::Range(range) => argv.extend(range.iter().map(|n| format!("{}", n))),
+            Value::Size(_) | Value::Duration(_) => argv.extend(units::argument(val)),
+            // Null is no argument at all, where `""` is an empty one.
+            Value::Void => {}
+            _ => argv.push(format!("{}", val)),
+        }
+    }
+    argv
+}
+
+fn trace_before(env: &Env, span: Span, cmd: impl FnOnce() -> String) -> Result<(), Error> {
+    match env.opts.xtrace {
+        true => match trace::before(&env.opts, env.line(span), &cmd()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::Io(err, Some(span))),
+        },
+        false => Ok(()),
+    }
+}
+
+fn trace_after(
+    env: &Env,
+    span: Span,
+    out: &process::Output,
+    started: Instant,
+) -> Result<(), Error> {
+    match env.opts.xtrace {
+        true => match trace::after(
+            &env.opts,
+            env.line(span),
+            out.status.code(),
+            started.elapsed(),
+        ) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::Io(err, Some(span))),
+        },
+        false => Ok(()),
+    }
+}
+
+fn abandon(children: Vec<(&str, Span, process::Child)>, err: Error) -> Error {
+    for (_, _, mut child) in children {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    err
+}
+
+fn spawn_error(program: &str, err: io::Error, span: Span) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => Error::CommandNotFound(String::from(program), err, Some(span)),
+        _ => Error::Io(err, Some(span)),
+    }
+}
+
+fn eval_set(args: &[Value], env: &mut Env) -> Result<Value, Error> {
+    let mut args = args.iter().map(|arg| format!("{}", arg));
+    while let Some(flag) = args.next() {
+        if let Err(msg) = env.opts.apply(&flag, &mut args) {
+            return Err(Error::Arg(msg, None));
+        }
+    }
+    Ok(Value::Void)
 }
@@ -0,0 +1,363 @@
+use crate::data;
+use crate::error::Error;
+use crate::interpreter::Value;
+use std::fmt::Write;
+
+/// Arrays and objects nested deeper than this are rejected rather than risking the stack.
+const MAX_DEPTH: usize = 512;
+
+/// Parses JSON text into a value.
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.skip_whitespace();
+    let val = parser.value(0)?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(val),
+        Some(_) => Err(parser.error("expected the end of the input")),
+    }
+}
+
+pub fn to_string(val: &Value, pretty: bool) -> Result<String, Error> {
+    let mut out = String::new();
+    match write_value(&mut out, val, pretty, 0) {
+        Ok(()) => Ok(out),
+        Err(err) => Err(err),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        data::error("JSON", self.text, self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, word: &str, val: Value) -> Result<Value, Error> {
+        match self.text[self.pos..].starts_with(word) {
+            true => {
+                self.pos += word.len();
+                Ok(val)
+            }
+            false => Err(self.error("expected a value")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => self.string().map(Value::Str),
+            Some('t') => self.expect("true", Value::Bool(true)),
+            Some('f') => self.expect("false", Value::Bool(false)),
+            Some('n') => self.expect("null", Value::Void),
+            Some('-') | Some('0'..='9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        self.skip_whitespace();
+        if let Some('}') = self.peek() {
+            self.pos += 1;
+            return Ok(Value::Record(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') => self.string()?,
+                _ => return Err(self.error("expected a string key")),
+            };
+            self.skip_whitespace();
+            match self.peek() {
+                Some(':') => self.pos += 1,
+                _ => return Err(self.error("expected ':' after an object key")),
+            }
+            self.skip_whitespace();
+            let val = self.value(depth + 1)?;
+            // A repeated key keeps its first position but takes the last value.
+            match fields.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v = val,
+                None => fields.push((key, val)),
+            }
+            self.skip_whitespace();
+            let before = self.pos;
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Record(fields)),
+                _ => {
+                    self.pos = before;
+                    return Err(self.error("expected ',' or '}' in an object"));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut vals = Vec::new();
+        self.skip_whitespace();
+        if let Some(']') = self.peek() {
+            self.pos += 1;
+            return Ok(Value::Arr(vals));
+        }
+        loop {
+            self.skip_whitespace();
+            vals.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            let before = self.pos;
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Arr(vals)),
+                _ => {
+                    self.pos = before;
+                    return Err(self.error("expected ',' or ']' in an array"));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let c = self.unicode_escape()?;
+                        s.push(c)
+                    }
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("invalid escape in a string"));
+                    }
+                },
+                Some(c) if (c as u32) < 0x20 => {
+                    self.pos = start;
+                    return Err(self.error("control character in a string"));
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The character after `\u`, which may be a pair of UTF-16 surrogates.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                if !self.text[self.pos..].starts_with("\\u") {
+                    return Err(self.error("unpaired surrogate in a string"));
+                }
+                self.pos += 2;
+                match self.hex4() {
+                    Ok(low @ 0xdc00..=0xdfff) => 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00),
+                    Ok(_) => return Err(self.error("unpaired surrogate in a string")),
+                    Err(err) => return Err(err),
+                }
+            }
+            0xdc00..=0xdfff => return Err(self.error("unpaired surrogate in a string")),
+            n => n,
+        };
+        match std::char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => Err(self.error("invalid unicode escape")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        match self.text.get(self.pos..self.pos + 4) {
+            Some(digits) if digits.chars().all(|c| c.is_ascii_hexdigit()) => {
+                self.pos += 4;
+                Ok(u32::from_str_radix(digits, 16).unwrap())
+            }
+            _ => Err(self.error("expected four hex digits after \\u")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.pos;
+        let digits = |p: &mut Parser| {
+            let from = p.pos;
+            while let Some('0'..='9') = p.peek() {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if let Some('-') = self.peek() {
+            self.pos += 1;
+        }
+        // No leading zeros, so `0` can only be followed by a fraction or exponent.
+        match self.peek() {
+            Some('0') => self.pos += 1,
+            Some('1'..='9') => {
+                digits(self);
+            }
+            _ => return Err(self.error("expected a digit")),
+        }
+        let mut int = true;
+        if let Some('.') = self.peek() {
+            self.pos += 1;
+            int = false;
+            if !digits(self) {
+                return Err(self.error("expected a digit after '.'"));
+            }
+        }
+        if let Some('e') | Some('E') = self.peek() {
+            self.pos += 1;
+            int = false;
+            if let Some('+') | Some('-') = self.peek() {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+        }
+        let text = &self.text[start..self.pos];
+        // Whole numbers too big for an int fall back to a float.
+        match (int, text.parse::<i64>()) {
+            (true, Ok(n)) => Ok(Value::Int(n)),
+            _ => match text.parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(Value::Num(n)),
+                _ => {
+                    self.pos = start;
+                    Err(self.error("number out of range"))
+                }
+            },
+        }
+    }
+}
+
+fn write_value(out: &mut String, val: &Value, pretty: bool, depth: usize) -> Result<(), Error> {
+    match val {
+        Value::Void => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{}", b).unwrap(),
+        Value::Int(n) | Value::Size(n) | Value::Duration(n) => write!(out, "{}", n).unwrap(),
+        Value::Num(n) if n.is_finite() => write!(out, "{:?}", n).unwrap(),
+        Value::Num(n) => return Err(Error::Type(format!("Cannot write {} as JSON.", n), None)),
+        Value::Str(s) => write_string(out, s),
+        Value::Pipeline(_) | Value::Regex(_) | Value::Date(_) => {
+            write_string(out, &format!("{}", val))
+        }
+        Value::Arr(vals) => {
+            let items: Vec<&Value> = vals.iter().collect();
+            return write_items(out, '[', ']', &items, pretty, depth, |out, val, depth| {
+                write_value(out, val, pretty, depth)
+            });
+        }
+        Value::Range(range) => {
+            let vals: Vec<Value> = range.iter().map(Value::Int).collect();
+            return write_value(out, &Value::Arr(vals), pretty, depth);
+        }
+        Value::Record(fields) => {
+            let items: Vec<&(String, Value)> = fields.iter().collect();
+            return write_items(
+                out,
+                '{',
+                '}',
+                &items,
+                pretty,
+                depth,
+                |out, (k, v), depth| {
+                    write_string(out, k);
+                    out.push_str(match pretty {
+                        true => ": ",
+                        false => ":",
+                    });
+                    write_value(out, v, pretty, depth)
+                },
+            );
+        }
+        Value::Closure(_) => {
+            return Err(Error::Type(
+                format!("Cannot write {} as JSON.", val.a_type_name()),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn write_items<T>(
+    out: &mut String,
+    open: char,
+    close: char,
+    items: &[T],
+    pretty: bool,
+    depth: usize,
+    write_item: impl Fn(&mut String, &T, usize) -> Result<(), Error>,
+) -> Result<(), Error> {
+    out.push(open);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if pretty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth + 1));
+        }
+        write_item(out, item, depth + 1)?;
+    }
+    if pretty && !items.is_empty() {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(close);
+    Ok(())
+}
+
+/// Writes `s` as a quoted string with JSON escapes, which TOML and YAML read the same way.
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
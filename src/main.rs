@@ -1,58 +1,179 @@
-#![feature(box_syntax, box_patterns, half_open_range_patterns, or_patterns)]
+#![feature(box_patterns)]
+#![cfg_attr(test, feature(test))]
 
 #[macro_use]
 extern crate lazy_static;
-use std::io::Write;
-use std::{env, io};
+#[cfg(test)]
+extern crate test;
+use error::Error;
+use interpreter::Options;
+use std::io::{IsTerminal, Write};
+use std::{env, fs, io, process};
 
+mod builtins;
+mod check;
+mod convert;
+mod csv;
+mod data;
+mod datetime;
+mod error;
+mod files;
 mod interpreter;
+mod json;
+mod math;
 mod parser;
+mod range;
+mod stream;
+mod strings;
+mod table;
 mod tokens;
+mod toml;
+mod trace;
+mod units;
+mod yaml;
 
 #[cfg(test)]
 mod tests;
 
-static PREFIX: &'static str = "🦪 ";
+static PREFIX: &str = "🦪 ";
+static USAGE: &str =
+    "usage: oysterlang [--strict] [-x] [--trace-timing] [--trace-file <path>] [--module-path <dir>] [script]
+
+Any option accepted by the `set` builtin may be given.";
 
 fn main() {
+    let mut opts = Options::default();
+    let mut script = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => {
+                if let Err(msg) = opts.apply(&arg, &mut args) {
+                    eprintln!("{}\n{}", msg, USAGE);
+                    process::exit(2);
+                }
+            }
+            _ if script.is_some() => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+            _ => script = Some(arg),
+        }
+    }
+
+    match script {
+        Some(path) => run_script(&path, opts),
+        None => repl(opts),
+    }
+}
+
+fn repl(mut opts: Options) {
+    if io::stdout().is_terminal() {
+        let width = env::var("COLUMNS").ok().and_then(|cols| cols.parse().ok());
+        opts.table_width = Some(width.unwrap_or(80));
+    }
     loop {
         // prompt
         print!("{}", PREFIX);
         if let Ok(dir) = env::current_dir() {
             print!("{}", dir.to_str().unwrap());
         }
-        print!("{}", ">");
+        print!(">");
 
         io::stdout().flush().unwrap();
 
         // read
         let mut input = String::new();
-        io::stdin()
+        let n = io::stdin()
             .read_line(&mut input)
             .expect("Failed to read line");
 
+        // stop at end of input
+        if n == 0 {
+            println!();
+            break;
+        }
+
         // check if input is empty
         if input.trim().is_empty() {
             continue;
         }
 
         // eval
-        let result = eval(&input);
+        let result = eval_with_options(&input, opts.clone());
 
         // print
         match result {
             Ok(ok) => print!("{}", ok),
-            Err(err) => eprintln!("{}", err),
+            Err(err) => eprintln!("{}", report(&err, &input)),
         }
     }
 }
 
-pub fn eval(input: &str) -> Result<String, String> {
-    match tokens::tokenize(input) {
-        Ok(ts) => match parser::parse(&ts) {
-            Ok(prog) => interpreter::interpret(&prog),
+fn run_script(path: &str, mut opts: Options) {
+    let mut input = match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    if !input.ends_with('\n') {
+        input.push('\n');
+    }
+    opts.script = Some(path.into());
+
+    match eval_with_options(&input, opts) {
+        Ok(ok) => print!("{}", ok),
+        Err(err) => {
+            eprintln!("{}", report_in(path, &err, &input));
+            process::exit(match err.root() {
+                Error::ExitStatus(_, code, _) => *code,
+                _ => 1,
+            });
+        }
+    }
+}
+
+pub fn eval(input: &str) -> Result<String, Error> {
+    eval_with_options(input, Options::default())
+}
+
+pub fn eval_with_options(input: &str, opts: Options) -> Result<String, Error> {
+    match tokens::tokenize_with_spans(input) {
+        Ok((ts, spans)) => match parser::parse_with_spans(&ts, &spans) {
+            Ok(prog) => {
+                for (span, msg) in check::warnings(&prog) {
+                    let (line, col) = span.line_col(input);
+                    eprintln!("{}:{}: warning: {}", line, col, msg);
+                }
+                interpreter::interpret_with_options(&prog, input, opts)
+            }
             Err(err) => Err(err),
         },
-        Err(err) => Err(format!("{}", err)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Formats `err` for the user, pointing at where in `input` it happened when that is known.
+pub fn report(err: &Error, input: &str) -> String {
+    match err.span() {
+        Some(span) => {
+            let (line, col) = span.line_col(input);
+            format!("{}:{}: {}", line, col, err)
+        }
+        None => format!("{}", err),
+    }
+}
+
+/// Formats `err` as `report` does, after the path of the script it happened in.
+pub fn report_in(path: &str, err: &Error, input: &str) -> String {
+    match err.span() {
+        Some(_) => format!("{}:{}", path, report(err, input)),
+        None => format!("{}: {}", path, err),
     }
 }
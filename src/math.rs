@@ -0,0 +1,262 @@
+use crate::error::Error;
+use crate::interpreter::Value;
+use crate::stream::Piped;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::f64::consts;
+
+const UNARY: &[&str] = &[
+    "abs", "floor", "ceil", "round", "sqrt", "exp", "log", "pow", "sin", "cos", "tan", "asin",
+    "acos", "atan",
+];
+
+const AGGREGATE: &[&str] = &["sum", "avg", "median", "min", "max", "stddev"];
+
+/// Runs `math <function>` on the numbers piped in, or on those given after the function's name
+/// when nothing is.
+pub fn run(input: Piped, args: &[Value]) -> Result<Piped, Error> {
+    let (func, args) = match args {
+        [Value::Str(func), args @ ..] => (func.as_str(), args),
+        _ => return Err(usage()),
+    };
+    match (func, args) {
+        ("pi", []) => return Ok(Piped::Value(Value::Num(consts::PI))),
+        ("e", []) => return Ok(Piped::Value(Value::Num(consts::E))),
+        ("tau", []) => return Ok(Piped::Value(Value::Num(consts::TAU))),
+        _ => {}
+    }
+
+    if AGGREGATE.contains(&func) {
+        let (sample, args): (Vec<&Value>, Vec<&Value>) = args
+            .iter()
+            .partition(|arg| matches!(arg, Value::Str(s) if s == "-s" || s == "--sample"));
+        if !sample.is_empty() && func != "stddev" {
+            return Err(usage());
+        }
+        let vals = match input {
+            Piped::Value(Value::Void) => Ok(args.into_iter().flat_map(items).collect()),
+            input if args.is_empty() => input
+                .into_stream()
+                .and_then(|items| items.collect::<Result<Vec<Value>, Error>>()),
+            _ => return Err(usage()),
+        };
+        return match vals {
+            Ok(vals) => aggregate(func, vals, !sample.is_empty()).map(Piped::Value),
+            Err(err) => Err(err),
+        };
+    }
+
+    if !UNARY.contains(&func) {
+        return Err(usage());
+    }
+    match input {
+        Piped::Value(Value::Void) => match args {
+            [val, args @ ..] => unary(func, val, args).map(Piped::Value),
+            [] => Err(usage()),
+        },
+        Piped::Value(val) if !matches!(val, Value::Arr(_) | Value::Range(_)) => {
+            unary(func, &val, args).map(Piped::Value)
+        }
+        input => match input.into_stream() {
+            Ok(items) => {
+                let (func, args) = (String::from(func), args.to_vec());
+                Ok(Piped::Stream(Box::new(items.map(move |item| match item {
+                    Ok(item) => unary(&func, &item, &args),
+                    Err(err) => Err(err),
+                }))))
+            }
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn usage() -> Error {
+    Error::Arg(
+        String::from(
+            "usage: math abs|floor|ceil|sqrt|exp|sin|cos|tan|asin|acos|atan, math round [digits], \
+             math log [base], math pow <exponent>, math sum|avg|median|min|max, \
+             math stddev [--sample], or math pi|e|tau",
+        ),
+        None,
+    )
+}
+
+fn items(val: &Value) -> Vec<Value> {
+    match val {
+        Value::Arr(vals) => vals.clone(),
+        Value::Range(range) => range.iter().map(Value::Int).collect(),
+        val => vec![val.clone()],
+    }
+}
+
+fn number(func: &str, val: &Value) -> Result<f64, Error> {
+    match val {
+        Value::Int(n) => Ok(*n as f64),
+        Value::Num(n) => Ok(*n),
+        _ => Err(Error::Type(
+            format!("math {} works on numbers, not {}.", func, val.a_type_name()),
+            None,
+        )),
+    }
+}
+
+fn arith_error(msg: String) -> Error {
+    Error::Arith(msg, None)
+}
+
+fn to_int(x: f64) -> Result<Value, Error> {
+    match x.is_finite() && x >= i64::MIN as f64 && x < i64::MAX as f64 {
+        true => Ok(Value::Int(x as i64)),
+        false => Err(arith_error(format!("{} does not fit in an int.", x))),
+    }
+}
+
+fn unary(func: &str, val: &Value, args: &[Value]) -> Result<Value, Error> {
+    let x = number(func, val)?;
+    let arg = |i: usize| match args.get(i) {
+        Some(arg) => number(func, arg),
+        None => Err(usage()),
+    };
+    match (func, val, args) {
+        ("abs", Value::Int(n), []) => match n.checked_abs() {
+            Some(n) => Ok(Value::Int(n)),
+            None => Err(arith_error(String::from("Integer overflow."))),
+        },
+        ("abs", _, []) => Ok(Value::Num(x.abs())),
+        ("floor", Value::Int(_), [])
+        | ("ceil", Value::Int(_), [])
+        | ("round", Value::Int(_), []) => Ok(val.clone()),
+        ("floor", _, []) => to_int(x.floor()),
+        ("ceil", _, []) => to_int(x.ceil()),
+        ("round", _, []) => to_int(x.round()),
+        // A negative number of digits rounds to tens, hundreds and so on.
+        ("round", _, [Value::Int(digits)]) => {
+            let scale = 10f64.powi((*digits).clamp(-308, 308) as i32);
+            Ok(Value::Num((x * scale).round() / scale))
+        }
+        ("sqrt", _, []) if x < 0.0 => Err(arith_error(format!(
+            "Cannot take the square root of {}.",
+            val
+        ))),
+        ("sqrt", _, []) => Ok(Value::Num(x.sqrt())),
+        ("exp", _, []) => Ok(Value::Num(x.exp())),
+        ("log", _, _) if x <= 0.0 => Err(arith_error(format!(
+            "Cannot take the logarithm of {}.",
+            val
+        ))),
+        ("log", _, []) => Ok(Value::Num(x.ln())),
+        ("log", _, [base]) => match arg(0) {
+            Ok(10.0) => Ok(Value::Num(x.log10())),
+            Ok(2.0) => Ok(Value::Num(x.log2())),
+            Ok(b) if b > 0.0 && b != 1.0 => Ok(Value::Num(x.log(b))),
+            Ok(_) => Err(arith_error(format!(
+                "Cannot take a logarithm to base {}.",
+                base
+            ))),
+            Err(err) => Err(err),
+        },
+        ("pow", Value::Int(n), [Value::Int(e)]) if *e >= 0 => {
+            match u32::try_from(*e).ok().and_then(|e| n.checked_pow(e)) {
+                Some(n) => Ok(Value::Int(n)),
+                None => Err(arith_error(String::from("Integer overflow."))),
+            }
+        }
+        ("pow", _, [_]) => arg(0).map(|e| Value::Num(x.powf(e))),
+        ("sin", _, []) => Ok(Value::Num(x.sin())),
+        ("cos", _, []) => Ok(Value::Num(x.cos())),
+        ("tan", _, []) => Ok(Value::Num(x.tan())),
+        ("asin", _, []) | ("acos", _, []) if !(-1.0..=1.0).contains(&x) => {
+            Err(arith_error(format!(
+                "Cannot take the {} of {}, which is outside -1 to 1.",
+                func, val
+            )))
+        }
+        ("asin", _, []) => Ok(Value::Num(x.asin())),
+        ("acos", _, []) => Ok(Value::Num(x.acos())),
+        ("atan", _, []) => Ok(Value::Num(x.atan())),
+        _ => Err(usage()),
+    }
+}
+
+fn aggregate(func: &str, vals: Vec<Value>, sample: bool) -> Result<Value, Error> {
+    // Sizes or durations all of one kind are summed up as that kind.
+    let kind: Option<fn(i64) -> Value> = match vals.first() {
+        Some(Value::Size(_)) if vals.iter().all(|val| matches!(val, Value::Size(_))) => {
+            Some(Value::Size)
+        }
+        Some(Value::Duration(_)) if vals.iter().all(|val| matches!(val, Value::Duration(_))) => {
+            Some(Value::Duration)
+        }
+        _ => None,
+    };
+    if let Some(wrap) = kind {
+        let amounts = vals
+            .iter()
+            .map(|val| match val {
+                Value::Size(n) | Value::Duration(n) => Value::Int(*n),
+                val => val.clone(),
+            })
+            .collect();
+        return match aggregate(func, amounts, sample) {
+            Ok(Value::Int(n)) => Ok(wrap(n)),
+            Ok(Value::Num(x)) => match to_int(x.round()) {
+                Ok(Value::Int(n)) => Ok(wrap(n)),
+                result => result,
+            },
+            result => result,
+        };
+    }
+    let mut xs = Vec::new();
+    for val in vals.iter() {
+        xs.push(number(func, val)?);
+    }
+    let ints = vals.iter().all(|val| matches!(val, Value::Int(_)));
+    let needed = match (func, sample) {
+        ("sum", _) => 0,
+        ("stddev", true) => 2,
+        _ => 1,
+    };
+    if xs.len() < needed {
+        return Err(arith_error(format!(
+            "math {} needs at least {} number{}.",
+            func,
+            needed,
+            if needed == 1 { "" } else { "s" }
+        )));
+    }
+    let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+    // Indices of the values from smallest to largest.
+    let mut order: Vec<usize> = (0..xs.len()).collect();
+    order.sort_by(|&a, &b| xs[a].partial_cmp(&xs[b]).unwrap_or(Ordering::Equal));
+    match func {
+        "sum" if ints => {
+            let mut total: i64 = 0;
+            for val in vals.iter() {
+                if let Value::Int(n) = val {
+                    match total.checked_add(*n) {
+                        Some(n) => total = n,
+                        None => return Err(arith_error(String::from("Integer overflow."))),
+                    }
+                }
+            }
+            Ok(Value::Int(total))
+        }
+        "sum" => Ok(Value::Num(xs.iter().sum())),
+        "avg" => Ok(Value::Num(mean)),
+        "min" => Ok(vals[order[0]].clone()),
+        "max" => Ok(vals[order[order.len() - 1]].clone()),
+        "median" => {
+            let mid = order.len() / 2;
+            match order.len() % 2 {
+                1 => Ok(vals[order[mid]].clone()),
+                _ => Ok(Value::Num((xs[order[mid - 1]] + xs[order[mid]]) / 2.0)),
+            }
+        }
+        _ => {
+            // The population's spread, or with `--sample` an estimate from a sample of it.
+            let squares: f64 = xs.iter().map(|x| (x - mean) * (x - mean)).sum();
+            let n = xs.len() - if sample { 1 } else { 0 };
+            Ok(Value::Num((squares / n as f64).sqrt()))
+        }
+    }
+}
@@ -1,214 +1,1407 @@
-use crate::tokens::Token;
+use crate::error::{Error, Span};
+use crate::tokens::{self, Token};
+use crate::units;
+use std::cell::Cell;
+use std::rc::Rc;
 
-pub enum Prog {
-    Stmt(Box<Stmt>, Box<Prog>),
-    End,
-}
+pub type Prog = Vec<Stmt>;
 
 pub enum Stmt {
-    Assign(Box<String>, Box<Expr>),
+    /// `$x = value`, with the span of `$x`.
+    Assign(String, Box<Expr>, Span),
+    /// `$cfg.port = 80` or `$xs[0] += 1`, which replaces part of a variable's value.
+    Update(Box<Expr>, Option<BinOp>, Box<Expr>),
+    /// `[$a, $b] = $pair`, `{host, port} = $cfg` or `$a, $b = $b, $a`, where the pattern must fit
+    /// the value.
+    Destructure(Pattern, Box<Expr>, Span),
+    /// `let $x = 1`, `let mut [$a, $b] = $pair` or `const $max = 10`.
+    Let(Decl, Pattern, Box<Expr>, Span),
     Expr(Expr),
+    /// `try { } catch $e { } finally { }`, where either the catch or the finally may be left out.
+    Try(Prog, Option<(Option<String>, Prog)>, Option<Prog>),
+    Throw(Expr, Span),
+    /// `if cond { } else { }`, where `else if` nests another `If` in the else block.
+    If(Box<Expr>, Prog, Option<Prog>),
+    For(String, Box<Expr>, Prog),
+    /// `import "lib/util.oy"`, `use "lib/util.oy"` or `source "lib/util.oy"`, with the path as
+    /// written.
+    Load(Load, String, Span),
+    /// `export` before an assignment, `let` or `const`, whose variables a script importing this
+    /// one is given.
+    Export(Box<Stmt>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Load {
+    /// `import "path"`, which binds a record of the script's exports to the variable after `as`, or
+    /// else to one named after the file, as `$util` is for `lib/util.oy`.
+    Import(Option<String>),
+    /// `use "path"`, which binds each of the script's exports to a variable of its own name.
+    Use,
+    /// `source "path"`, which runs the script each time as if its statements were here, so that
+    /// every variable it sets is set here.
+    Source,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decl {
+    /// `let`, which may be bound again by another `let` but not assigned.
+    Let,
+    /// `let mut`, which may be assigned. Closures share it rather than taking a copy.
+    Mut,
+    /// `const`, which may be neither assigned nor bound again.
+    Const,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp {
+    Coalesce,
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Match,
+    NotMatch,
+    Range,
+    RangeInclusive,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Mod,
+    Pow,
+}
+
+impl BinOp {
+    fn from_token(t: &Token) -> Option<BinOp> {
+        match t {
+            Token::QuestionQuestion => Some(BinOp::Coalesce),
+            Token::PipePipe => Some(BinOp::Or),
+            Token::AmpAmp => Some(BinOp::And),
+            Token::EqEq => Some(BinOp::Eq),
+            Token::BangEq => Some(BinOp::Ne),
+            Token::Lt => Some(BinOp::Lt),
+            Token::LtEq => Some(BinOp::Le),
+            Token::Gt => Some(BinOp::Gt),
+            Token::GtEq => Some(BinOp::Ge),
+            Token::In => Some(BinOp::In),
+            Token::EqTilde => Some(BinOp::Match),
+            Token::BangTilde => Some(BinOp::NotMatch),
+            Token::DotDot => Some(BinOp::Range),
+            Token::DotDotEq => Some(BinOp::RangeInclusive),
+            Token::BitOr => Some(BinOp::BitOr),
+            Token::BitXor => Some(BinOp::BitXor),
+            Token::BitAnd => Some(BinOp::BitAnd),
+            Token::Shl => Some(BinOp::Shl),
+            Token::Shr => Some(BinOp::Shr),
+            Token::Plus => Some(BinOp::Add),
+            Token::Minus => Some(BinOp::Sub),
+            Token::Ast => Some(BinOp::Mul),
+            Token::Slash => Some(BinOp::Div),
+            Token::DoubleSlash => Some(BinOp::IntDiv),
+            Token::Mod => Some(BinOp::Mod),
+            Token::AstAst => Some(BinOp::Pow),
+            _ => None,
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Coalesce => 1,
+            BinOp::Or => 2,
+            BinOp::And => 3,
+            BinOp::Eq
+            | BinOp::Ne
+            | BinOp::Lt
+            | BinOp::Le
+            | BinOp::Gt
+            | BinOp::Ge
+            | BinOp::In
+            | BinOp::Match
+            | BinOp::NotMatch => 4,
+            BinOp::Range | BinOp::RangeInclusive => 5,
+            BinOp::BitOr => 6,
+            BinOp::BitXor => 7,
+            BinOp::BitAnd => 8,
+            BinOp::Shl | BinOp::Shr => 9,
+            BinOp::Add | BinOp::Sub => 10,
+            BinOp::Mul | BinOp::Div | BinOp::IntDiv | BinOp::Mod => 11,
+            // Binds tighter than unary operators on its left, so `-2 ** 2` is `-(2 ** 2)`.
+            BinOp::Pow => 13,
+        }
+    }
+
+    fn is_right_assoc(self) -> bool {
+        self == BinOp::Pow
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+/// Unary operators bind tighter than every binary operator except `**`.
+const UNARY_PRECEDENCE: u8 = 12;
+
 pub enum Expr {
-    Add(Box<Expr>, Box<Expr>),
-    Sub(Box<Expr>, Box<Expr>),
-    Mul(Box<Expr>, Box<Expr>),
-    Div(Box<Expr>, Box<Expr>),
-    Mod(Box<Expr>, Box<Expr>),
-    Arr(),
-    Num(f64),
-    Str(String),
-    Path(String),
-    Param(String),
-    Var(String),
-    Cmd(Box<Expr>, Vec<Expr>),
-}
-
-pub fn parse(ts: &[Token]) -> Result<Prog, String> {
-    match parse_prog(ts) {
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Arr(Vec<Expr>, Span),
+    Int(i64, Span),
+    Num(f64, Span),
+    Bool(bool, Span),
+    Null(Span),
+    /// `10kb` or `2MiB`, in bytes.
+    Size(i64, Span),
+    /// `500ms` or `1h30m`, in nanoseconds.
+    Duration(i64, Span),
+    Str(String, Span),
+    /// `r"pattern"`, compiled when it is evaluated.
+    Regex(String, Span),
+    Path(String, Span),
+    Param(String, Span),
+    Var(String, Span),
+    Field(Box<Expr>, String),
+    /// `value[index]`, where the index may be a range to take a slice.
+    Index(Box<Expr>, Box<Expr>, Span),
+    Method(Box<Expr>, String, Vec<Expr>, Span),
+    /// `$f(args)`, calling the closure that `$f` holds.
+    Call(Box<Expr>, Vec<Expr>, Span),
+    Cmd(Box<Expr>, Vec<Expr>, Span),
+    Lambda(Vec<String>, Rc<Prog>, Span),
+    /// Stages joined by `|`, each reading the previous one's output.
+    Pipe(Vec<Expr>),
+    /// Commands joined by `&&` or `||`, where the right one runs only if the left one succeeded
+    /// or failed.
+    Chain(BinOp, Box<Expr>, Box<Expr>),
+    /// `match value { pattern => result }`, where each arm may have an `if` guard.
+    Match(Box<Expr>, Vec<(Pattern, Option<Expr>, Prog)>, Span),
+}
+
+pub const TYPE_NAMES: &[&str] = &[
+    "string", "int", "float", "size", "duration", "bool", "array", "range", "record", "closure",
+    "regex", "date", "pipeline",
+];
+
+/// The left side of an arm of a `match`.
+pub enum Pattern {
+    /// `_`, which fits anything.
+    Any,
+    /// `$x`, which fits anything and names it.
+    Bind(String),
+    /// A literal such as `1`, `"a"` or `null`, which fits values equal to it.
+    Value(Expr),
+    /// `1..10` or `1..=10`, which fits values that compare between the bounds. Either bound may
+    /// be left out, as in `18..` or `..0`.
+    Range(Option<Expr>, Option<Expr>, bool),
+    /// `r"pattern"`, which fits text the regex finds a match in.
+    Regex(String, Span),
+    /// A type name such as `int`, which fits any value of that type.
+    Type(String),
+    /// `[a, b]`, or `[a, ..$rest, z]` where the rest may be left unnamed as `..`.
+    Arr(Vec<Pattern>, Option<(Option<String>, Vec<Pattern>)>),
+    /// `{name: pattern, age}`, which fits records having those fields.
+    Record(Vec<(String, Pattern)>),
+}
+
+impl Expr {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Binary(_, box l, box r) | Expr::Chain(_, box l, box r) => {
+                match (l.span(), r.span()) {
+                    (Some(l), Some(r)) => Some(l.to(r)),
+                    (l, r) => l.or(r),
+                }
+            }
+            Expr::Unary(_, box e) | Expr::Field(box e, _) => e.span(),
+            Expr::Null(span)
+            | Expr::Int(_, span)
+            | Expr::Num(_, span)
+            | Expr::Bool(_, span)
+            | Expr::Size(_, span)
+            | Expr::Duration(_, span)
+            | Expr::Arr(_, span)
+            | Expr::Lambda(_, _, span)
+            | Expr::Var(_, span)
+            | Expr::Str(_, span)
+            | Expr::Path(_, span)
+            | Expr::Param(_, span)
+            | Expr::Regex(_, span)
+            | Expr::Index(_, _, span)
+            | Expr::Method(_, _, _, span)
+            | Expr::Call(_, _, span)
+            | Expr::Cmd(_, _, span)
+            | Expr::Match(_, _, span) => Some(*span),
+            Expr::Pipe(cmds) => match (
+                cmds.first().and_then(Expr::span),
+                cmds.last().and_then(Expr::span),
+            ) {
+                (Some(first), Some(last)) => Some(first.to(last)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn parse(ts: &[Token]) -> Result<Prog, Error> {
+    parse_with_spans(ts, &[])
+}
+
+pub fn parse_with_spans(ts: &[Token], spans: &[Span]) -> Result<Prog, Error> {
+    let parser = Parser {
+        spans,
+        furthest: Cell::new(ts.len()),
+    };
+    match parser.parse_prog(ts) {
         Some((prog, ts)) => match ts {
             [] => Ok(prog),
-            _ => Err(format!("Unexpected tokens: {:?}.", ts)),
+            _ => Err(Error::Parse(
+                format!("Unexpected tokens: {:?}.", ts),
+                Some(parser.span(ts)),
+            )),
         },
-        None => Err(String::from("Parse failed.")),
+        None => {
+            let rest = &ts[ts.len() - parser.furthest.get()..];
+            let msg = match rest {
+                [Token::NewLine, ..] | [] => {
+                    String::from("Parse failed: unexpected end of statement.")
+                }
+                [t, ..] => format!("Parse failed: unexpected token {:?}.", t),
+            };
+            Err(Error::Parse(msg, Some(parser.span(rest))))
+        }
     }
 }
 
-fn parse_prog(ts: &[Token]) -> Option<(Prog, &[Token])> {
-    if let [] = ts {
-        return Some((Prog::End, &[]));
+struct Parser<'s> {
+    spans: &'s [Span],
+    /// The fewest remaining tokens any statement got to before failing, for error reporting.
+    furthest: Cell<usize>,
+}
+
+impl Parser<'_> {
+    fn fail(&self, ts: &[Token]) {
+        if ts.len() < self.furthest.get() {
+            self.furthest.set(ts.len());
+        }
+    }
+
+    /// The span of the first token in `ts`, which must be a suffix of the parsed tokens.
+    fn span(&self, ts: &[Token]) -> Span {
+        match self.spans.len().checked_sub(ts.len()) {
+            Some(i) if i < self.spans.len() => self.spans[i],
+            Some(i) if i > 0 && i == self.spans.len() => {
+                let end = self.spans[i - 1].end;
+                Span::new(end, end)
+            }
+            _ => Span::default(),
+        }
+    }
+
+    /// The span from the first token of `from` up to (but not including) the first token of `to`.
+    fn span_between(&self, from: &[Token], to: &[Token]) -> Span {
+        let start = self.span(from);
+        match self.spans.len().checked_sub(to.len()) {
+            Some(i) if i > 0 && i <= self.spans.len() => start.to(self.spans[i - 1]),
+            _ => start,
+        }
     }
 
-    if let Some((stmt, ts)) = parse_stmt(ts) {
-        if let [t, ..] = ts {
-            if let Token::NewLine | Token::Semi = t {
-                if let Some((next, ts)) = parse_prog(&ts[1..]) {
-                    let prog = Prog::Stmt(Box::new(stmt), Box::new(next));
-                    return Some((prog, ts));
+    /// Whether the first token of `ts` directly follows the one before it, with no space between.
+    fn adjacent(&self, ts: &[Token]) -> bool {
+        match self.spans.len().checked_sub(ts.len()) {
+            Some(i) if i > 0 && i < self.spans.len() => {
+                self.spans[i - 1].end == self.spans[i].start
+            }
+            _ => self.spans.is_empty(),
+        }
+    }
+
+    fn parse_prog<'t>(&self, ts: &'t [Token]) -> Option<(Prog, &'t [Token])> {
+        let mut stmts = Vec::new();
+        let mut ts = ts;
+        while !ts.is_empty() {
+            match self.parse_stmt(ts) {
+                Some((stmt, [Token::NewLine, rest @ ..]))
+                | Some((stmt, [Token::Semi, rest @ ..])) => {
+                    stmts.push(stmt);
+                    ts = rest;
+                }
+                Some((_, rest)) => {
+                    self.fail(rest);
+                    return None;
+                }
+                None => {
+                    self.fail(ts);
+                    return None;
                 }
             }
         }
+        Some((stmts, ts))
     }
 
-    return None;
-}
+    fn parse_block<'t>(&self, ts: &'t [Token]) -> Option<(Prog, &'t [Token])> {
+        if let [Token::LCurl, ..] = ts {
+            return self.parse_block_body(&ts[1..]);
+        }
+
+        None
+    }
 
-fn parse_stmt(ts: &[Token]) -> Option<(Stmt, &[Token])> {
-    if let [Token::Var(name), Token::Eq, ..] = ts {
-        if let Some((expr, ts)) = parse_expr(&ts[2..]) {
-            let stmt = Stmt::Assign(box name.clone(), box expr);
-            return Some((stmt, ts));
+    fn parse_block_body<'t>(&self, ts: &'t [Token]) -> Option<(Prog, &'t [Token])> {
+        let mut stmts: Vec<Stmt> = Vec::new();
+        let mut ts = skip_newlines(ts);
+        loop {
+            if let [Token::RCurl, ..] = ts {
+                return Some((stmts, &ts[1..]));
+            }
+            match self.parse_stmt(ts) {
+                Some((stmt, ts0)) => {
+                    stmts.push(stmt);
+                    match ts0 {
+                        [Token::NewLine, ..] | [Token::Semi, ..] => ts = skip_newlines(&ts0[1..]),
+                        [Token::RCurl, ..] => ts = ts0,
+                        _ => {
+                            self.fail(ts0);
+                            return None;
+                        }
+                    }
+                }
+                None => {
+                    self.fail(ts);
+                    return None;
+                }
+            }
         }
     }
 
-    if let Some((expr, ts)) = parse_expr(ts) {
-        return Some((Stmt::Expr(expr), ts));
+    fn parse_try<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        if let [Token::Try, ..] = ts {
+            if let Some((body, ts)) = self.parse_block(skip_newlines(&ts[1..])) {
+                let mut ts = ts;
+                let mut catch = None;
+                let mut finally = None;
+
+                if let [Token::Catch, ..] = skip_newlines(ts) {
+                    let ts0 = &skip_newlines(ts)[1..];
+                    let (name, ts0) = match ts0 {
+                        [Token::Var(name), ..] => (Some(name.clone()), &ts0[1..]),
+                        _ => (None, ts0),
+                    };
+                    match self.parse_block(skip_newlines(ts0)) {
+                        Some((handler, ts0)) => {
+                            catch = Some((name, handler));
+                            ts = ts0;
+                        }
+                        None => return None,
+                    }
+                }
+
+                if let [Token::Finally, ..] = skip_newlines(ts) {
+                    match self.parse_block(skip_newlines(&skip_newlines(ts)[1..])) {
+                        Some((cleanup, ts0)) => {
+                            finally = Some(cleanup);
+                            ts = ts0;
+                        }
+                        None => return None,
+                    }
+                }
+
+                if catch.is_none() && finally.is_none() {
+                    self.fail(ts);
+                    return None;
+                }
+
+                return Some((Stmt::Try(body, catch, finally), ts));
+            }
+        }
+
+        None
     }
 
-    return None;
-}
+    fn parse_if<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        if let [Token::If, ..] = ts {
+            if let Some((cond, ts)) = self.parse_expr(&ts[1..]) {
+                if let Some((then, ts)) = self.parse_block(skip_newlines(ts)) {
+                    if let [Token::Else, ..] = skip_newlines(ts) {
+                        let ts = skip_newlines(&skip_newlines(ts)[1..]);
+                        // `else if` is an else block holding just the nested if.
+                        let otherwise = match ts {
+                            [Token::If, ..] => self.parse_if(ts).map(|(stmt, ts)| (vec![stmt], ts)),
+                            _ => self.parse_block(ts),
+                        };
+                        return match otherwise {
+                            Some((otherwise, ts)) => {
+                                let stmt = Stmt::If(Box::new(cond), then, Some(otherwise));
+                                Some((stmt, ts))
+                            }
+                            None => None,
+                        };
+                    }
+                    return Some((Stmt::If(Box::new(cond), then, None), ts));
+                }
+            }
+        }
 
-fn parse_expr(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let [Token::Path(s), ..] = ts {
-        let mut exprs: Vec<Expr> = Vec::new();
-        let mut ts = &ts[1..];
+        None
+    }
+
+    fn parse_for<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        if let [Token::For, Token::Var(name), Token::In, ..] = ts {
+            if let Some((values, ts)) = self.parse_expr(&ts[3..]) {
+                if let Some((body, ts)) = self.parse_block(skip_newlines(ts)) {
+                    let stmt = Stmt::For(name.clone(), Box::new(values), body);
+                    return Some((stmt, ts));
+                }
+            }
+        }
+
+        self.fail(ts);
+        None
+    }
+
+    fn parse_stmt<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        if let [Token::Try, ..] = ts {
+            return self.parse_try(ts);
+        }
+
+        if let [Token::If, ..] = ts {
+            return self.parse_if(ts);
+        }
+
+        if let [Token::For, ..] = ts {
+            return self.parse_for(ts);
+        }
+
+        if let [Token::Throw, ..] = ts {
+            if let Some((expr, ts0)) = self.parse_expr(&ts[1..]) {
+                let span = self.span_between(ts, ts0);
+                return Some((Stmt::Throw(expr, span), ts0));
+            }
+        }
+
+        if let [Token::Let, ..] | [Token::Const, ..] = ts {
+            return self.parse_let(ts);
+        }
+
+        if let [Token::Import, ..] | [Token::Use, ..] | [Token::Source, ..] = ts {
+            return self.parse_load(ts);
+        }
+
+        if let [Token::Export, ..] = ts {
+            return self.parse_export(ts);
+        }
+
+        if is_assignment(ts) {
+            return self.parse_assign(ts);
+        }
+
+        if let Some((expr, ts)) = self.parse_expr(ts) {
+            return Some((Stmt::Expr(expr), ts));
+        }
+
+        None
+    }
+
+    /// `import path`, `import path as $name`, `use path` or `source path`, where the path is a
+    /// string or written bare.
+    fn parse_load<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        let path = match &ts[1..] {
+            [Token::Str(s), ..] => String::from(s.trim_matches('"')),
+            [Token::Path(s), ..] => s.clone(),
+            _ => {
+                self.fail(&ts[1..]);
+                return None;
+            }
+        };
+        let (load, rest) = match (&ts[0], &ts[2..]) {
+            (Token::Import, [Token::As, Token::Var(name), rest @ ..]) if !name.contains('.') => {
+                (Load::Import(Some(name.clone())), rest)
+            }
+            (Token::Import, [Token::As, rest @ ..]) => {
+                self.fail(rest);
+                return None;
+            }
+            (Token::Import, rest) => (Load::Import(None), rest),
+            (Token::Use, rest) => (Load::Use, rest),
+            (_, rest) => (Load::Source, rest),
+        };
+        let span = self.span_between(ts, rest);
+        Some((Stmt::Load(load, path, span), rest))
+    }
+
+    fn parse_export<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        let ts0 = &ts[1..];
+        let parsed = match ts0 {
+            [Token::Let, ..] | [Token::Const, ..] => self.parse_let(ts0),
+            [Token::Var(name), Token::Eq, ..] if !name.contains('.') => self.parse_assign(ts0),
+            _ => {
+                self.fail(ts0);
+                None
+            }
+        };
+        match parsed {
+            Some((stmt, rest)) => Some((Stmt::Export(Box::new(stmt)), rest)),
+            None => None,
+        }
+    }
+
+    fn parse_let<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        let (decl, ts0) = match ts {
+            [Token::Let, Token::Mut, ..] => (Decl::Mut, &ts[2..]),
+            [Token::Let, ..] => (Decl::Let, &ts[1..]),
+            _ => (Decl::Const, &ts[1..]),
+        };
+        match self.parse_pattern(ts0) {
+            Some((pattern, eq @ [Token::Eq, ..])) => {
+                let span = self.span_between(ts, eq);
+                match self.parse_expr(&eq[1..]) {
+                    Some((expr, rest)) => {
+                        Some((Stmt::Let(decl, pattern, Box::new(expr), span), rest))
+                    }
+                    None => None,
+                }
+            }
+            Some((_, rest)) => {
+                self.fail(rest);
+                None
+            }
+            None => {
+                self.fail(ts0);
+                None
+            }
+        }
+    }
+
+    /// `$x = value`, or one of the other assignments: to part of a variable as in
+    /// `$cfg.port = 80`, by an operator as in `$n += 1`, or to patterns as in `[$a, $b] = $pair`
+    /// and `$a, $b = $b, $a`.
+    fn parse_assign<'t>(&self, ts: &'t [Token]) -> Option<(Stmt, &'t [Token])> {
+        if let [Token::Var(_), ..] = ts {
+            if let Some((target, ts0)) = self.parse_factor(ts) {
+                match ts0 {
+                    [Token::Eq, rest @ ..] => return self.parse_assign_to(ts, target, None, rest),
+                    [t, rest @ ..] if compound_op(t).is_some() => {
+                        return self.parse_assign_to(ts, target, compound_op(t), rest)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut patterns = Vec::new();
+        let mut ts0 = ts;
+        loop {
+            match self.parse_pattern(ts0) {
+                Some((pattern, [Token::Comma, rest @ ..])) => {
+                    patterns.push(pattern);
+                    ts0 = rest;
+                }
+                Some((pattern, [Token::Eq, rest @ ..])) => {
+                    patterns.push(pattern);
+                    ts0 = rest;
+                    break;
+                }
+                Some((_, rest)) => {
+                    self.fail(rest);
+                    return None;
+                }
+                None => {
+                    self.fail(ts0);
+                    return None;
+                }
+            }
+        }
+        let span = self.span_between(ts, ts0);
+        let values = ts0;
+        let mut exprs = Vec::new();
         loop {
-            // Gather the following expressions as a vector for modifying the command.
-            if let Some((expr, ts0)) = parse_factor(ts) {
-                exprs.push(expr);
-                ts = ts0;
-                continue;
+            match self.parse_expr(ts0) {
+                Some((expr, [Token::Comma, rest @ ..])) => {
+                    exprs.push(expr);
+                    ts0 = rest;
+                }
+                Some((expr, rest)) => {
+                    exprs.push(expr);
+                    ts0 = rest;
+                    break;
+                }
+                None => return None,
             }
-            break;
         }
-        let expr = Expr::Cmd(box Expr::Path(String::from(s)), exprs);
-        return Some((expr, ts));
+        // Several targets take the items of an array, and several values make one.
+        let pattern = match patterns.len() {
+            1 => patterns.pop().unwrap(),
+            _ => Pattern::Arr(patterns, None),
+        };
+        let expr = match exprs.len() {
+            1 => exprs.pop().unwrap(),
+            _ => Expr::Arr(exprs, self.span_between(values, ts0)),
+        };
+        Some((Stmt::Destructure(pattern, Box::new(expr), span), ts0))
     }
 
-    if let Some((lfactor, ts)) = parse_term(ts) {
-        let mut expr = lfactor;
-        let mut ts = ts;
+    /// The value assigned to `target`, a variable or part of one, where `op` is the operator of a
+    /// compound assignment.
+    fn parse_assign_to<'t>(
+        &self,
+        ts: &'t [Token],
+        target: Expr,
+        op: Option<BinOp>,
+        rest: &'t [Token],
+    ) -> Option<(Stmt, &'t [Token])> {
+        let (expr, rest) = self.parse_expr(rest)?;
+        match target {
+            // `$x += 1` is `$x = $x + 1`.
+            Expr::Var(name, span) => {
+                let expr = match op {
+                    Some(op) => {
+                        let current = Expr::Var(name.clone(), span);
+                        Expr::Binary(op, Box::new(current), Box::new(expr))
+                    }
+                    None => expr,
+                };
+                Some((Stmt::Assign(name, Box::new(expr), span), rest))
+            }
+            target if is_part(&target) => {
+                Some((Stmt::Update(Box::new(target), op, Box::new(expr)), rest))
+            }
+            _ => {
+                self.fail(ts);
+                None
+            }
+        }
+    }
+
+    fn parse_expr<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        self.parse_chain(ts)
+    }
+
+    /// Pipelines joined by `&&` and `||`, as in `make && make install`. Between expressions,
+    /// these are taken by `parse_binary` as logical operators.
+    fn parse_chain<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        let (mut expr, mut ts) = self.parse_pipe(ts)?;
         loop {
-            // Keep finding arithmetic stuff chained onto the right side and nest them in expressions.
-            if let [t, ..] = ts {
-                if let Token::Plus | Token::Minus = t {
-                    if let Some((rexpr, ts0)) = parse_term(&ts[1..]) {
-                        expr = match t {
-                            Token::Plus => Expr::Add(box expr, box rexpr),
-                            Token::Minus => Expr::Sub(box expr, box rexpr),
-                            _ => unreachable!(),
-                        };
+            let op = match ts {
+                [Token::AmpAmp, ..] => BinOp::And,
+                [Token::PipePipe, ..] => BinOp::Or,
+                _ => return Some((expr, ts)),
+            };
+            // Like a pipe, either may be followed by a line break before the next command.
+            match self.parse_pipe(skip_newlines(&ts[1..])) {
+                Some((rexpr, ts0)) => {
+                    expr = Expr::Chain(op, Box::new(expr), Box::new(rexpr));
+                    ts = ts0;
+                }
+                None => {
+                    self.fail(&ts[1..]);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Parses operators that bind at least as tightly as `min`, by precedence climbing.
+    fn parse_binary<'t>(&self, ts: &'t [Token], min: u8) -> Option<(Expr, &'t [Token])> {
+        if let Some((lexpr, ts)) = self.parse_unary(ts) {
+            let mut expr = lexpr;
+            let mut ts = ts;
+            loop {
+                // Keep folding operators onto the left side while they bind tightly enough.
+                if let [t, ..] = ts {
+                    if let Some(op) = BinOp::from_token(t) {
+                        let prec = op.precedence();
+                        if prec >= min {
+                            let next = match op.is_right_assoc() {
+                                true => prec,
+                                false => prec + 1,
+                            };
+                            if let Some((rexpr, ts0)) = self.parse_binary(&ts[1..], next) {
+                                expr = Expr::Binary(op, Box::new(expr), Box::new(rexpr));
+                                ts = ts0;
+                                continue;
+                            }
+                        }
+                    }
+                }
+                break;
+            }
+
+            return Some((expr, ts));
+        }
+
+        None
+    }
+
+    fn parse_unary<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        let op = match ts {
+            [Token::Minus, ..] => UnOp::Neg,
+            [Token::Bang, ..] => UnOp::Not,
+            _ => return self.parse_factor(ts),
+        };
+        match self.parse_binary(&ts[1..], UNARY_PRECEDENCE) {
+            Some((expr, ts)) => Some((Expr::Unary(op, Box::new(expr)), ts)),
+            None => None,
+        }
+    }
+
+    fn parse_pipe<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        let head = match ts {
+            [Token::Path(_), ..] => self.parse_cmd(ts),
+            _ => self.parse_binary(ts, 0),
+        };
+        if let Some((cmd, ts)) = head {
+            let mut cmds = vec![cmd];
+            let mut ts = ts;
+            loop {
+                // A pipe may be followed by a line break before the next command.
+                if let [Token::Pipe, ..] = ts {
+                    if let Some((cmd, ts0)) = self.parse_cmd(skip_newlines(&ts[1..])) {
+                        cmds.push(cmd);
                         ts = ts0;
                         continue;
                     }
                 }
+                break;
             }
-            break;
+
+            return match cmds.len() {
+                1 => Some((cmds.pop().unwrap(), ts)),
+                _ => Some((Expr::Pipe(cmds), ts)),
+            };
         }
 
-        return Some((expr, ts));
+        None
     }
 
-    return None;
-}
+    fn parse_cmd<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Path(s), ..] = ts {
+            if s == "where" {
+                return self.parse_where(ts);
+            }
 
-fn parse_term(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let Some((lfactor, ts)) = parse_factor(ts) {
-        let mut expr = lfactor;
-        let mut ts = ts;
+            let start = ts;
+            let mut exprs: Vec<Expr> = Vec::new();
+            let mut ts = &ts[1..];
+            loop {
+                // Gather the following expressions as a vector for modifying the command.
+                if let Some((expr, ts0)) = self.parse_arg(ts) {
+                    exprs.push(expr);
+                    ts = ts0;
+                    continue;
+                }
+                break;
+            }
+            let span = self.span_between(start, ts);
+            let path = Expr::Path(String::from(s), self.span(start));
+            let expr = Expr::Cmd(Box::new(path), exprs, span);
+            return Some((expr, ts));
+        }
+
+        None
+    }
+
+    /// `where` followed by a condition on the fields of each item, as in `where size > 10`. Bare
+    /// words in the condition name fields of the item, which is also `$it`. A closure or a
+    /// variable holding one is passed on as it is.
+    fn parse_where<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let Some((cond, ts0)) = self.parse_binary(&ts[1..], 0) {
+            let span = self.span_between(ts, ts0);
+            let arg = match cond {
+                Expr::Lambda(_, _, _) | Expr::Var(_, _) => cond,
+                cond => {
+                    let cond_span = self.span_between(&ts[1..], ts0);
+                    let body = Stmt::Expr(fields_of_it(cond));
+                    Expr::Lambda(vec![String::from("$it")], Rc::new(vec![body]), cond_span)
+                }
+            };
+            let path = Expr::Path(String::from("where"), self.span(ts));
+            return Some((Expr::Cmd(Box::new(path), vec![arg], span), ts0));
+        }
+
+        self.fail(&ts[1..]);
+        None
+    }
+
+    /// A command argument. Here `-1` is a negative number rather than a subtraction, `-x`
+    /// stays a flag and keywords are plain words, as in `echo in`. A `+` with no space after it
+    /// starts a word running up to the next space, as in `^date +%Y-%m-%d`.
+    fn parse_arg<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let Some(word) = ts.first().and_then(tokens::keyword_word) {
+            return Some((Expr::Path(String::from(word), self.span(ts)), &ts[1..]));
+        }
+        if let [Token::Plus, _, ..] = ts {
+            let glued = |i: &usize| {
+                let ends = matches!(
+                    ts[*i],
+                    Token::NewLine
+                        | Token::Semi
+                        | Token::Pipe
+                        | Token::PipePipe
+                        | Token::AmpAmp
+                        | Token::Comma
+                        | Token::RParen
+                        | Token::RSq
+                        | Token::RCurl
+                );
+                !ends && !self.spans.is_empty() && self.adjacent(&ts[*i..])
+            };
+            let n = 1 + (1..ts.len()).take_while(glued).count();
+            if n > 1 {
+                let word: String = ts[..n].iter().map(tokens::text).collect();
+                let span = self.span_between(ts, &ts[n..]);
+                return Some((Expr::Path(word, span), &ts[n..]));
+            }
+        }
+        if let [Token::Minus, Token::Num(_), ..] = ts {
+            let span = self.span_between(ts, &ts[2..]);
+            return match self.parse_num(&ts[1..]) {
+                Some((Expr::Int(n, _), ts)) => Some((Expr::Int(-n, span), ts)),
+                Some((Expr::Num(n, _), ts)) => Some((Expr::Num(-n, span), ts)),
+                Some((Expr::Size(n, _), ts)) => Some((Expr::Size(-n, span), ts)),
+                Some((Expr::Duration(n, _), ts)) => Some((Expr::Duration(-n, span), ts)),
+                _ => None,
+            };
+        }
+
+        self.parse_factor(ts)
+    }
+
+    /// A value followed by any number of `.field`s, `.method(args)`s and `[index]`es.
+    fn parse_factor<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let Some((atom, ts0)) = self.parse_atom(ts) {
+            let start = ts;
+            let mut expr = atom;
+            let mut ts = ts0;
+            loop {
+                match ts {
+                    [Token::Dot, Token::Path(name), ..] => {
+                        expr = Expr::Field(Box::new(expr), name.clone());
+                        ts = &ts[2..];
+                    }
+                    // A field directly followed by arguments is a method call, and a variable
+                    // or closure directly followed by them is a call of the closure.
+                    [Token::LParen, ..] if self.adjacent(ts) => match expr {
+                        Expr::Field(value, name) => match self.parse_list(ts, &Token::RParen) {
+                            Some((args, ts0)) => {
+                                let span = self.span_between(start, ts0);
+                                expr = Expr::Method(value, name, args, span);
+                                ts = ts0;
+                            }
+                            None => return None,
+                        },
+                        Expr::Var(..) | Expr::Index(..) | Expr::Call(..) | Expr::Lambda(..) => {
+                            match self.parse_list(ts, &Token::RParen) {
+                                Some((args, ts0)) => {
+                                    let span = self.span_between(start, ts0);
+                                    expr = Expr::Call(Box::new(expr), args, span);
+                                    ts = ts0;
+                                }
+                                None => return None,
+                            }
+                        }
+                        _ => return Some((expr, ts)),
+                    },
+                    [Token::LSq, ..] if self.adjacent(ts) => match self.parse_expr(&ts[1..]) {
+                        Some((index, [Token::RSq, rest @ ..])) => {
+                            let span = self.span_between(start, rest);
+                            expr = Expr::Index(Box::new(expr), Box::new(index), span);
+                            ts = rest;
+                        }
+                        // `$x[1..]` slices to the end.
+                        Some((lo, [Token::DotDot, Token::RSq, rest @ ..])) => {
+                            let span = self.span_between(start, rest);
+                            let hi = Expr::Int(i64::MAX, span);
+                            let index = Expr::Binary(BinOp::Range, Box::new(lo), Box::new(hi));
+                            expr = Expr::Index(Box::new(expr), Box::new(index), span);
+                            ts = rest;
+                        }
+                        _ => {
+                            self.fail(&ts[1..]);
+                            return None;
+                        }
+                    },
+                    _ => return Some((expr, ts)),
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Expressions separated by commas up to `close`, where `ts` starts with the opening bracket.
+    fn parse_list<'t>(&self, ts: &'t [Token], close: &Token) -> Option<(Vec<Expr>, &'t [Token])> {
+        let mut exprs: Vec<Expr> = Vec::new();
+        let mut ts = skip_newlines(&ts[1..]);
         loop {
-            // Keep finding multiplicitive stuff chained onto the right side and nest them in expressions.
             if let [t, ..] = ts {
-                if let Token::Ast | Token::Slash | Token::Mod = t {
-                    if let Some((rexpr, ts0)) = parse_factor(&ts[1..]) {
-                        expr = match t {
-                            Token::Ast => Expr::Mul(box expr, box rexpr),
-                            Token::Slash => Expr::Div(box expr, box rexpr),
-                            Token::Mod => Expr::Mod(box expr, box rexpr),
-                            _ => unreachable!(),
-                        };
-                        ts = ts0;
-                        continue;
-                    }
+                if t == close {
+                    return Some((exprs, &ts[1..]));
                 }
             }
-            break;
+            match self.parse_expr(ts) {
+                Some((expr, ts0)) => {
+                    exprs.push(expr);
+                    ts = match skip_newlines(ts0) {
+                        [Token::Comma, ..] => skip_newlines(&skip_newlines(ts0)[1..]),
+                        [t, ..] if t == close => skip_newlines(ts0),
+                        _ => {
+                            self.fail(ts0);
+                            return None;
+                        }
+                    };
+                }
+                None => {
+                    self.fail(ts);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn parse_atom<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let Some(some) = self.parse_num(ts) {
+            return Some(some);
+        }
+
+        if let Some(some) = self.parse_str(ts) {
+            return Some(some);
+        }
+
+        match ts {
+            [Token::True, ..] => return Some((Expr::Bool(true, self.span(ts)), &ts[1..])),
+            [Token::False, ..] => return Some((Expr::Bool(false, self.span(ts)), &ts[1..])),
+            [Token::Null, ..] => return Some((Expr::Null(self.span(ts)), &ts[1..])),
+            _ => {}
+        }
+
+        if let Some(some) = self.parse_regex(ts) {
+            return Some(some);
+        }
+
+        if let Some(some) = self.parse_path(ts) {
+            return Some(some);
+        }
+
+        if let Some(some) = self.parse_param(ts) {
+            return Some(some);
+        }
+
+        if let [Token::Var(s), ..] = ts {
+            // `$a.b.c` lexes as a single token; everything after the first dot is a field lookup.
+            let mut parts = s.split('.');
+            let name = String::from(parts.next().unwrap());
+            let expr = parts.fold(Expr::Var(name, self.span(ts)), |expr, field| {
+                Expr::Field(Box::new(expr), String::from(field))
+            });
+            return Some((expr, &ts[1..]));
         }
 
-        return Some((expr, ts));
+        if let [Token::LCurl, Token::Pipe, ..] | [Token::LCurl, Token::PipePipe, ..] = ts {
+            return self.parse_lambda(ts);
+        }
+
+        if let [Token::Match, ..] = ts {
+            return self.parse_match(ts);
+        }
+
+        if let [Token::LSq, ..] = ts {
+            if let Some((exprs, ts0)) = self.parse_list(ts, &Token::RSq) {
+                return Some((Expr::Arr(exprs, self.span_between(ts, ts0)), ts0));
+            }
+        }
+
+        if let [Token::LParen, ..] = ts {
+            if let Some((expr, ts)) = self.parse_expr(&ts[1..]) {
+                if let [Token::RParen, ..] = ts {
+                    // Make sure you find a right parenthesis at the end of this nested expression.
+                    return Some((expr, &ts[1..]));
+                }
+            }
+        }
+
+        None
     }
 
-    return None;
-}
+    fn parse_lambda<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        let start = ts;
+        let mut params: Vec<String> = Vec::new();
+        let ts = match ts {
+            [Token::LCurl, Token::PipePipe, ..] => &ts[2..],
+            _ => {
+                let mut ts = &ts[2..];
+                loop {
+                    match ts {
+                        [Token::Var(name), Token::Comma, ..] => {
+                            params.push(name.clone());
+                            ts = &ts[2..];
+                        }
+                        [Token::Var(name), Token::Pipe, ..] => {
+                            params.push(name.clone());
+                            break &ts[2..];
+                        }
+                        [Token::Pipe, ..] if params.is_empty() => break &ts[1..],
+                        _ => {
+                            self.fail(ts);
+                            return None;
+                        }
+                    }
+                }
+            }
+        };
+        match self.parse_block_body(ts) {
+            Some((body, ts)) => {
+                let span = self.span_between(start, ts);
+                Some((Expr::Lambda(params, Rc::new(body), span), ts))
+            }
+            None => None,
+        }
+    }
 
-fn parse_factor(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let Some(some) = parse_num(ts) {
-        return Some(some);
+    /// `match value { ... }`, with one `pattern => result` arm per line (or between commas).
+    fn parse_match<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        let start = ts;
+        let (subject, ts) = match self.parse_expr(&ts[1..]) {
+            Some((subject, ts)) => (subject, skip_newlines(ts)),
+            None => return None,
+        };
+        let mut ts = match ts {
+            [Token::LCurl, ..] => skip_newlines(&ts[1..]),
+            _ => {
+                self.fail(ts);
+                return None;
+            }
+        };
+        let mut arms = Vec::new();
+        loop {
+            if let [Token::RCurl, rest @ ..] = ts {
+                let span = self.span_between(start, rest);
+                return Some((Expr::Match(Box::new(subject), arms, span), rest));
+            }
+            let (pattern, ts0) = match self.parse_pattern(ts) {
+                Some(some) => some,
+                None => {
+                    self.fail(ts);
+                    return None;
+                }
+            };
+            let (guard, ts0) = match ts0 {
+                [Token::If, ..] => match self.parse_expr(&ts0[1..]) {
+                    Some((guard, ts0)) => (Some(guard), ts0),
+                    None => return None,
+                },
+                _ => (None, ts0),
+            };
+            let ts0 = match ts0 {
+                [Token::FatArrow, ..] => skip_newlines(&ts0[1..]),
+                _ => {
+                    self.fail(ts0);
+                    return None;
+                }
+            };
+            // A result in braces is a block, unless the braces make a closure.
+            let body = match ts0 {
+                [Token::LCurl, t, ..] if *t != Token::Pipe && *t != Token::PipePipe => {
+                    self.parse_block(ts0)
+                }
+                _ => self.parse_stmt(ts0).map(|(stmt, ts)| (vec![stmt], ts)),
+            };
+            let ts0 = match body {
+                Some((body, ts0)) => {
+                    arms.push((pattern, guard, body));
+                    ts0
+                }
+                None => return None,
+            };
+            ts = match ts0 {
+                [Token::NewLine, ..] | [Token::Semi, ..] | [Token::Comma, ..] => {
+                    skip_newlines(&ts0[1..])
+                }
+                [Token::RCurl, ..] => ts0,
+                _ => {
+                    self.fail(ts0);
+                    return None;
+                }
+            };
+        }
     }
 
-    if let Some(some) = parse_str(ts) {
-        return Some(some);
+    fn parse_pattern<'t>(&self, ts: &'t [Token]) -> Option<(Pattern, &'t [Token])> {
+        match ts {
+            [Token::Underscore, ..] => return Some((Pattern::Any, &ts[1..])),
+            [Token::Var(name), ..] if !name.contains('.') => {
+                return Some((Pattern::Bind(name.clone()), &ts[1..]))
+            }
+            [Token::Path(name), ..] if TYPE_NAMES.contains(&name.as_str()) => {
+                return Some((Pattern::Type(name.clone()), &ts[1..]))
+            }
+            [Token::Regex(_), ..] => {
+                return match self.parse_regex(ts) {
+                    Some((Expr::Regex(pattern, span), ts)) => {
+                        Some((Pattern::Regex(pattern, span), ts))
+                    }
+                    _ => None,
+                }
+            }
+            [Token::LSq, ..] => return self.parse_arr_pattern(ts),
+            [Token::LCurl, ..] => return self.parse_record_pattern(ts),
+            _ => {}
+        }
+
+        let (lo, ts) = match ts {
+            [Token::DotDot, ..] | [Token::DotDotEq, ..] => (None, ts),
+            _ => match self.parse_literal(ts) {
+                Some((lo, ts)) => (Some(lo), ts),
+                None => return None,
+            },
+        };
+        let inclusive = match ts {
+            [Token::DotDot, ..] => false,
+            [Token::DotDotEq, ..] => true,
+            _ => return lo.map(|lo| (Pattern::Value(lo), ts)),
+        };
+        match (self.parse_literal(&ts[1..]), inclusive) {
+            (Some((hi, ts)), _) => Some((Pattern::Range(lo, Some(hi), inclusive), ts)),
+            // Only `..` may leave out its end, and only after a start.
+            (None, false) if lo.is_some() => Some((Pattern::Range(lo, None, false), &ts[1..])),
+            (None, _) => {
+                self.fail(&ts[1..]);
+                None
+            }
+        }
     }
 
-    if let Some(some) = parse_path(ts) {
-        return Some(some);
+    fn parse_literal<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        match ts {
+            [Token::Minus, Token::Num(_), ..] => self.parse_arg(ts),
+            [Token::Num(_), ..]
+            | [Token::Str(_), ..]
+            | [Token::True, ..]
+            | [Token::False, ..]
+            | [Token::Null, ..] => self.parse_atom(ts),
+            _ => None,
+        }
     }
 
-    if let Some(some) = parse_param(ts) {
-        return Some(some);
+    fn parse_arr_pattern<'t>(&self, ts: &'t [Token]) -> Option<(Pattern, &'t [Token])> {
+        let mut before = Vec::new();
+        let mut rest: Option<(Option<String>, Vec<Pattern>)> = None;
+        let mut ts = skip_newlines(&ts[1..]);
+        loop {
+            if let [Token::RSq, ..] = ts {
+                return Some((Pattern::Arr(before, rest), &ts[1..]));
+            }
+            let ts0 = match (ts, &mut rest) {
+                ([Token::DotDot, Token::Var(name), ..], None) => {
+                    rest = Some((Some(name.clone()), Vec::new()));
+                    &ts[2..]
+                }
+                ([Token::DotDot, ..], None) => {
+                    rest = Some((None, Vec::new()));
+                    &ts[1..]
+                }
+                (_, rest) => match self.parse_pattern(ts) {
+                    Some((pattern, ts0)) => {
+                        match rest {
+                            Some((_, after)) => after.push(pattern),
+                            None => before.push(pattern),
+                        }
+                        ts0
+                    }
+                    None => {
+                        self.fail(ts);
+                        return None;
+                    }
+                },
+            };
+            ts = match skip_newlines(ts0) {
+                [Token::Comma, ..] => skip_newlines(&skip_newlines(ts0)[1..]),
+                [Token::RSq, ..] => skip_newlines(ts0),
+                _ => {
+                    self.fail(ts0);
+                    return None;
+                }
+            };
+        }
     }
 
-    if let [Token::Var(s), ..] = ts {
-        return Some((Expr::Var(s.clone()), &ts[1..]));
+    fn parse_record_pattern<'t>(&self, ts: &'t [Token]) -> Option<(Pattern, &'t [Token])> {
+        let mut fields = Vec::new();
+        let mut ts = skip_newlines(&ts[1..]);
+        loop {
+            if let [Token::RCurl, ..] = ts {
+                return Some((Pattern::Record(fields), &ts[1..]));
+            }
+            let (name, ts0) = match ts {
+                [Token::Path(name), ..] => (name.clone(), &ts[1..]),
+                [Token::Str(name), ..] => (String::from(name.trim_matches('"')), &ts[1..]),
+                _ => {
+                    self.fail(ts);
+                    return None;
+                }
+            };
+            let (pattern, ts0) = match ts0 {
+                [Token::Colon, ..] => match self.parse_pattern(skip_newlines(&ts0[1..])) {
+                    Some(some) => some,
+                    None => {
+                        self.fail(&ts0[1..]);
+                        return None;
+                    }
+                },
+                _ => (Pattern::Bind(format!("${}", name)), ts0),
+            };
+            fields.push((name, pattern));
+            ts = match skip_newlines(ts0) {
+                [Token::Comma, ..] => skip_newlines(&skip_newlines(ts0)[1..]),
+                [Token::RCurl, ..] => skip_newlines(ts0),
+                _ => {
+                    self.fail(ts0);
+                    return None;
+                }
+            };
+        }
     }
 
-    if let [Token::LParen, ..] = ts {
-        if let Some((expr, ts)) = parse_expr(&ts[1..]) {
-            if let [Token::RParen, ..] = ts {
-                // Make sure you find a right parenthesis at the end of this nested expression.
-                return Some((expr, &ts[1..]));
+    fn parse_num<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Num(s), ..] = ts {
+            let digits = s.replace('_', "");
+            if let Some(bytes) = units::size(&digits) {
+                return Some((Expr::Size(bytes, self.span(ts)), &ts[1..]));
+            }
+            if let Some(nanos) = units::duration(&digits) {
+                return Some((Expr::Duration(nanos, self.span(ts)), &ts[1..]));
             }
+            let int = match digits.get(..2) {
+                Some("0x") => i64::from_str_radix(&digits[2..], 16),
+                Some("0o") => i64::from_str_radix(&digits[2..], 8),
+                Some("0b") => i64::from_str_radix(&digits[2..], 2),
+                _ if !digits.contains('.') => digits.parse::<i64>(),
+                _ => match digits.parse::<f64>() {
+                    Ok(n) => return Some((Expr::Num(n, self.span(ts)), &ts[1..])),
+                    Err(_) => return None,
+                },
+            };
+            // The lexer has turned away integer literals too large for an int.
+            if let Ok(n) = int {
+                return Some((Expr::Int(n, self.span(ts)), &ts[1..]));
+            }
+        }
+
+        None
+    }
+
+    fn parse_str<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Str(s), ..] = ts {
+            let val = s.trim_matches('"');
+            let expr = Expr::Str(String::from(val), self.span(ts));
+            return Some((expr, &ts[1..]));
         }
+
+        None
     }
 
-    return None;
-}
+    fn parse_regex<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Regex(s), ..] = ts {
+            let pattern = s[1..].trim_matches('"');
+            let expr = Expr::Regex(String::from(pattern), self.span(ts));
+            return Some((expr, &ts[1..]));
+        }
+
+        None
+    }
 
-fn parse_num(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let [Token::Num(s), ..] = ts {
-        if let Ok(n) = s.parse::<f64>() {
-            return Some((Expr::Num(n), &ts[1..]));
+    fn parse_path<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Path(s), ..] = ts {
+            let expr = Expr::Path(String::from(s), self.span(ts));
+            return Some((expr, &ts[1..]));
         }
+
+        None
     }
 
-    return None;
-}
+    fn parse_param<'t>(&self, ts: &'t [Token]) -> Option<(Expr, &'t [Token])> {
+        if let [Token::Param(s), ..] = ts {
+            let expr = Expr::Param(String::from(s), self.span(ts));
+            return Some((expr, &ts[1..]));
+        }
 
-fn parse_str(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let [Token::Str(s), ..] = ts {
-        let val = s.trim_matches('"');
-        let expr = Expr::Str(String::from(val));
-        return Some((expr, &ts[1..]));
+        None
     }
+}
 
-    return None;
+/// Turns the bare words in a `where` condition into fields of `$it`.
+fn fields_of_it(expr: Expr) -> Expr {
+    let it = fields_of_it;
+    match expr {
+        Expr::Path(name, span) => Expr::Field(Box::new(Expr::Var(String::from("$it"), span)), name),
+        Expr::Binary(op, box l, box r) => Expr::Binary(op, Box::new(it(l)), Box::new(it(r))),
+        Expr::Unary(op, box e) => Expr::Unary(op, Box::new(it(e))),
+        Expr::Arr(exprs, span) => Expr::Arr(exprs.into_iter().map(it).collect(), span),
+        Expr::Field(box e, name) => Expr::Field(Box::new(it(e)), name),
+        Expr::Index(box e, box i, span) => Expr::Index(Box::new(it(e)), Box::new(it(i)), span),
+        Expr::Method(box e, name, args, span) => Expr::Method(
+            Box::new(it(e)),
+            name,
+            args.into_iter().map(it).collect(),
+            span,
+        ),
+        expr => expr,
+    }
 }
 
-fn parse_path(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let [Token::Path(s), ..] = ts {
-        let expr = Expr::Path(String::from(s));
-        return Some((expr, &ts[1..]));
+/// Whether the statement starting `ts` assigns, having `=` or an operator such as `+=` outside of
+/// any brackets.
+fn is_assignment(ts: &[Token]) -> bool {
+    let mut depth = 0;
+    for t in ts {
+        match t {
+            Token::LParen | Token::LSq | Token::LCurl => depth += 1,
+            Token::RParen | Token::RSq | Token::RCurl if depth == 0 => return false,
+            Token::RParen | Token::RSq | Token::RCurl => depth -= 1,
+            Token::NewLine | Token::Semi if depth == 0 => return false,
+            Token::Eq if depth == 0 => return true,
+            t if depth == 0 && compound_op(t).is_some() => return true,
+            _ => {}
+        }
     }
+    false
+}
 
-    return None;
+fn compound_op(t: &Token) -> Option<BinOp> {
+    match t {
+        Token::PlusEq => Some(BinOp::Add),
+        Token::MinusEq => Some(BinOp::Sub),
+        Token::AstEq => Some(BinOp::Mul),
+        _ => None,
+    }
 }
 
-fn parse_param(ts: &[Token]) -> Option<(Expr, &[Token])> {
-    if let [Token::Param(s), ..] = ts {
-        let expr = Expr::Param(String::from(s));
-        return Some((expr, &ts[1..]));
+/// Whether `expr` is part of a variable, reached through fields and indexes.
+fn is_part(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_, _) => true,
+        Expr::Field(box e, _) | Expr::Index(box e, _, _) => is_part(e),
+        _ => false,
     }
+}
 
-    return None;
+fn skip_newlines(ts: &[Token]) -> &[Token] {
+    match ts {
+        [Token::NewLine, ..] => skip_newlines(&ts[1..]),
+        _ => ts,
+    }
 }
@@ -0,0 +1,139 @@
+use std::fmt::{self, Display, Formatter};
+
+/// A lazy sequence of ints from `start` towards `end` in steps of `step`. Nothing is stored but
+/// the bounds, so `0..1_000_000_000` costs no more than `0..10`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+    /// Never zero. A range only heads towards `end` in the direction of its step, so `10..0` is
+    /// empty while `(10..0).step(-1)` counts down.
+    pub step: i64,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Range {
+        Range {
+            start,
+            end,
+            inclusive,
+            step: 1,
+        }
+    }
+
+    /// The same range going up (or down) by `step`, or `None` when `step` is zero.
+    pub fn with_step(self, step: i64) -> Option<Range> {
+        match step {
+            0 => None,
+            _ => Some(Range { step, ..self }),
+        }
+    }
+
+    /// The same elements in the opposite order, or `None` when the step can't be negated.
+    pub fn rev(self) -> Option<Range> {
+        let step = self.step.checked_neg()?;
+        Some(match self.last() {
+            Some(last) => Range {
+                start: last,
+                end: self.start,
+                inclusive: true,
+                step,
+            },
+            None => Range {
+                end: self.start,
+                step,
+                ..self
+            },
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        // Widened so that ranges spanning the whole of i64 don't overflow.
+        let (start, step) = (self.start as i128, self.step as i128);
+        let end = match self.inclusive {
+            true => self.end as i128 + step.signum(),
+            false => self.end as i128,
+        };
+        let distance = (end - start) * step.signum();
+        match distance > 0 {
+            true => ((distance + step.abs() - 1) / step.abs()) as u64,
+            false => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: u64) -> Option<i64> {
+        match index < self.len() {
+            true => Some((self.start as i128 + index as i128 * self.step as i128) as i64),
+            false => None,
+        }
+    }
+
+    pub fn last(&self) -> Option<i64> {
+        match self.len() {
+            0 => None,
+            n => self.get(n - 1),
+        }
+    }
+
+    pub fn contains(&self, n: i64) -> bool {
+        let offset = n as i128 - self.start as i128;
+        let step = self.step as i128;
+        offset % step == 0 && offset / step >= 0 && ((offset / step) as u64) < self.len()
+    }
+
+    /// The elements that are valid indices into something of length `len`, for slicing.
+    pub fn indices(&self, len: usize) -> Vec<usize> {
+        let len = len as i64;
+        let inside = |i: &i64| 0 <= *i && *i < len;
+        // Stop as soon as the range leaves the indices rather than walking the rest of it.
+        let outside = |i: &i64| match self.step > 0 {
+            true => *i < 0,
+            false => *i >= len,
+        };
+        self.iter()
+            .skip_while(outside)
+            .take_while(inside)
+            .map(|i| i as usize)
+            .collect()
+    }
+
+    pub fn iter(&self) -> RangeIter {
+        RangeIter {
+            range: *self,
+            index: 0,
+        }
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let dots = match self.inclusive {
+            true => "..=",
+            false => "..",
+        };
+        match self.step {
+            1 => write!(f, "{}{}{}", self.start, dots, self.end),
+            step => write!(f, "({}{}{}).step({})", self.start, dots, self.end, step),
+        }
+    }
+}
+
+pub struct RangeIter {
+    range: Range,
+    index: u64,
+}
+
+impl Iterator for RangeIter {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let n = self.range.get(self.index);
+        self.index += 1;
+        n
+    }
+}
@@ -0,0 +1,68 @@
+use crate::error::Error;
+use crate::interpreter::Value;
+
+/// Items passed from one pipeline stage to the next, made one at a time as the next stage asks
+/// for them.
+pub type Stream = Box<dyn Iterator<Item = Result<Value, Error>>>;
+
+/// What flows through `|` between builtins: either a stream of items or a single value.
+pub enum Piped {
+    Stream(Stream),
+    Value(Value),
+}
+
+impl Piped {
+    pub fn into_value(self) -> Result<Value, Error> {
+        match self {
+            Piped::Stream(items) => items.collect::<Result<Vec<Value>, Error>>().map(Value::Arr),
+            Piped::Value(val) => Ok(val),
+        }
+    }
+
+    /// The items of what was piped. Arrays and ranges give their elements, and text gives its
+    /// lines, so the output of an external command arrives as strings.
+    pub fn into_stream(self) -> Result<Stream, Error> {
+        match self {
+            Piped::Stream(items) => Ok(items),
+            Piped::Value(Value::Arr(vals)) => Ok(Box::new(vals.into_iter().map(Ok))),
+            Piped::Value(Value::Range(range)) => {
+                Ok(Box::new(range.iter().map(|n| Ok(Value::Int(n)))))
+            }
+            Piped::Value(val @ Value::Str(_)) | Piped::Value(val @ Value::Pipeline(_)) => {
+                let lines: Vec<Result<Value, Error>> = format!("{}", val)
+                    .lines()
+                    .map(|line| Ok(Value::Str(String::from(line))))
+                    .collect();
+                Ok(Box::new(lines.into_iter()))
+            }
+            Piped::Value(Value::Void) => Ok(Box::new(std::iter::empty())),
+            Piped::Value(val) => Err(Error::Type(
+                format!("Cannot pipe {} into a list builtin.", val.a_type_name()),
+                None,
+            )),
+        }
+    }
+
+    /// The bytes written to an external command's input: a line per item, or the raw output of a
+    /// command piped along unchanged.
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        let items: Stream = match self {
+            Piped::Value(Value::Pipeline(out)) => return Ok(out.stdout),
+            Piped::Value(Value::Void) => return Ok(Vec::new()),
+            Piped::Value(val @ Value::Arr(_)) | Piped::Value(val @ Value::Range(_)) => {
+                Piped::Value(val).into_stream()?
+            }
+            Piped::Stream(items) => items,
+            Piped::Value(val) => Box::new(std::iter::once(Ok(val))),
+        };
+        let mut text = String::new();
+        for item in items {
+            let val = item?;
+            text.push_str(&format!("{}", val));
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        Ok(text.into_bytes())
+    }
+}
@@ -0,0 +1,460 @@
+use crate::data;
+use crate::error::{Error, Span};
+use crate::interpreter::Value;
+use crate::stream::Piped;
+use regex::{Captures, Regex};
+
+/// String builtins.
+pub const COMMANDS: &[&str] = &[
+    "split",
+    "join",
+    "trim",
+    "trim-start",
+    "trim-end",
+    "replace",
+    "upper",
+    "lower",
+    "contains",
+    "starts-with",
+    "ends-with",
+    "substring",
+    "pad-start",
+    "pad-end",
+    "repeat",
+    "lines",
+    "length",
+    "format",
+    "captures",
+    "parse",
+];
+
+/// Strings built by `repeat` and padding are refused beyond this many bytes, rather than
+/// running out of memory.
+const MAX_LEN: usize = 1 << 30;
+
+/// Runs the string builtin `name`, where `spans` locates each of `args`.
+pub fn run(name: &str, input: Piped, args: &[Value], spans: &[Span]) -> Result<Piped, Error> {
+    // Compile a pattern given as a string once, rather than for every item.
+    let args: &[Value] = &match (name, args) {
+        ("captures", [pattern @ Value::Str(_), rest @ ..])
+        | ("parse", [pattern @ Value::Str(_), rest @ ..]) => {
+            let re = self::pattern(pattern)?;
+            [vec![Value::Regex(re)], rest.to_vec()].concat()
+        }
+        _ => args.to_vec(),
+    };
+    match (name, input) {
+        ("join", input) => join(input, args),
+        ("parse", input) => parse(input, args),
+        // With values to fill it, a format string needs nothing piped in.
+        ("format", Piped::Value(Value::Void)) => match args {
+            [Value::Str(template), vals @ ..] if !vals.is_empty() => {
+                format(template, &Value::Void, vals).map(|s| Piped::Value(Value::Str(s)))
+            }
+            _ => Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+        },
+        (_, Piped::Value(val)) if !matches!(val, Value::Arr(_) | Value::Range(_)) => {
+            apply(name, args, val, spans).map(Piped::Value)
+        }
+        (_, input) => match input.into_stream() {
+            Ok(items) => {
+                let (name, args, spans) = (String::from(name), args.to_vec(), spans.to_vec());
+                Ok(Piped::Stream(Box::new(items.map(move |item| match item {
+                    Ok(item) => apply(&name, &args, item, &spans),
+                    Err(err) => Err(err),
+                }))))
+            }
+            Err(err) => Err(err),
+        },
+    }
+}
+
+fn usage(name: &str) -> &'static str {
+    match name {
+        "split" => "split [separator]",
+        "join" => "join [separator]",
+        "trim" => "trim [characters]",
+        "trim-start" => "trim-start [characters]",
+        "trim-end" => "trim-end [characters]",
+        "replace" => "replace <text> <with>",
+        "upper" => "upper",
+        "lower" => "lower",
+        "contains" => "contains <text>",
+        "starts-with" => "starts-with <text>",
+        "ends-with" => "ends-with <text>",
+        "substring" => "substring <start> [end]",
+        "pad-start" => "pad-start <width> [character]",
+        "pad-end" => "pad-end <width> [character]",
+        "repeat" => "repeat <count>",
+        "lines" => "lines",
+        "length" => "length [--bytes]",
+        "captures" => "captures <pattern> [--all]",
+        "parse" => "parse <pattern>",
+        _ => "format <template> [values...]",
+    }
+}
+
+fn apply(name: &str, args: &[Value], item: Value, spans: &[Span]) -> Result<Value, Error> {
+    if let ("format", [Value::Str(template), vals @ ..]) = (name, args) {
+        return format(template, &item, vals).map(Value::Str);
+    }
+    let text = match item {
+        Value::Str(s) => s,
+        Value::Pipeline(_) => format!("{}", item),
+        _ => {
+            return Err(Error::Type(
+                format!("{} works on text, not {}.", name, item.a_type_name()),
+                None,
+            ))
+        }
+    };
+    let trim_set = |chars: &str| -> Vec<char> { chars.chars().collect() };
+    let val = match (name, args) {
+        ("split", []) => strs(text.split_whitespace()),
+        ("split", [Value::Str(sep)]) if sep.is_empty() => {
+            Value::Arr(text.chars().map(|c| Value::Str(c.to_string())).collect())
+        }
+        ("split", [Value::Str(sep)]) => strs(text.split(sep.as_str())),
+        ("split", [Value::Regex(re)]) => strs(re.split(&text)),
+        ("trim", []) => Value::Str(String::from(text.trim())),
+        ("trim", [Value::Str(chars)]) => {
+            Value::Str(String::from(text.trim_matches(&trim_set(chars)[..])))
+        }
+        ("trim-start", []) => Value::Str(String::from(text.trim_start())),
+        ("trim-start", [Value::Str(chars)]) => {
+            Value::Str(String::from(text.trim_start_matches(&trim_set(chars)[..])))
+        }
+        ("trim-end", []) => Value::Str(String::from(text.trim_end())),
+        ("trim-end", [Value::Str(chars)]) => {
+            Value::Str(String::from(text.trim_end_matches(&trim_set(chars)[..])))
+        }
+        ("replace", [Value::Str(from), Value::Str(to)]) if !from.is_empty() => {
+            Value::Str(text.replace(from.as_str(), to))
+        }
+        // The replacement may refer to groups as `$1` or `${name}`.
+        ("replace", [Value::Regex(re), Value::Str(to)]) => {
+            Value::Str(re.replace_all(&text, to.as_str()).into_owned())
+        }
+        ("upper", []) => Value::Str(text.to_uppercase()),
+        ("lower", []) => Value::Str(text.to_lowercase()),
+        ("contains", [Value::Str(s)]) => Value::Bool(text.contains(s.as_str())),
+        ("starts-with", [Value::Str(s)]) => Value::Bool(text.starts_with(s.as_str())),
+        ("ends-with", [Value::Str(s)]) => Value::Bool(text.ends_with(s.as_str())),
+        ("substring", [Value::Int(start)]) => Value::Str(substring(&text, *start, None)),
+        ("substring", [Value::Int(start), Value::Int(end)]) => {
+            Value::Str(substring(&text, *start, Some(*end)))
+        }
+        ("pad-start", [Value::Int(width), fill @ ..])
+        | ("pad-end", [Value::Int(width), fill @ ..]) => {
+            let fill = match fill {
+                [] => ' ',
+                [Value::Str(s)] if s.chars().count() == 1 => s.chars().next().unwrap(),
+                _ => return Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+            };
+            let align = match name {
+                "pad-start" => '>',
+                _ => '<',
+            };
+            let width = (*width).max(0) as usize;
+            let missing = width.saturating_sub(text.chars().count());
+            let len = missing.checked_mul(fill.len_utf8());
+            match len.and_then(|len| len.checked_add(text.len())) {
+                Some(len) if len <= MAX_LEN => {}
+                _ => return Err(too_long(name, spans)),
+            }
+            Value::Str(pad(&text, width, fill, align))
+        }
+        ("repeat", [Value::Int(n)]) if *n >= 0 => match text.len().checked_mul(*n as usize) {
+            Some(len) if len <= MAX_LEN => Value::Str(text.repeat(*n as usize)),
+            _ => return Err(too_long(name, spans)),
+        },
+        ("lines", []) => strs(text.lines()),
+        ("length", []) => Value::Int(text.chars().count() as i64),
+        ("length", [Value::Str(flag)]) if flag == "-b" || flag == "--bytes" => {
+            Value::Int(text.len() as i64)
+        }
+        ("captures", [Value::Regex(re)]) => match re.captures(&text) {
+            Some(caps) => groups(re, &caps),
+            None => Value::Void,
+        },
+        ("captures", [Value::Regex(re), Value::Str(flag)]) if flag == "-a" || flag == "--all" => {
+            Value::Arr(
+                re.captures_iter(&text)
+                    .map(|caps| groups(re, &caps))
+                    .collect(),
+            )
+        }
+        _ => return Err(Error::Arg(format!("usage: {}", usage(name)), None)),
+    };
+    Ok(val)
+}
+
+/// The regex in `val`, which may also be a string holding one.
+pub fn pattern(val: &Value) -> Result<Regex, Error> {
+    match val {
+        Value::Regex(re) => Ok(re.clone()),
+        Value::Str(s) => compile(s),
+        _ => Err(Error::Type(
+            format!("Cannot use {} as a pattern.", val.a_type_name()),
+            None,
+        )),
+    }
+}
+
+pub fn compile(pattern: &str) -> Result<Regex, Error> {
+    match Regex::new(pattern) {
+        Ok(re) => Ok(re),
+        // Only the last line of the message says what is wrong; the rest draws the pattern.
+        Err(err) => Err(Error::Arg(
+            format!(
+                "Invalid regex {}: {}.",
+                pattern,
+                format!("{}", err)
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ")
+            ),
+            None,
+        )),
+    }
+}
+
+/// The groups of one match: a record when the pattern names any of them, and otherwise an array.
+fn groups(re: &Regex, caps: &Captures) -> Value {
+    let vals = caps.iter().skip(1).map(|m| match m {
+        Some(m) => Value::Str(String::from(m.as_str())),
+        None => Value::Void,
+    });
+    match re.capture_names().any(|name| name.is_some()) {
+        true => Value::Record(group_names(re).into_iter().zip(vals).collect()),
+        false => Value::Arr(vals.collect()),
+    }
+}
+
+/// The name of each group after the whole match, with `column1`, `column2` and so on for those
+/// without one.
+fn group_names(re: &Regex) -> Vec<String> {
+    re.capture_names()
+        .enumerate()
+        .skip(1)
+        .map(|(i, name)| match name {
+            Some(name) => String::from(name),
+            None => format!("column{}", i),
+        })
+        .collect()
+}
+
+/// A record for each line piped in that matches the pattern, with a field per group.
+fn parse(input: Piped, args: &[Value]) -> Result<Piped, Error> {
+    let re = match args {
+        [Value::Regex(re)] => re.clone(),
+        _ => return Err(Error::Arg(format!("usage: {}", usage("parse")), None)),
+    };
+    let names = group_names(&re);
+    let items = input.into_stream()?;
+    Ok(Piped::Stream(Box::new(items.filter_map(move |item| {
+        let line = match item {
+            Ok(Value::Str(line)) => line,
+            Ok(val) => {
+                return Some(Err(Error::Type(
+                    format!("parse works on text, not {}.", val.a_type_name()),
+                    None,
+                )))
+            }
+            Err(err) => return Some(Err(err)),
+        };
+        let caps = re.captures(&line)?;
+        let vals = caps.iter().skip(1).map(|m| match m {
+            Some(m) => {
+                data::number(m.as_str()).unwrap_or_else(|| Value::Str(String::from(m.as_str())))
+            }
+            None => Value::Void,
+        });
+        Some(Ok(Value::Record(names.iter().cloned().zip(vals).collect())))
+    }))))
+}
+
+fn strs<'a>(parts: impl Iterator<Item = &'a str>) -> Value {
+    Value::Arr(parts.map(|s| Value::Str(String::from(s))).collect())
+}
+
+fn join(input: Piped, args: &[Value]) -> Result<Piped, Error> {
+    let sep = match args {
+        [] => "",
+        [Value::Str(sep)] => sep,
+        _ => return Err(Error::Arg(format!("usage: {}", usage("join")), None)),
+    };
+    let items = input.into_stream()?;
+    let mut parts = Vec::new();
+    for item in items {
+        match item {
+            Ok(Value::Void) => parts.push(String::new()),
+            Ok(item) => parts.push(format!("{}", item)),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Piped::Value(Value::Str(parts.join(sep))))
+}
+
+/// The characters of `text` from `start` up to `end`, or to the end of the text. Negative indices
+/// count back from the end, and indices past either end are clamped to it.
+fn substring(text: &str, start: i64, end: Option<i64>) -> String {
+    let len = text.chars().count() as i64;
+    let index = |i: i64| match i < 0 {
+        true => (len + i).max(0),
+        false => i.min(len),
+    };
+    let (start, end) = (index(start), index(end.unwrap_or(len)));
+    text.chars()
+        .skip(start as usize)
+        .take((end - start).max(0) as usize)
+        .collect()
+}
+
+/// The error for a string longer than `MAX_LEN`, located at the count or width that asked for it.
+fn too_long(name: &str, spans: &[Span]) -> Error {
+    Error::Arith(
+        format!(
+            "{} would make a string longer than {} bytes.",
+            name, MAX_LEN
+        ),
+        spans.first().copied(),
+    )
+}
+
+/// `text` filled out with `fill` to `width` characters, lined up by `align`: `<` to the left, `>`
+/// to the right or `^` in the middle.
+fn pad(text: &str, width: usize, fill: char, align: char) -> String {
+    let missing = width.saturating_sub(text.chars().count());
+    let (before, after) = match align {
+        '>' => (missing, 0),
+        '^' => (missing / 2, missing - missing / 2),
+        _ => (0, missing),
+    };
+    let fill = |n: usize| fill.to_string().repeat(n);
+    format!("{}{}{}", fill(before), text, fill(after))
+}
+
+/// Fills in the placeholders of `template`. `{}` takes the next of `vals`, `{0}` the first, and
+/// `{name}` a field of `item`. Without `vals`, `{}` stands for `item` itself. After a colon, a
+/// placeholder can give a fill character and alignment, a width and a precision, as in
+/// `{:>8.2}`, `{:03}` or `{name:-^20}`.
+fn format(template: &str, item: &Value, vals: &[Value]) -> Result<String, Error> {
+    let error = |msg: &str| {
+        Error::Arg(
+            format!("Invalid format string {}: {}.", template, msg),
+            None,
+        )
+    };
+    let positional = match vals {
+        [] => std::slice::from_ref(item),
+        vals => vals,
+    };
+    let mut out = String::new();
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let (brace, after) = (&rest[i..i + 1], &rest[i + 1..]);
+        // Doubled braces stand for themselves.
+        if after.starts_with(brace) {
+            out.push_str(brace);
+            rest = &after[1..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(error("unmatched }"));
+        }
+        let close = match after.find('}') {
+            Some(close) => close,
+            None => return Err(error("unclosed {")),
+        };
+        let (key, spec) = match after[..close].split_once(':') {
+            Some((key, spec)) => (key, spec),
+            None => (&after[..close], ""),
+        };
+        let val = match key {
+            "" => {
+                next += 1;
+                positional.get(next - 1)
+            }
+            key if key.chars().all(|c| c.is_ascii_digit()) => match key.parse::<usize>() {
+                Ok(i) => positional.get(i),
+                Err(_) => None,
+            },
+            key => item.field(key),
+        };
+        let val = match val {
+            Some(val) => val,
+            None => return Err(error(&format!("there is no value for {{{}}}", key))),
+        };
+        match format_spec(val, spec) {
+            Some(text) => out.push_str(&text),
+            None => return Err(error(&format!("cannot read {}", spec))),
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn format_spec(val: &Value, spec: &str) -> Option<String> {
+    let chars: Vec<char> = spec.chars().collect();
+    let is_align = |c: Option<&char>| matches!(c, Some('<') | Some('>') | Some('^'));
+    let (fill, align, mut i) = match (is_align(chars.get(1)), is_align(chars.first())) {
+        (true, _) => (chars[0], Some(chars[1]), 2),
+        (false, true) => (' ', Some(chars[0]), 1),
+        (false, false) => (' ', None, 0),
+    };
+    let number = |i: &mut usize| -> Option<usize> {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        match *i > start {
+            true => Some(
+                chars[start..*i]
+                    .iter()
+                    .collect::<String>()
+                    .parse()
+                    .unwrap_or(usize::MAX),
+            ),
+            false => None,
+        }
+    };
+    // A leading zero pads numbers with zeros after their sign, as in `{:05}`.
+    let zeros = align.is_none() && chars.get(i) == Some(&'0');
+    let width = number(&mut i);
+    let precision = match chars.get(i) {
+        Some('.') => {
+            i += 1;
+            Some(number(&mut i)?)
+        }
+        _ => None,
+    };
+    // A width or precision too large to fill makes the placeholder unreadable.
+    if i < chars.len() || width.max(precision).is_some_and(|n| n > MAX_LEN) {
+        return None;
+    }
+
+    let numeric = matches!(val, Value::Int(_) | Value::Num(_));
+    let text = match (val, precision) {
+        (Value::Int(n), Some(p)) => format!("{:.*}", p, *n as f64),
+        (Value::Num(n), Some(p)) => format!("{:.*}", p, n),
+        // Null fills its place with nothing.
+        (Value::Void, _) => String::new(),
+        (val, Some(p)) => format!("{}", val).chars().take(p).collect(),
+        (val, None) => format!("{}", val),
+    };
+    let width = width.unwrap_or(0);
+    if zeros && numeric {
+        return Some(match text.strip_prefix('-') {
+            Some(digits) => format!("-{}", pad(digits, width.saturating_sub(1), '0', '>')),
+            None => pad(&text, width, '0', '>'),
+        });
+    }
+    // Numbers line up on the right unless told otherwise, and everything else on the left.
+    let align = align.unwrap_or(if numeric { '>' } else { '<' });
+    Some(pad(&text, width, fill, align))
+}
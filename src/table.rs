@@ -0,0 +1,175 @@
+use crate::interpreter::{Options, Value};
+use crate::json;
+
+/// Columns are narrowed down to this many characters before any are dropped to fit the width.
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// How a statement's value is shown.
+pub fn output(val: &Value, opts: &Options) -> String {
+    let records = match val {
+        Value::Arr(vals) if !vals.is_empty() && vals.iter().all(is_record) => vals,
+        // Null on its own prints nothing, where in an array or record it shows as `null`.
+        Value::Void => return String::new(),
+        _ => return format!("{}", val),
+    };
+    match opts.table_width {
+        Some(width) => render(records, width),
+        None => records
+            .iter()
+            .map(|record| match json::to_string(record, false) {
+                Ok(line) => line,
+                Err(_) => format!("{}", record),
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+fn is_record(val: &Value) -> bool {
+    matches!(val, Value::Record(_))
+}
+
+/// Draws `records` as a table no wider than `width`, with a column per field in the order the
+/// fields first appear and the index of each record down the left.
+pub fn render(records: &[Value], width: usize) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for record in records {
+        if let Value::Record(fields) = record {
+            for (k, _) in fields {
+                if !names.contains(&k.as_str()) {
+                    names.push(k);
+                }
+            }
+        }
+    }
+    let mut columns: Vec<Column> = names
+        .iter()
+        .map(|name| {
+            let vals: Vec<Option<&Value>> = records.iter().map(|r| r.field(name)).collect();
+            Column {
+                name: String::from(*name),
+                cells: vals
+                    .iter()
+                    .map(|v| v.map_or_else(String::new, cell))
+                    .collect(),
+                numeric: vals.iter().all(|v| {
+                    matches!(
+                        v,
+                        None | Some(Value::Int(_))
+                            | Some(Value::Num(_))
+                            | Some(Value::Size(_))
+                            | Some(Value::Duration(_))
+                    )
+                }),
+                width: 0,
+            }
+        })
+        .collect();
+    for column in columns.iter_mut() {
+        column.width = column
+            .cells
+            .iter()
+            .chain(std::iter::once(&column.name))
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0);
+    }
+
+    let index_width = format!("{}", records.len() - 1).len();
+    let total = |columns: &[Column]| -> usize {
+        index_width + 1 + columns.iter().map(|c| c.width + 3).sum::<usize>()
+    };
+    // Narrow the widest column a character at a time, then drop columns from the right.
+    while total(&columns) > width {
+        match columns.iter_mut().max_by_key(|c| c.width) {
+            Some(widest) if widest.width > MIN_COLUMN_WIDTH => widest.width -= 1,
+            _ => break,
+        }
+    }
+    let mut dropped = false;
+    while total(&columns) > width && columns.len() > 1 {
+        columns.pop();
+        dropped = true;
+    }
+    if dropped {
+        // Make room for a column marking where the rest were cut off.
+        if total(&columns) + 4 > width && columns.len() > 1 {
+            columns.pop();
+        }
+        columns.push(Column {
+            name: String::from("…"),
+            cells: vec![String::from("…"); records.len()],
+            numeric: false,
+            width: 1,
+        });
+    }
+
+    let mut lines = Vec::new();
+    let mut header = format!(" {:>w$}", "#", w = index_width);
+    let mut rule = "─".repeat(index_width + 2);
+    for column in columns.iter() {
+        header.push_str(" │ ");
+        header.push_str(&column.pad(&column.name));
+        rule.push('┼');
+        rule.push_str(&"─".repeat(column.width + 2));
+    }
+    lines.push(header);
+    lines.push(rule);
+    for i in 0..records.len() {
+        let mut line = format!(" {:>w$}", i, w = index_width);
+        for column in columns.iter() {
+            line.push_str(" │ ");
+            line.push_str(&column.pad(&column.cells[i]));
+        }
+        lines.push(line);
+    }
+    lines
+        .iter()
+        .map(|line| line.trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+struct Column {
+    name: String,
+    cells: Vec<String>,
+    numeric: bool,
+    width: usize,
+}
+
+impl Column {
+    fn pad(&self, s: &str) -> String {
+        let len = s.chars().count();
+        if len > self.width {
+            let cut: String = s.chars().take(self.width - 1).collect();
+            return cut + "…";
+        }
+        let fill = " ".repeat(self.width - len);
+        match self.numeric {
+            true => fill + s,
+            false => String::from(s) + &fill,
+        }
+    }
+}
+
+/// A value as it fits in one cell: nested arrays and records are summed up by their size, and
+/// only the first line of text is shown.
+fn cell(val: &Value) -> String {
+    let count = |n: usize, what: &str| match n {
+        1 => format!("1 {}", what),
+        n => format!("{} {}s", n, what),
+    };
+    match val {
+        Value::Arr(vals) => format!("[{}]", count(vals.len(), "item")),
+        Value::Record(fields) => format!("{{{}}}", count(fields.len(), "field")),
+        _ => {
+            let text = format!("{}", val);
+            let mut lines = text.lines();
+            match (lines.next(), lines.next()) {
+                (Some(first), Some(_)) => format!("{}…", first),
+                (Some(first), None) => String::from(first),
+                (None, _) => String::new(),
+            }
+        }
+    }
+}
@@ -72,6 +72,8 @@ mod tokenize {
                 r"./dir.something",
                 r"the_file.txt",
                 r"the-file.txt",
+                r".\this\is\a\path.txt",
+                r"dir\file.txt",
             ];
             for path in test_paths.iter() {
                 test_path(path);
@@ -79,9 +81,281 @@ mod tokenize {
         }
     }
 
+    #[test]
+    fn test_path_argument() {
+        let ts = tokens::tokenize("cat file.txt { $a.b }").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Path(String::from("cat")),
+                tokens::Token::Path(String::from("file.txt")),
+                tokens::Token::LCurl,
+                tokens::Token::Var(String::from("$a.b")),
+                tokens::Token::RCurl,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        let ts = tokens::tokenize("try catch finally throw trying").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Try,
+                tokens::Token::Catch,
+                tokens::Token::Finally,
+                tokens::Token::Throw,
+                tokens::Token::Path(String::from("trying")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_access() {
+        let s = String::from("$e.message");
+        let ts = tokens::tokenize(&s).unwrap();
+        assert_eq!(ts, vec![tokens::Token::Var(s)]);
+    }
+
+    #[test]
+    fn test_ranges() {
+        use tokens::Token;
+        let ts = tokens::tokenize("1..10 0..=$n ../bin/run.sh").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                Token::Num(String::from("1")),
+                Token::DotDot,
+                Token::Num(String::from("10")),
+                Token::Num(String::from("0")),
+                Token::DotDotEq,
+                Token::Var(String::from("$n")),
+                Token::Path(String::from("../bin/run.sh")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_method_dot() {
+        use tokens::Token;
+        let ts = tokens::tokenize("(1..3).rev() .gitignore").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                Token::LParen,
+                Token::Num(String::from("1")),
+                Token::DotDot,
+                Token::Num(String::from("3")),
+                Token::RParen,
+                Token::Dot,
+                Token::Path(String::from("rev")),
+                Token::LParen,
+                Token::RParen,
+                Token::Path(String::from(".gitignore")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        for s in [
+            "0xdead_BEEF",
+            "0o755",
+            "0b1111_0000",
+            "1_000_000",
+            "3.141_592",
+        ] {
+            let ts = tokens::tokenize(s).unwrap();
+            assert_eq!(ts, vec![tokens::Token::Num(String::from(s))]);
+        }
+    }
+
+    #[test]
+    fn test_int_division() {
+        let ts = tokens::tokenize("7 // 2").unwrap();
+        assert_eq!(ts[1], tokens::Token::DoubleSlash);
+    }
+
+    #[test]
+    fn test_operators() {
+        let ts = tokens::tokenize("** == != <= >= < > && || ! bit-and bit-shl").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::AstAst,
+                tokens::Token::EqEq,
+                tokens::Token::BangEq,
+                tokens::Token::LtEq,
+                tokens::Token::GtEq,
+                tokens::Token::Lt,
+                tokens::Token::Gt,
+                tokens::Token::AmpAmp,
+                tokens::Token::PipePipe,
+                tokens::Token::Bang,
+                tokens::Token::BitAnd,
+                tokens::Token::Shl,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_operators() {
+        let ts = tokens::tokenize("$s =~ r\"^\\d+$\" != $t !~ \"x\"").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Var(String::from("$s")),
+                tokens::Token::EqTilde,
+                tokens::Token::Regex(String::from("r\"^\\d+$\"")),
+                tokens::Token::BangEq,
+                tokens::Token::Var(String::from("$t")),
+                tokens::Token::BangTilde,
+                tokens::Token::Str(String::from("\"x\"")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_float() {
+        let ts = tokens::tokenize("[-1.5, 2-0.5]").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::LSq,
+                tokens::Token::Minus,
+                tokens::Token::Num(String::from("1.5")),
+                tokens::Token::Comma,
+                tokens::Token::Num(String::from("2")),
+                tokens::Token::Minus,
+                tokens::Token::Num(String::from("0.5")),
+                tokens::Token::RSq,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unit_literals() {
+        let ts = tokens::tokenize("[10kb, 2MiB, 1h30m, 500ms, 0b, 0x1f]").unwrap();
+        let nums: Vec<tokens::Token> = ts
+            .into_iter()
+            .filter(|t| {
+                !matches!(
+                    t,
+                    tokens::Token::LSq | tokens::Token::Comma | tokens::Token::RSq
+                )
+            })
+            .collect();
+        assert_eq!(
+            nums,
+            ["10kb", "2MiB", "1h30m", "500ms", "0b", "0x1f"]
+                .iter()
+                .map(|s| tokens::Token::Num(String::from(*s)))
+                .collect::<Vec<tokens::Token>>()
+        );
+    }
+
+    #[test]
+    fn test_literal_keywords() {
+        let ts = tokens::tokenize("null true false ?? nullable").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Null,
+                tokens::Token::True,
+                tokens::Token::False,
+                tokens::Token::QuestionQuestion,
+                tokens::Token::Path(String::from("nullable")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_program_tokens() {
+        let ts = tokens::tokenize("^ls -la").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Path(String::from("^ls")),
+                tokens::Token::Param(String::from("-la")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment_tokens() {
+        let ts = tokens::tokenize("$x += 1 -= *= -1").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Var(String::from("$x")),
+                tokens::Token::PlusEq,
+                tokens::Token::Num(String::from("1")),
+                tokens::Token::MinusEq,
+                tokens::Token::AstEq,
+                tokens::Token::Minus,
+                tokens::Token::Num(String::from("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binding_keywords() {
+        let ts = tokens::tokenize("let mut const letter").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Let,
+                tokens::Token::Mut,
+                tokens::Token::Const,
+                tokens::Token::Path(String::from("letter")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_keywords() {
+        let ts = tokens::tokenize("import \"lib/util.oy\" as $u\nexport $x = 1\n").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Import,
+                tokens::Token::Str(String::from("\"lib/util.oy\"")),
+                tokens::Token::As,
+                tokens::Token::Var(String::from("$u")),
+                tokens::Token::NewLine,
+                tokens::Token::Export,
+                tokens::Token::Var(String::from("$x")),
+                tokens::Token::Eq,
+                tokens::Token::Num(String::from("1")),
+                tokens::Token::NewLine,
+            ]
+        );
+        let ts = tokens::tokenize("use util; source ./env.oy").unwrap();
+        assert_eq!(ts[0], tokens::Token::Use);
+        assert_eq!(ts[3], tokens::Token::Source);
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let ts = tokens::tokenize("match $x { _ => matches }").unwrap();
+        assert_eq!(
+            ts,
+            vec![
+                tokens::Token::Match,
+                tokens::Token::Var(String::from("$x")),
+                tokens::Token::LCurl,
+                tokens::Token::Underscore,
+                tokens::Token::FatArrow,
+                tokens::Token::Path(String::from("matches")),
+                tokens::Token::RCurl,
+            ]
+        );
+    }
+
     #[test]
     fn test_param() {
-        let params = vec![
+        let params = [
             String::from("--parameter"),
             String::from("-r"),
             String::from("--param-eter"),
@@ -97,17 +371,360 @@ mod tokenize {
 
 mod parse {
 
-    use crate::parser::{self, Expr, Prog};
+    use crate::parser::{self, BinOp, Decl, Expr, Load, Pattern, UnOp};
     use crate::tokens::Token;
     use parser::Stmt;
 
+    #[test]
+    fn test_plus_word_argument() {
+        let input = "date +%Y-%m-%d 1\n";
+        let (ts, spans) = crate::tokens::tokenize_with_spans(input).unwrap();
+        match parser::parse_with_spans(&ts, &spans).as_deref() {
+            Ok([Stmt::Expr(Expr::Cmd(_, args, _))]) => match args.as_slice() {
+                [Expr::Path(word, _), Expr::Int(1, _)] => assert_eq!(word, "+%Y-%m-%d"),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_single_integer() {
         let ts = [Token::Num(String::from("1")), Token::NewLine];
         let res = parser::parse(&ts);
 
-        match res {
-            Ok(Prog::Stmt(box Stmt::Expr(Expr::Num(n)), box Prog::End)) => assert_eq!(n, 1.0),
+        match res.as_deref() {
+            Ok([Stmt::Expr(Expr::Int(n, _))]) => assert_eq!(*n, 1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_int_literals() {
+        let cases = vec![
+            ("1_000", 1000),
+            ("0xff", 255),
+            ("0o17", 15),
+            ("0b1010_1010", 170),
+        ];
+        for (s, expected) in cases {
+            let ts = [Token::Num(String::from(s)), Token::NewLine];
+            match parser::parse(&ts).as_deref() {
+                Ok([Stmt::Expr(Expr::Int(n, _))]) => assert_eq!(*n, expected),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_literal() {
+        let ts = [Token::Num(String::from("1_000.5")), Token::NewLine];
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Num(n, _))]) => assert_eq!(*n, 1000.5),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_negative_args_and_flags() {
+        let ts = vec![
+            Token::Path(String::from("head")),
+            Token::Minus,
+            Token::Num(String::from("1")),
+            Token::Param(String::from("-x")),
+            Token::NewLine,
+        ];
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Cmd(_, args, _))]) => match args.as_slice() {
+                [Expr::Int(-1, _), Expr::Param(p, _)] => assert_eq!(p, "-x"),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        // 1 + 2 * 3 == 7 && !0 parses as ((1 + (2 * 3)) == 7) && (!0)
+        let ts = vec![
+            Token::Num(String::from("1")),
+            Token::Plus,
+            Token::Num(String::from("2")),
+            Token::Ast,
+            Token::Num(String::from("3")),
+            Token::EqEq,
+            Token::Num(String::from("7")),
+            Token::AmpAmp,
+            Token::Bang,
+            Token::Num(String::from("0")),
+            Token::NewLine,
+        ];
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Binary(BinOp::And, box l, box r)), ..]) => {
+                assert!(matches!(r, Expr::Unary(UnOp::Not, _)));
+                match l {
+                    Expr::Binary(BinOp::Eq, box Expr::Binary(BinOp::Add, _, box mul), _) => {
+                        assert!(matches!(mul, Expr::Binary(BinOp::Mul, _, _)))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_lambda() {
+        let ts = crate::tokens::tokenize("{ |$a, $b| $a + $b }\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Lambda(params, body, _))]) => {
+                assert_eq!(params, &vec!["$a", "$b"]);
+                match body.as_slice() {
+                    [Stmt::Expr(Expr::Binary(op, _, _))] => {
+                        assert_eq!(*op, BinOp::Add)
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_value_pipe() {
+        let ts = crate::tokens::tokenize("[1, 2] | map { |$x| $x } | count\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Pipe(stages))]) => match stages.as_slice() {
+                [Expr::Arr(_, _), Expr::Cmd(_, map_args, _), Expr::Cmd(_, count_args, _)] => {
+                    assert!(matches!(map_args.as_slice(), [Expr::Lambda(_, _, _)]));
+                    assert!(count_args.is_empty());
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_match_precedence() {
+        let ts = crate::tokens::tokenize("$a =~ r\"x\" && $b !~ \"y\"\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Binary(BinOp::And, box l, box r)), ..]) => {
+                match l {
+                    Expr::Binary(BinOp::Match, _, box Expr::Regex(pattern, _)) => {
+                        assert_eq!(pattern, "x")
+                    }
+                    _ => unreachable!(),
+                }
+                match r {
+                    Expr::Binary(BinOp::NotMatch, _, box Expr::Str(s, _)) => assert_eq!(s, "y"),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_unit_literals() {
+        for (input, bytes) in [("1.5kb\n", 1500), ("2KiB\n", 2048), ("0b\n", 0)] {
+            let ts = crate::tokens::tokenize(input).unwrap();
+            match parser::parse(&ts).as_deref() {
+                Ok([Stmt::Expr(Expr::Size(n, _))]) => assert_eq!(*n, bytes),
+                _ => unreachable!(),
+            }
+        }
+        let ts = crate::tokens::tokenize("1h30m\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Duration(n, _))]) => assert_eq!(*n, 5_400_000_000_000),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_coalesce_precedence() {
+        let ts = crate::tokens::tokenize("$a ?? $b || true\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Binary(BinOp::Coalesce, box l, box r))]) => {
+                assert!(matches!(l, Expr::Var(..)));
+                match r {
+                    Expr::Binary(BinOp::Or, _, box Expr::Bool(true, _)) => {}
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_assignment_targets() {
+        let ts = crate::tokens::tokenize("$n += 1\n$cfg.port = 80\n$a, $b = $b, $a\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([first, second, third]) => {
+                match first {
+                    Stmt::Assign(name, expr, _) => {
+                        assert_eq!(name, "$n");
+                        match &**expr {
+                            Expr::Binary(BinOp::Add, l, _) => {
+                                assert!(matches!(&**l, Expr::Var(var, _) if var == "$n"))
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+                match second {
+                    Stmt::Update(box Expr::Field(_, field), None, box Expr::Int(80, _)) => {
+                        assert_eq!(field, "port")
+                    }
+                    _ => unreachable!(),
+                }
+                match third {
+                    Stmt::Destructure(Pattern::Arr(targets, None), box Expr::Arr(values, _), _) => {
+                        assert_eq!(targets.len(), 2);
+                        assert_eq!(values.len(), 2);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_let_and_const() {
+        let ts = crate::tokens::tokenize("let mut [$a, $b] = $pair\nconst $max = 10\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([first, second]) => {
+                assert!(matches!(
+                    first,
+                    Stmt::Let(Decl::Mut, Pattern::Arr(_, None), _, _)
+                ));
+                match second {
+                    Stmt::Let(Decl::Const, Pattern::Bind(name), _, _) => assert_eq!(name, "$max"),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_loads_and_exports() {
+        let ts = crate::tokens::tokenize(
+            "import \"lib/util.oy\"\nimport lib/util.oy as $u\nuse \"util\"\nsource \"env.oy\"\nexport const $max = 10\n",
+        )
+        .unwrap();
+        let stmts = parser::parse(&ts).unwrap();
+        let loads: Vec<(Load, String)> = stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Load(load, path, _) => Some((load.clone(), path.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            loads,
+            vec![
+                (Load::Import(None), String::from("lib/util.oy")),
+                (
+                    Load::Import(Some(String::from("$u"))),
+                    String::from("lib/util.oy")
+                ),
+                (Load::Use, String::from("util")),
+                (Load::Source, String::from("env.oy")),
+            ]
+        );
+        match &stmts[4] {
+            Stmt::Export(stmt) => assert!(matches!(
+                &**stmt,
+                Stmt::Let(Decl::Const, Pattern::Bind(_), _, _)
+            )),
+            _ => unreachable!(),
+        }
+        for input in [
+            "import\n",
+            "import \"a\" as\n",
+            "export $x\n",
+            "export $a.b = 1\n",
+        ] {
+            let ts = crate::tokens::tokenize(input).unwrap();
+            assert!(parser::parse(&ts).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_keywords_as_arguments() {
+        let ts = crate::tokens::tokenize("echo in source\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Cmd(_, args, _))]) => match args.as_slice() {
+                [Expr::Path(a, _), Expr::Path(b, _)] => {
+                    assert_eq!((a.as_str(), b.as_str()), ("in", "source"))
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_match_arms() {
+        let ts = crate::tokens::tokenize(
+            "match $x {\n  1..=9 => 1\n  [$a, ..$rest] if $a => 2, {name} => 3\n  int => 4\n}\n",
+        )
+        .unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Match(_, arms, _))]) => match arms.as_slice() {
+                [(range, None, _), (arr, Some(_), _), (record, None, _), (ty, None, _)] => {
+                    assert!(matches!(range, Pattern::Range(Some(_), Some(_), true)));
+                    match arr {
+                        Pattern::Arr(before, Some((Some(rest), after))) => {
+                            assert!(matches!(before.as_slice(), [Pattern::Bind(_)]));
+                            assert_eq!(rest, "$rest");
+                            assert!(after.is_empty());
+                        }
+                        _ => unreachable!(),
+                    }
+                    match record {
+                        Pattern::Record(fields) => match fields.as_slice() {
+                            [(name, Pattern::Bind(var))] => {
+                                assert_eq!(name, "name");
+                                assert_eq!(var, "$name");
+                            }
+                            _ => unreachable!(),
+                        },
+                        _ => unreachable!(),
+                    }
+                    assert!(matches!(ty, Pattern::Type(name) if name == "int"));
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_where_condition() {
+        let ts = crate::tokens::tokenize("ls | where size > 10\n").unwrap();
+        match parser::parse(&ts).as_deref() {
+            Ok([Stmt::Expr(Expr::Pipe(stages))]) => match stages.as_slice() {
+                [_, Expr::Cmd(_, args, _)] => match args.as_slice() {
+                    [Expr::Lambda(params, body, _)] => {
+                        assert_eq!(params, &vec!["$it"]);
+                        match body.as_slice() {
+                            [Stmt::Expr(Expr::Binary(BinOp::Gt, l, _))] => match &**l {
+                                Expr::Field(box Expr::Var(it, _), field) => {
+                                    assert_eq!((it.as_str(), field.as_str()), ("$it", "size"))
+                                }
+                                _ => unreachable!(),
+                            },
+                            _ => unreachable!(),
+                        }
+                    }
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            },
             _ => unreachable!(),
         }
     }
@@ -149,16 +766,16 @@ mod parse {
         let mut count_path = 0;
         let mut count_num = 0;
 
-        match res {
-            Ok(Prog::Stmt(box Stmt::Expr(Expr::Cmd(box Expr::Path(s), v)), box Prog::End)) => {
+        match res.as_deref() {
+            Ok([Stmt::Expr(Expr::Cmd(box Expr::Path(s, _), v, _))]) => {
                 assert_eq!(v.len(), ts.len() - 2);
-                assert_eq!(s, String::from(".\\this\\is\\a\\path.txt"));
+                assert_eq!(*s, String::from(".\\this\\is\\a\\path.txt"));
                 for ex in v.iter() {
                     match ex {
-                        Expr::Str(_) => count_str += 1,
-                        Expr::Param(_) => count_param += 1,
-                        Expr::Path(_) => count_path += 1,
-                        Expr::Num(_) => count_num += 1,
+                        Expr::Str(_, _) => count_str += 1,
+                        Expr::Param(_, _) => count_param += 1,
+                        Expr::Path(_, _) => count_path += 1,
+                        Expr::Num(_, _) => count_num += 1,
                         _ => panic!("There was something unexpected in the vector."),
                     }
                 }
@@ -174,13 +791,14 @@ mod parse {
 
 mod interpret {
     use crate::{
+        error::Span,
         interpreter,
-        parser::{Expr, Prog, Stmt},
+        parser::{Expr, Stmt},
     };
 
     #[test]
     fn test_single_integer() {
-        let prog = Prog::Stmt(box Stmt::Expr(Expr::Num(1.0)), box Prog::End);
+        let prog = vec![Stmt::Expr(Expr::Num(1.0, Span::default()))];
         match interpreter::interpret(&prog) {
             Ok(out) => assert_eq!(out, "1\n"),
             _ => unreachable!(),
@@ -224,6 +842,91 @@ mod eval {
         assert_eval("1 / 4\n", "0.25\n")
     }
 
+    #[test]
+    fn test_int_and_float() {
+        assert_eval("7 / 2\n", "3.5\n");
+        assert_eval("7 // 2\n(0 - 7) // 2\n", "3\n-4\n");
+        assert_eval("7 // (0 - 2)\n(0 - 7) // (0 - 2)\n", "-4\n3\n");
+        assert_eval("7.5 // 2\n", "3\n");
+        assert_eval("-7 % 2\n7 % -2\n-7 % -2\n-6 % 4\n", "1\n-1\n-1\n2\n");
+        assert_eval("$a = -7\n$b = 2\n($a // $b) * $b + $a % $b\n", "-7\n");
+        assert_eval("-7.5 % 2\n7.5 % -2\n", "0.5\n-0.5\n");
+        assert_eval("(-9_223_372_036_854_775_807 - 1) % -1\n", "0\n");
+        assert_eval("1 + 0.5\n2 * 1.5\n", "1.5\n3\n");
+        assert_eval("9_007_199_254_740_993 + 0\n", "9007199254740993\n");
+        assert_eval("0xff + 0b1 + 0o10\n", "264\n");
+    }
+
+    #[test]
+    fn test_int_errors() {
+        for input in ["9223372036854775807 + 1\n", "1 // 0\n", "1 % 0\n"] {
+            match eval(input) {
+                Err(err) => assert_eq!(err.kind(), "arithmetic"),
+                _ => unreachable!(),
+            }
+        }
+        assert_eval("1 / 0\n", "inf\n");
+    }
+
+    #[test]
+    fn test_unary() {
+        assert_eval("-3\n", "-3\n");
+        assert_eval("2 * -3\n", "-6\n");
+        assert_eval("$x = 4\n2 * -$x\n- -$x\n", "-8\n4\n");
+        assert_eval("!0\n!1\n", "true\nfalse\n");
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eval("2 ** 10\n", "1024\n");
+        assert_eval("2 ** 3 ** 2\n", "512\n");
+        assert_eval("-2 ** 2\n", "-4\n");
+        assert_eval("2 ** -1\n", "0.5\n");
+        assert_eval("4 ** 0.5\n", "2\n");
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eval(
+            "1 < 2\n2 <= 1\n1 == 1.0\n\"a\" != \"b\"\n",
+            "true\nfalse\ntrue\ntrue\n",
+        );
+        assert_eval("\"abc\" < \"abd\"\n3 >= 3\n", "true\ntrue\n");
+        assert_eval("1 + 2 == 3 && 2 < 3\n", "true\n");
+    }
+
+    #[test]
+    fn test_logic_short_circuits() {
+        assert_eval("1 || 1 // 0\n0 && 1 // 0\n", "true\nfalse\n");
+        assert_eval("if 1 < 2 && 2 < 3 { \"yes\" }\n", "yes\n");
+    }
+
+    #[test]
+    fn test_command_chains() {
+        assert_eval(
+            "set -e\nbash -c \"exit 1\" || bash -c \"exit 2\" || bash -c \"exit 0\"\n$?\n",
+            "0\n",
+        );
+        // A failing command on the left of `&&` stops the chain without ending the script.
+        assert_eval(
+            "set -e\nbash -c \"exit 1\" && bash -c \"exit 0\"\n$?\n",
+            "1\n",
+        );
+        assert_eval(
+            "set -e\ntry { bash -c \"exit 0\" && bash -c \"exit 4\" } catch $e { $e.code }\n",
+            "4\n",
+        );
+        assert_eval("[1, 2] | count && \"yes\"\n", "yes\n");
+        assert_eval("bash -c \"exit 1\" ||\n  \"fallback\"\n", "fallback\n");
+    }
+
+    #[test]
+    fn test_bitwise() {
+        assert_eval("12 bit-and 10\n12 bit-or 3\n12 bit-xor 5\n", "8\n15\n9\n");
+        assert_eval("1 bit-shl 4\n256 bit-shr 2\n", "16\n64\n");
+        assert_eval("1 + 1 bit-shl 2\n", "8\n");
+    }
+
     #[test]
     fn test_parens() {
         assert_eval("(1 + 2)\n", "3\n")
@@ -277,8 +980,2012 @@ mod eval {
         assert_eval("bash \"-c\" \"echo nothing\"\n$?\n", "0\n")
     }
 
-    // #[test]
-    // fn test_command_hello() {
-    //     assert_eval("./hello.exe\n$?\n", "0\n")
-    // }
+    #[test]
+    fn test_try_catch_thrown_string() {
+        assert_eval(
+            "try { throw \"oops\" } catch $e { $e.message; $e.kind }\n",
+            "oops\nthrown\n",
+        )
+    }
+
+    #[test]
+    fn test_catch_variable_scope() {
+        assert_eval(
+            "try { throw \"x\" } catch $e { $e.kind }\n$e ?? \"unset\"\n",
+            "thrown\nunset\n",
+        );
+        assert_eval(
+            "$e = 1\ntry { throw \"x\" } catch $e { $e.kind }\n$e\n",
+            "thrown\n1\n",
+        );
+        match eval("set -u\ntry { throw \"x\" } catch $e { }\n$e\n") {
+            Err(err) => assert_eq!(err.kind(), "name"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_try_catch_type_error() {
+        assert_eval(
+            "try {\n  1 + \"a\"\n  2\n} catch $e {\n  $e.kind\n}\n",
+            "type\n",
+        )
+    }
+
+    #[test]
+    fn test_try_keeps_assignments_before_error() {
+        assert_eval(
+            "try { $a = 1; throw \"x\" } catch { $a = $a + 1 }\n$a\n",
+            "2\n",
+        )
+    }
+
+    #[test]
+    fn test_finally_runs() {
+        assert_eval("try { 1 } finally { 2 }\n", "1\n2\n");
+        assert_eval(
+            "try { try { throw \"a\" } finally { throw \"b\" } } catch $e { $e.message }\n",
+            "b\n",
+        );
+    }
+
+    #[test]
+    fn test_uncaught_throw() {
+        match eval("try { throw \"x\" } finally { 1 }\n") {
+            Err(err) => assert_eq!(err.kind(), "thrown"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_rethrow_record() {
+        assert_eval(
+            "try { try { 1 + \"a\" } catch $e { throw $e } } catch $e { $e.kind }\n",
+            "type\n",
+        )
+    }
+
+    #[test]
+    fn test_errexit() {
+        assert_eval("bash -c \"exit 3\"\n$?\n", "3\n");
+        assert_eval(
+            "set -e\ntry { bash -c \"exit 3\" } catch $e { $e.code; $e.kind }\n",
+            "3\nexit-status\n",
+        );
+        assert_eval("set -e\nset --no-errexit\nbash -c \"exit 3\"\n$?\n", "3\n");
+        // `true` and `false` are values, and `^true` and `^false` run the programs.
+        assert_eval(
+            "set -e\ntry { ^false } catch $e { $e.kind }\n^true\n$?\nfalse\n",
+            "exit-status\n0\nfalse\n",
+        );
+    }
+
+    #[test]
+    fn test_if_else() {
+        assert_eval("if 1 { 2 } else { 3 }\n", "2\n");
+        assert_eval("if 0 { 2 } else if \"\" { 3 } else { 4 }\n", "4\n");
+        assert_eval("if bash -c \"exit 1\" {\n  1\n}\nelse {\n  2\n}\n", "2\n");
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eval("$PATH\n", &format!("{}\n", path));
+    }
+
+    #[test]
+    fn test_pipe() {
+        assert_eval("bash -c \"exit 2\" | cat\n$?\n", "0\n");
+        assert_eval("set --pipefail\nbash -c \"exit 2\" | cat\n$?\n", "2\n");
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eval(
+            "1..10\n0..=5\n(0..10).step(3)\n",
+            "1..10\n0..=5\n(0..10).step(3)\n",
+        );
+        assert_eval("(1..=4).rev()[0..4]\n", "[4, 3, 2, 1]\n");
+        assert_eval("(0..10).step(4).rev()[0..3]\n", "[8, 4, 0]\n");
+        assert_eval(
+            "5 in 1..10\n10 in 1..10\n10 in 1..=10\n",
+            "true\nfalse\ntrue\n",
+        );
+        assert_eval(
+            "4 in (0..10).step(2)\n5 in (0..10).step(2)\n",
+            "true\nfalse\n",
+        );
+        assert_eval("1..5 == (1..=4)\n", "true\n");
+    }
+
+    #[test]
+    fn test_ranges_are_lazy() {
+        assert_eval(
+            "$r = 0..1_000_000_000_000\n999_999_999_999 in $r\n",
+            "true\n",
+        );
+        assert_eval("$r = 0..1_000_000_000_000\n$r.rev()[0]\n", "999999999999\n");
+    }
+
+    #[test]
+    fn test_for() {
+        assert_eval(
+            "$sum = 0\nfor $i in 1..=10 { $sum = $sum + $i }\n$sum\n",
+            "55\n",
+        );
+        assert_eval("for $x in [\"a\", \"b\"] {\n  $x\n}\n", "a\nb\n");
+        assert_eval("for $i in (1..4).rev() { $i * 10 }\n", "30\n20\n10\n");
+        // The loop variable hides one outside the loop only while the loop runs.
+        assert_eval(
+            "$i = \"outer\"\nfor $i in 1..3 { $i }\n$i\nfor $j in [1] { }\n$j ?? \"unset\"\n",
+            "1\n2\nouter\nunset\n",
+        );
+        assert_eval(
+            "$i = 0\ntry { for $i in 1..3 { throw \"x\" } } catch { }\n$i\n",
+            "0\n",
+        );
+    }
+
+    #[test]
+    fn test_indexing_and_slicing() {
+        assert_eval(
+            "$a = [10, 20, 30, 40]\n$a[1]\n$a[-1]\n$a[1..3]\n",
+            "20\n40\n[20, 30]\n",
+        );
+        assert_eval("$a = [10, 20, 30, 40]\n$a[(0..10).step(2)]\n", "[10, 30]\n");
+        assert_eval("$s = \"héllo\"\n$s[1]\n$s[0..=2]\n", "é\nhél\n");
+        assert_eval(
+            "$a = [10, 20, 30, 40]\n$a[1..]\n$a[4..]\n\"héllo\"[3..]\n",
+            "[20, 30, 40]\n[]\nlo\n",
+        );
+        assert_eval(
+            "\"b\" in [\"a\", \"b\"]\n\"ell\" in \"hello\"\n",
+            "true\ntrue\n",
+        );
+    }
+
+    #[test]
+    fn test_range_as_command_args() {
+        assert_eval("bash -c \"exit $#\" sh (1..=3) [4, 5]\n$?\n", "5\n");
+    }
+
+    #[test]
+    fn test_closures() {
+        assert_eval(
+            "$k = 10\n$add = { |$x| $x + $k }\n[1, 2] | map $add\n",
+            "[11, 12]\n",
+        );
+        assert_eval("{ || 42 }\n", "{ || ... }\n");
+        assert_eval(
+            "[1, 2] | map { |$x|\n  $y = $x * 3\n  $y + 1\n}\n",
+            "[4, 7]\n",
+        );
+    }
+
+    #[test]
+    fn test_calling_closures() {
+        assert_eval(
+            "$add = { |$x, $y| $x + $y }\n$add(1, 2)\n$add(1, $add(2, 3)) * 2\n",
+            "3\n12\n",
+        );
+        assert_eval(
+            "$fs = [{ || \"a\" }]\n$fs[0]()\n$curry = { |$x| { |$y| $x - $y } }\n$curry(5)(2)\n",
+            "a\n3\n",
+        );
+        assert_eval("({ |$s| $s | upper })(\"b\")\n", "B\n");
+        // With a space between, the parentheses are an argument of their own.
+        assert_eval("$x = 1\nmath max $x (2)\n", "2\n");
+    }
+
+    #[test]
+    fn test_map_where_reduce() {
+        assert_eval(
+            "1..=6 | where { |$n| $n % 2 == 0 } | map { |$n| $n * $n }\n",
+            "[4, 16, 36]\n",
+        );
+        assert_eval("[1, 2, 3] | filter { |$n| $n > 1 } | count\n", "2\n");
+        assert_eval("1..=4 | reduce { |$acc, $n| $acc * $n }\n", "24\n");
+        assert_eval("[] | reduce 7 { |$acc, $n| $acc + $n }\n", "7\n");
+    }
+
+    #[test]
+    fn test_sort_and_group() {
+        assert_eval(
+            "[\"pear\", \"fig\"] | sort-by { |$s| $s }\n",
+            "[fig, pear]\n",
+        );
+        assert_eval("[2, 3, 1] | sort-by { |$n| $n } --reverse\n", "[3, 2, 1]\n");
+        assert_eval(
+            "1..=5 | group-by { |$n| $n % 2 == 0 }\n",
+            "{false: [1, 3, 5], true: [2, 4]}\n",
+        );
+        assert_eval("[1, 2, 1, 3, 2] | uniq\n", "[1, 2, 3]\n");
+    }
+
+    #[test]
+    fn test_first_last() {
+        assert_eval("10..20 | first\n10..20 | last\n", "10\n19\n");
+        assert_eval(
+            "10..20 | first 2\n10..20 | last 2\n",
+            "[10, 11]\n[18, 19]\n",
+        );
+        assert_eval("[] | first\n", "");
+    }
+
+    #[test]
+    fn test_streams_are_lazy() {
+        assert_eval(
+            "0..1_000_000_000_000 | map { |$x| $x * 2 } | where $it % 3 == 0 | first 3\n",
+            "[0, 6, 12]\n",
+        );
+        assert_eval("[1, 1, 2] | uniq | map { |$x| $x + 1 } | count\n", "2\n");
+    }
+
+    #[test]
+    fn test_values_into_commands() {
+        assert_eval("[3, 1, 2] | sort | first\n", "1\n");
+        assert_eval(
+            "1..=3 | map { |$x| $x * 10 } | sort -n -r | first\n",
+            "30\n",
+        );
+        assert_eval("[5] | bash -c \"read n; exit $n\"\n$?\n", "5\n");
+        assert_eval("\"a b c\" | wc -w | map { |$n| $n + \"!\" }\n", "[3!]\n");
+    }
+
+    #[test]
+    fn test_where_and_get() {
+        let catch = "try { throw \"boom\" } catch $e { $errs = [$e, $e] }\n";
+        assert_eval(
+            &format!("{}$errs | where kind == \"thrown\" | get message\n", catch),
+            "[boom, boom]\n",
+        );
+        assert_eval(
+            &format!("{}$errs | where kind == \"io\" | count\n", catch),
+            "0\n",
+        );
+        assert_eval(&format!("{}$errs[0] | get kind\n", catch), "thrown\n");
+        assert_eval("1..=6 | where $it > 4\n", "[5, 6]\n");
+    }
+
+    #[test]
+    fn test_command_output_into_builtins() {
+        assert_eval("printf \"b\\na\\nb\\n\" | uniq | count\n", "2\n");
+        assert_eval("printf \"b\\na\\n\" | sort-by { |$l| $l } | first\n", "a\n");
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let text = "{\"name\": \"oyster\", \"tags\": [\"a\", \"b\\n\\u00e9\"], \"n\": -3, \"x\": 1.0, \"ok\": true, \"none\": null, \"empty\": {}}";
+        let compact = "{\"name\":\"oyster\",\"tags\":[\"a\",\"b\\né\"],\"n\":-3,\"x\":1.0,\"ok\":true,\"none\":null,\"empty\":{}}";
+        let path = std::env::temp_dir().join("oysterlang-test-round-trip.json");
+        std::fs::write(&path, text).unwrap();
+        let path = path.display();
+        assert_eval(
+            &format!("json {} | to json -c\n", path),
+            &format!("{}\n", compact),
+        );
+        assert_eval(
+            &format!(
+                "cat {} | from json | to json | from json | to json --compact\n",
+                path
+            ),
+            &format!("{}\n", compact),
+        );
+        assert_eval(&format!("$d = (json {})\n$d.tags[1]\n", path), "b\né\n");
+        assert_eval(&format!("$d = (json {})\n$d.n * 2\n", path), "-6\n");
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eval(
+            "[1, [2.5, \"x\"], []] | to json --compact\n",
+            "[1,[2.5,\"x\"],[]]\n",
+        );
+        assert_eval(
+            "[1, [1 < 2]] | to json\n",
+            "[\n  1,\n  [\n    true\n  ]\n]\n",
+        );
+        assert_eval("0..3 | map { |$x| $x * 2 } | to json -c\n", "[0,2,4]\n");
+    }
+
+    fn temp_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, text).unwrap();
+        format!("{}", path.display())
+    }
+
+    #[test]
+    fn test_import_and_use() {
+        let dir = temp_dir("oysterlang-test-import");
+        let write = |name: &str, text: &str| {
+            let path = format!("{}/{}", dir, name);
+            std::fs::write(&path, text).unwrap();
+            path
+        };
+        let helper = write(
+            "oysterlang-import-helper.oy",
+            "export let $greeting = \"hi\"\n",
+        );
+        let util = write(
+            "oysterlang-import-util.oy",
+            "\"loading\"\nexport $double = { |$x| $x * 2 }\nexport const $factor = 3\n$private = 1\nuse \"oysterlang-import-helper\"\nexport $both = $greeting\n",
+        );
+        // The script runs once however often it is imported, and only its exports are seen.
+        assert_eval(
+            &format!(
+                "import \"{0}\"\nimport \"{0}\" as $u\n[1, 2] | map $oysterlang_import_util.double\n$u.factor\n$u.private ?? \"hidden\"\n$u.both\n",
+                util
+            ),
+            "loading\n[2, 4]\n3\nhidden\nhi\n",
+        );
+        assert_eval(
+            &format!("use \"{}\"\n$factor + 1\n$private ?? \"hidden\"\n", util),
+            "loading\n4\nhidden\n",
+        );
+        // Exported closures are called like any other, or as methods of the imported record.
+        assert_eval(
+            &format!(
+                "use \"{0}\"\n$double(21)\nimport \"{0}\" as $u\n$u.double($factor)\n",
+                util
+            ),
+            "loading\n42\n6\n",
+        );
+        // `source` runs the script as if it were written in place, keeping every variable it sets.
+        assert_eval(
+            &format!("source \"{}\"\n$private\n$greeting\n", util),
+            "loading\n1\nhi\n",
+        );
+        assert_eval(
+            &format!(
+                "$greeting = \"bye\"\nsource \"{0}\"\n$greeting\nsource \"{0}\"\n",
+                helper
+            ),
+            "hi\n",
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_search_path() {
+        use crate::eval_with_options;
+        use crate::interpreter::Options;
+
+        let dir = temp_dir("oysterlang-test-search");
+        std::fs::write(
+            format!("{}/oysterlang-search.oy", dir),
+            "export $found = \"yes\"\n",
+        )
+        .unwrap();
+        let mut opts = Options::default();
+        opts.module_path.push(dir.clone().into());
+        match eval_with_options("import \"oysterlang-search\" as $m\n$m.found\n", opts) {
+            Ok(out) => assert_eq!(out, "yes\n"),
+            Err(err) => panic!("{}", err),
+        }
+        assert_eval(
+            &format!(
+                "$OYSTER_PATH = \"/nowhere:{}\"\nuse oysterlang-search.oy\n$found\n",
+                dir
+            ),
+            "yes\n",
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv() {
+        let path = temp_file(
+            "oysterlang-test.csv",
+            "name,size,note\r\nalpha,10,\"hello, world\"\nbeta,2.5,\"say \"\"hi\"\"\"\ngamma,007,\"12\"\n",
+        );
+        assert_eval(
+            &format!("cat {} | from csv | get size\n", path),
+            "[10, 2.5, 007]\n",
+        );
+        assert_eval(
+            &format!("cat {} | from csv | where size == 10 | get note\n", path),
+            "[hello, world]\n",
+        );
+        assert_eval(
+            &format!("cat {} | from csv | to csv\n", path),
+            "name,size,note\nalpha,10,\"hello, world\"\nbeta,2.5,\"say \"\"hi\"\"\"\ngamma,007,\"12\"\n",
+        );
+        assert_eval(
+            &format!("cat {} | from csv | to tsv --no-headers\n", path),
+            "alpha\t10\thello, world\nbeta\t2.5\t\"say \"\"hi\"\"\"\ngamma\t007\t\"12\"\n",
+        );
+        let path = temp_file("oysterlang-test.ssv", "1;2\n3;4\n");
+        assert_eval(
+            &format!("cat {} | from csv -n -s \";\" | get column2\n", path),
+            "[2, 4]\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-date.csv",
+            "when,s\n2024-01-05,\"2024-01-05\"\n",
+        );
+        assert_eval(
+            &format!(
+                "$rows = (cat {} | from csv)\n$rows[0].when | typeof\n",
+                path
+            ),
+            "date\n",
+        );
+        assert_eval(
+            &format!("cat {} | from csv | to csv\n", path),
+            "when,s\n2024-01-05T00:00:00Z,\"2024-01-05\"\n",
+        );
+    }
+
+    #[test]
+    fn test_toml() {
+        let path = temp_file(
+            "oysterlang-test.toml",
+            "title = \"demo\" # comment\n\n[owner]\nname = 'Tom'\ndob = 1979-05-27T07:32:00Z\n\n\
+             [db]\nports = [ 8000,\n  8001 ]\nlimits = { max = 1_000, ratio = 0.5 }\n\n\
+             [[servers]]\nname = \"a\"\n\n[[servers]]\nname = \"b\"\n",
+        );
+        assert_eval(
+            &format!(
+                "$t = (cat {} | from toml)\n$t.db.ports[1] + $t.db.limits.max\n",
+                path
+            ),
+            "9001\n",
+        );
+        assert_eval(
+            &format!("cat {} | from toml | get servers | get name\n", path),
+            "[a, b]\n",
+        );
+        assert_eval(
+            &format!("cat {} | from toml | to toml\n", path),
+            "title = \"demo\"\n\n[owner]\nname = \"Tom\"\ndob = 1979-05-27T07:32:00Z\n\n\
+             [db]\nports = [8000, 8001]\n\n[db.limits]\nmax = 1000\nratio = 0.5\n\n\
+             [[servers]]\nname = \"a\"\n\n[[servers]]\nname = \"b\"\n",
+        );
+        assert_eval(
+            &format!("cat {} | from toml | get owner | get dob | typeof\n", path),
+            "date\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-version.json",
+            "{\"version\":\"2024-01-01\"}",
+        );
+        assert_eval(
+            &format!("json {} | to toml\n", path),
+            "version = \"2024-01-01\"\n",
+        );
+        assert_eval(
+            &format!(
+                "json {} | to toml | from toml | get version | typeof\n",
+                path
+            ),
+            "string\n",
+        );
+    }
+
+    #[test]
+    fn test_yaml() {
+        let path = temp_file(
+            "oysterlang-test.yaml",
+            "---\nname: demo  # comment\ntags: [a, \"b c\", 3]\nservers:\n- name: a\n  port: 80\n\
+             - name: b\n  port: 0x1F\nnone:\nnote: |\n  one\n  two\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | get servers | get port\n", path),
+            "[80, 31]\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | to json -c\n", path),
+            "{\"name\":\"demo\",\"tags\":[\"a\",\"b c\",3],\"servers\":[{\"name\":\"a\",\"port\":80},\
+             {\"name\":\"b\",\"port\":31}],\"none\":null,\"note\":\"one\\ntwo\\n\"}\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | to yaml\n", path),
+            "name: demo\ntags:\n  - a\n  - b c\n  - 3\nservers:\n  - name: a\n    port: 80\n\
+             \x20 - name: b\n    port: 31\nnone: null\nnote: \"one\\ntwo\\n\"\n",
+        );
+        assert_eval(
+            &format!(
+                "cat {} | from yaml | to yaml | from yaml | get tags\n",
+                path
+            ),
+            "[a, b c, 3]\n",
+        );
+        assert_eval(
+            "[\"yes\", \"Off\", \"y\", \"1.0\", \"+1\", \".5\", \"007\", \"2024-01-05\", \"x\"] | to yaml\n",
+            "- \"yes\"\n- \"Off\"\n- \"y\"\n- \"1.0\"\n- \"+1\"\n- \".5\"\n- \"007\"\n- \"2024-01-05\"\n- x\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-date.yaml",
+            "d: 2024-01-05\ns: '2024-01-05'\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | get d | typeof\n", path),
+            "date\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | to yaml\n", path),
+            "d: 2024-01-05T00:00:00Z\ns: \"2024-01-05\"\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-items.yaml",
+            "- |\n  one\n  two\n- >-\n  folded\n  text\n- x\n",
+        );
+        assert_eval(
+            &format!("cat {} | from yaml | to json -c\n", path),
+            "[\"one\\ntwo\\n\",\"folded text\",\"x\"]\n",
+        );
+    }
+
+    #[test]
+    fn test_records_as_json_lines() {
+        let path = temp_file("oysterlang-test-lines.csv", "name,size\na,1\nbeta,22\n");
+        assert_eval(
+            &format!("cat {} | from csv\n", path),
+            "{\"name\":\"a\",\"size\":1}\n{\"name\":\"beta\",\"size\":22}\n",
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        use crate::interpreter::{Options, Value};
+        use crate::{eval_with_options, table};
+        let path = temp_file(
+            "oysterlang-test-table.json",
+            "[{\"name\": \"a\", \"size\": 1, \"tags\": [1, 2]},
+              {\"name\": \"beta\", \"size\": 22, \"owner\": {\"id\": 1}, \"note\": \"two\\nlines\"}]",
+        );
+        let opts = Options {
+            table_width: Some(80),
+            ..Default::default()
+        };
+        match eval_with_options(&format!("json {}\n", path), opts) {
+            Ok(out) => assert_eq!(
+                out,
+                " # │ name │ size │ tags      │ owner     │ note\n\
+                 ───┼──────┼──────┼───────────┼───────────┼──────\n \
+                 0 │ a    │    1 │ [2 items] │           │\n \
+                 1 │ beta │   22 │           │ {1 field} │ two…\n"
+            ),
+            _ => unreachable!(),
+        }
+
+        let records = vec![Value::Record(vec![
+            (
+                String::from("path"),
+                Value::Str(String::from("/a/very/long/path/name")),
+            ),
+            (String::from("size"), Value::Int(4096)),
+            (
+                String::from("modified"),
+                Value::Str(String::from("yesterday")),
+            ),
+        ])];
+        let out = table::render(&records, 30);
+        assert_eq!(
+            out,
+            " # │ path     │ size │ …\n───┼──────────┼──────┼───\n 0 │ /a/very… │ 4096 │ …"
+        );
+        assert!(out.lines().all(|line| line.chars().count() <= 30));
+    }
+
+    pub fn temp_dir(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir(&path).unwrap();
+        format!("{}", path.display())
+    }
+
+    #[test]
+    fn test_ls_and_stat() {
+        let dir = temp_dir("oysterlang-test-ls");
+        std::fs::write(format!("{}/b.txt", dir), "hello").unwrap();
+        std::fs::write(format!("{}/a.txt", dir), "hi").unwrap();
+        std::fs::write(format!("{}/.hidden", dir), "").unwrap();
+        std::fs::create_dir(format!("{}/sub", dir)).unwrap();
+        assert_eval(
+            &format!("ls \"{}\" | get name\n", dir),
+            "[a.txt, b.txt, sub]\n",
+        );
+        assert_eval(&format!("ls \"{}\" -a | count\n", dir), "4\n");
+        assert_eval(
+            &format!(
+                "ls \"{}\" | where type == \"file\" | sort-by size --reverse | first | get name\n",
+                dir
+            ),
+            "b.txt\n",
+        );
+        assert_eval(
+            &format!("$f = stat \"{}/b.txt\"\n$f.size\n$f.type\n", dir),
+            "5 B\nfile\n",
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = format!("{}/a.txt", dir);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+            assert_eval(&format!("(stat \"{}\").permissions\n", path), "rw-r-----\n");
+        }
+        match eval(&format!("(stat \"{}/a.txt\").modified\n", dir)) {
+            Ok(out) => assert!(out.len() == 21 && out.ends_with("Z\n"), "{}", out),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        use crate::datetime::DateTime;
+        let timestamp = |secs| format!("{}", DateTime::from_secs(secs));
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_827_696), "2000-02-29T12:34:56Z");
+        assert_eq!(timestamp(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_du() {
+        let dir = temp_dir("oysterlang-test-du");
+        std::fs::create_dir_all(format!("{}/sub/deeper", dir)).unwrap();
+        std::fs::write(format!("{}/sub/a", dir), "12345").unwrap();
+        std::fs::write(format!("{}/sub/deeper/b", dir), "123").unwrap();
+        std::fs::write(format!("{}/c", dir), "1").unwrap();
+        assert_eval(
+            &format!("du \"{}\" | map {{ |$e| $e.size }}\n", dir),
+            "[1 B, 8 B]\n",
+        );
+        assert_eval(&format!("du \"{}\" | get size | math sum\n", dir), "9 B\n");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_ps() {
+        assert_eval(
+            &format!("ps | where pid == {} | count\n", std::process::id()),
+            "1\n",
+        );
+    }
+
+    #[test]
+    fn test_open_and_save() {
+        let dir = temp_dir("oysterlang-test-open");
+        assert_eval(
+            &format!(
+                "[1, 2] | save \"{0}/n.json\"\nopen \"{0}/n.json\"\nopen \"{0}/n.json\" --raw\n",
+                dir
+            ),
+            "[1, 2]\n[\n  1,\n  2\n]\n",
+        );
+        assert_eval(
+            &format!(
+                "open \"{0}/n.json\" | map {{ |$n| $n * 10 }} | save \"{0}/n.yaml\"\nopen \"{0}/n.yaml\" | first\n",
+                dir
+            ),
+            "10\n",
+        );
+        assert_eval(
+            &format!(
+                "\"plain text\" | save \"{0}/t.txt\"\nopen \"{0}/t.txt\"\n",
+                dir
+            ),
+            "plain text\n",
+        );
+    }
+
+    #[test]
+    fn test_programs_behind_builtins() {
+        // `^name` runs the program rather than the builtin of the same name.
+        let dir = temp_dir("oysterlang-test-programs");
+        std::fs::write(format!("{}/a.txt", dir), "hi").unwrap();
+        std::fs::create_dir_all(format!("{}/sub/deeper", dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/gone/deeper", dir)).unwrap();
+        assert_eval(&format!("^ls -la \"{}\" | lines | count\n", dir), "6\n");
+        assert_eval(
+            &format!("^rm -rf \"{0}/sub\"\n$?\nls \"{0}\" | get name\n", dir),
+            "0\n[a.txt, gone]\n",
+        );
+        // Short flags given together still run the builtin.
+        assert_eval(
+            &format!("rm -rf \"{0}/gone\"\nls \"{0}\" | get name\n", dir),
+            "[a.txt]\n",
+        );
+        assert_eval(
+            "[\"a\", \"a\", \"b\"] | ^uniq -c | lines | map { |$l| $l | trim }\n",
+            "[2 a, 1 b]\n",
+        );
+        std::fs::write(format!("{}/two.txt", dir), "1\n2\n").unwrap();
+        assert_eval(
+            &format!(
+                "^split -l 1 \"{0}/two.txt\" \"{0}/part-\"\nls \"{0}\" | get name\n",
+                dir
+            ),
+            "[a.txt, part-aa, part-ab, two.txt]\n",
+        );
+        assert_eval(
+            "(^date +%s | lines | first | into int) > 1_600_000_000\n",
+            "true\n",
+        );
+        match eval(&format!("ls -la \"{}\"\n", dir)) {
+            Err(err) => assert_eq!(
+                format!("{}", err),
+                "usage: ls [path] [--all] (or `^ls` to run the program)"
+            ),
+            Ok(out) => panic!("{}", out),
+        }
+    }
+
+    #[test]
+    fn test_file_commands() {
+        let dir = temp_dir("oysterlang-test-files");
+        assert_eval(
+            &format!(
+                "mkdir \"{0}/a/b\" --parents
+                 \"x\" | save \"{0}/a/b/x.txt\"
+                 cp \"{0}/a/b/x.txt\" \"{0}/y.txt\"
+                 cp \"{0}/a\" \"{0}/copy\" -r
+                 mv \"{0}/y.txt\" \"{0}/a\"
+                 ls \"{0}\" | get name
+                 ls \"{0}/a\" | get name
+                 open \"{0}/copy/b/x.txt\"
+                 rm \"{0}/copy\" -r
+                 rm (ls \"{0}/a\" | where type == \"file\" | get name | map {{ |$n| \"{0}/a/\" + $n }})
+                 rm \"{0}/missing\" --force
+                 ls \"{0}\" -a | get name
+                 ls \"{0}/a\" | get name\n",
+                dir
+            ),
+            "[a, copy]\n[b, y.txt]\nx\n[a]\n[b]\n",
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eval(
+            "\"a,b,,c\" | split \",\"\n\"  one two  \" | split\n[\"a\", 1] | join \", \"\n",
+            "[a, b, , c]\n[one, two]\na, 1\n",
+        );
+        assert_eval(
+            "\"  pad  \" | trim\n\"xxpadxy\" | trim \"xy\"\n\"--a--\" | trim-start \"-\"\n\"--a--\" | trim-end \"-\"\n",
+            "pad\npad\na--\n--a\n",
+        );
+        assert_eval(
+            "\"a-b-c\" | replace \"-\" \"+\"\n\"MiXeD\" | upper\n\"MiXeD\" | lower\n",
+            "a+b+c\nMIXED\nmixed\n",
+        );
+        assert_eval(
+            "$s = \"oyster.toml\"\n$s | contains \"ter\"\n$s | starts-with \"oy\"\n$s | ends-with \".json\"\n",
+            "true\ntrue\nfalse\n",
+        );
+        assert_eval(
+            "\"ab\" | pad-start 4\n\"7\" | pad-start 3 \"0\"\n\"ab\" | pad-end 4 \".\"\n\"ab\" | repeat 3\n",
+            "  ab\n007\nab..\nababab\n",
+        );
+        assert_eval(
+            "[\"a\", \"bb\"] | upper\n[\"a\", \"bb\"] | map { |$s| $s | length }\n",
+            "[A, BB]\n[1, 2]\n",
+        );
+        let path = temp_file("oysterlang-test-lines.txt", "one\ntwo\r\nthree\n");
+        assert_eval(
+            &format!("cat {0} | lines\ncat {0} | lines | count\n", path),
+            "[one, two, three]\n3\n",
+        );
+    }
+
+    #[test]
+    fn test_unicode_strings() {
+        assert_eval(
+            "$s = \"naïve café\"\n$s | length\n$s | length --bytes\n",
+            "10\n12\n",
+        );
+        assert_eval(
+            "\"héllo\" | substring 1 3\n\"héllo\" | substring -3\n\"héllo\" | substring 3 99\n\"héllo\" | substring 4 2 | length\n",
+            "él\nllo\nlo\n0\n",
+        );
+        assert_eval(
+            "\"日本語\" | split \"\"\n\"straße\" | upper\n\"ÀÉÎ\" | lower\n",
+            "[日, 本, 語]\nSTRASSE\nàéî\n",
+        );
+        assert_eval(
+            "\"é\" | pad-start 3 \"·\"\n\"ü\" | format \"[{:^5}]\"\n",
+            "··é\n[  ü  ]\n",
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eval(
+            "3.14159 | format \"{:.2}\"\n3.14159 | format \"{:>8.3}|\"\n42 | format \"{:<5}|\"\n",
+            "3.14\n   3.142|\n42   |\n",
+        );
+        assert_eval(
+            "format \"{} has {:03} files\" \"src\" 7\nformat \"{1}-{0}\" \"a\" \"b\"\n-5 | format \"{:04}\"\n",
+            "src has 007 files\nb-a\n-005\n",
+        );
+        assert_eval(
+            "\"abcdef\" | format \"{:.3}\"\n\"ab\" | format \"{:*>4}\"\n1 | format \"{{{}}}\"\n",
+            "abc\n**ab\n{1}\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-format.json",
+            "[{\"name\": \"a\", \"size\": 1.5}, {\"name\": \"bcd\", \"size\": 20}]",
+        );
+        assert_eval(
+            &format!("json {} | format \"{{name:-<4}}{{size:>6.1}}\"\n", path),
+            "[a---   1.5, bcd-  20.0]\n",
+        );
+    }
+
+    #[test]
+    fn test_regex_match() {
+        assert_eval(
+            "\"main.rs\" =~ r\"\\.rs$\"\n\"main.rs\" !~ \"^main\"\n\"Main\" =~ r\"(?i)^main$\"\n",
+            "true\nfalse\ntrue\n",
+        );
+        assert_eval(
+            "$re = r\"[0-9]+\"\n$re\n[\"a1\", \"b\", \"c22\"] | where { |$s| $s =~ $re }\n",
+            "[0-9]+\n[a1, c22]\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-regex.json",
+            "[{\"name\": \"main.rs\"}, {\"name\": \"notes.md\"}, {\"name\": \"lib.rs\"}]",
+        );
+        assert_eval(
+            &format!("json {} | where name =~ r\"\\.rs$\" | get name\n", path),
+            "[main.rs, lib.rs]\n",
+        );
+    }
+
+    #[test]
+    fn test_captures_and_replace() {
+        assert_eval(
+            "$d = \"2024-01-05\"\n$d | captures r\"(\\d+)-(\\d+)\"\n$d | captures r\"(?P<year>\\d+)-(?P<month>\\d+)\"\n$d | captures r\"x\"\n",
+            "[2024, 01]\n{year: 2024, month: 01}\n",
+        );
+        assert_eval(
+            "\"a1 b22 c\" | captures \"([a-z])(\\d+)?\" --all\n",
+            "[[a, 1], [b, 22], [c, null]]\n",
+        );
+        assert_eval(
+            "\"ann@example.com\" | replace r\"(?P<user>\\w+)@(\\w+)\" \"${user} at $2\"\n\"a.b.c\" | replace \".\" \"-\"\n\"a.b.c\" | replace r\".\" \"-\"\n",
+            "ann at example.com\na-b-c\n-----\n",
+        );
+        assert_eval("\"a1b22c\" | split r\"\\d+\"\n", "[a, b, c]\n");
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let path = temp_file(
+            "oysterlang-test-parse.txt",
+            "root 1 init\nbob 42 bash -l\n\nnot a process\n",
+        );
+        assert_eval(
+            &format!(
+                "cat {} | parse r\"^(?P<user>\\w+) (?P<pid>\\d+) (?P<cmd>.+)$\" | where pid > 1 | get cmd\n",
+                path
+            ),
+            "[bash -l]\n",
+        );
+        assert_eval(
+            "[\"k=v\", \"n=3\"] | parse \"(\\w+)=(\\w+)\"\n",
+            "{\"column1\":\"k\",\"column2\":\"v\"}\n{\"column1\":\"n\",\"column2\":3}\n",
+        );
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eval(
+            "-3 | math abs\nmath abs -2.5\n[1.5, -1.5] | math floor\n[1.5, -1.5] | math ceil\n[2.5, -2.5] | math round\n",
+            "3\n2.5\n[1, -2]\n[2, -1]\n[3, -3]\n",
+        );
+        assert_eval(
+            "2.71828 | math round 2\n1234 | math round -2\n16 | math sqrt\n1000 | math log 10\n1 | math exp\n",
+            "2.72\n1200\n4\n3\n2.718281828459045\n",
+        );
+        assert_eval(
+            "2 | math pow 10\n4 | math pow 0.5\n0 | math cos\n1 | math asin | math round 4\n",
+            "1024\n2\n1\n1.5708\n",
+        );
+        assert_eval(
+            "math pi\nmath e | math round 3\n(math tau) / 2 == (math pi)\n",
+            "3.141592653589793\n2.718\ntrue\n",
+        );
+    }
+
+    #[test]
+    fn test_null_and_bools() {
+        assert_eval(
+            "true\nfalse\nnull\n!null\ntrue && false\nif null { 1 } else { 2 }\n",
+            "true\nfalse\ntrue\nfalse\n2\n",
+        );
+        assert_eval(
+            "[1, null]\nnull == null\nnull == 0\n$missing == null\n[null, true] | to json --compact\n",
+            "[1, null]\ntrue\nfalse\ntrue\n[null,true]\n",
+        );
+    }
+
+    #[test]
+    fn test_coalesce() {
+        assert_eval(
+            "$missing ?? 5\n0 ?? 5\nfalse ?? 5\nnull ?? null ?? 3\n",
+            "5\n0\nfalse\n3\n",
+        );
+        assert_eval("set -u\n$missing ?? \"default\"\n", "default\n");
+    }
+
+    #[test]
+    fn test_null_in_text() {
+        // Null fills in as nothing, and a command is given no argument for it.
+        assert_eval(
+            "format \"<{}>\" null\n[\"a\", null, \"b\"] | join \",\"\n",
+            "<>\na,,b\n",
+        );
+        assert_eval("bash \"-c\" \"exit $#\" x null \"\"\n$?\n", "1\n");
+    }
+
+    #[test]
+    fn test_destructuring_assignment() {
+        assert_eval(
+            "[$a, $b] = [1, 2]\n$a, $b = $b, $a\n[$a, $b]\n[$x, ..$rest] = 1..4 | into int\n$rest\n",
+            "[2, 1]\n[2, 3]\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-config.json",
+            r#"{"host": "example.com", "port": 8080}"#,
+        );
+        assert_eval(
+            &format!(
+                "{{host, port: $p}} = json \"{}\"\n$host + \":\" + ($p | into string)\n",
+                path
+            ),
+            "example.com:8080\n",
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        assert_eval(
+            "$n = 5\n$n += 2\n$n *= 3\n$n -= 1\n$n\n$s = \"a\"\n$s += \"b\"\n$s\n",
+            "20\nab\n",
+        );
+        assert_eval(
+            "[1, 2] | map { |$i| $j = $i; $j *= 10; $j }\n",
+            "[10, 20]\n",
+        );
+    }
+
+    #[test]
+    fn test_assigning_to_parts() {
+        assert_eval(
+            "$cfg.port = 80\n$cfg.host = \"x\"\n$cfg.port += 1\n$cfg\n$cfg.port\n",
+            "{port: 81, host: x}\n81\n",
+        );
+        assert_eval(
+            "$xs = [1, [2, 3]]\n$ys = $xs\n$xs[1][0] = 9\n$xs[-1][1] *= 10\n$xs\n$ys\n",
+            "[1, [9, 30]]\n[1, [2, 3]]\n",
+        );
+        // The index of a compound assignment is only worked out once.
+        assert_eval(
+            "$xs = [1, 2, 3]\nlet mut $calls = 0\n$next = { |$i| $calls += 1\n  $i }\n$xs[[1] | map $next | first] += 10\n$xs\n$calls\n",
+            "[1, 12, 3]\n1\n",
+        );
+    }
+
+    #[test]
+    fn test_block_scoped_let() {
+        assert_eval(
+            "$x = \"outer\"\nif true {\n  let $x = \"inner\"\n  $x\n  let $x = \"again\"\n  $x\n  $y = \"leaks\"\n}\n$x\n$y\n",
+            "inner\nagain\nouter\nleaks\n",
+        );
+        assert_eval(
+            "for $i in 1..3 { let $last = $i }\n$last ?? \"gone\"\nlet [$a, $b] = [1, 2]\n$a + $b\n",
+            "gone\n3\n",
+        );
+    }
+
+    #[test]
+    fn test_closures_share_let_mut() {
+        // Closures copy plain variables but share those bound with `let mut`.
+        assert_eval(
+            "$n = 1\n$get = { |$i| $n }\n$n = 2\n[0] | map $get\nlet mut $m = 1\n$getm = { |$i| $m }\n$m = 5\n[0] | map $getm\n",
+            "[1]\n[5]\n",
+        );
+        assert_eval(
+            "let mut $total = 0\n[1, 2, 3] | map { |$i| $total += $i } | count\n$total\n",
+            "3\n6\n",
+        );
+    }
+
+    #[test]
+    fn test_keywords_as_arguments() {
+        assert_eval("echo in | lines\n", "[in]\n");
+        assert_eval("echo source | lines\n", "[source]\n");
+        assert_eval("for $x in [1] { echo if $x in | lines }\n", "[if 1 in]\n");
+    }
+
+    #[test]
+    fn test_long_script() {
+        let input: String = (0..20_000).map(|i| format!("$x = {}\n", i)).collect();
+        assert_eval(&format!("{}$x\n", input), "19999\n");
+    }
+
+    #[test]
+    fn test_loop_updates_in_place() {
+        assert_eval(
+            "$xs = [0, 0, 0]\n$cfg.n = 0\nfor $i in 0..3000 {\n  $xs[$i % 3] += 1\n  $cfg.n += 1\n}\n$xs\n$cfg.n\n",
+            "[1000, 1000, 1000]\n3000\n",
+        );
+    }
+
+    #[test]
+    fn test_calls_dont_copy_variables() {
+        // Copying 20,000 variables for each of 20,000 calls would take minutes.
+        let vars: String = (0..20_000).map(|i| format!("$v{} = {}\n", i, i)).collect();
+        let started = std::time::Instant::now();
+        assert_eval(
+            &format!("{}0..20000 | map {{ |$x| $x + $v1 }} | count\n", vars),
+            "20000\n",
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_match_literals_and_ranges() {
+        assert_eval(
+            "$f = { |$v| match $v {\n  0 => \"zero\"\n  1..10 => \"small\"\n  ..0 => \"negative\"\n  \"a\" => \"letter\"\n  null => \"nothing\"\n  1kb..=1mb => \"medium\"\n  _ => \"other\"\n} }\n[0, 5, 1.5, -3, \"a\", null, 1mb, 10] | map $f\n",
+            "[zero, small, small, negative, letter, nothing, medium, other]\n",
+        );
+        assert_eval(
+            "$x = match 3 { 3 => \"three\", _ => \"no\" }\n$x\n",
+            "three\n",
+        );
+    }
+
+    #[test]
+    fn test_match_destructuring() {
+        assert_eval(
+            "$f = { |$v| match $v {\n  [] => \"empty\"\n  [$a] => $a\n  [$a, ..$mid, $z] if $a == $z => $mid | count\n  [$a, ..] => $a * 10\n} }\n[[], [7], [1, 2, 3, 1], [4, 5]] | map $f\n",
+            "[empty, 7, 2, 40]\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-people.json",
+            r#"[{"name": "root"}, {"name": "al", "age": 30}, {"name": "kid", "age": 3}, {"id": 1}]"#,
+        );
+        assert_eval(
+            &format!(
+                "json \"{}\" | map {{ |$p| match $p {{\n  {{name: \"root\"}} => \"admin\"\n  {{name, age: 18..}} => $name + \" is an adult\"\n  {{name}} => $name\n  _ => \"nobody\"\n}} }}\n",
+                path
+            ),
+            "[admin, al is an adult, kid, nobody]\n",
+        );
+    }
+
+    #[test]
+    fn test_match_regex_and_types() {
+        assert_eval(
+            "match \"bob@home\" {\n  r\"^(?P<user>\\w+)@(?P<host>\\w+)$\" => $user + \" at \" + $host\n  _ => \"?\"\n}\n",
+            "bob at home\n",
+        );
+        assert_eval(
+            "[1, 2.5, \"x\", [1], 2s, true] | map { |$v| match $v { int => \"i\", float => \"f\", string => \"s\", array => \"a\", bool => \"b\", _ => \"?\" } } | join \"\"\n",
+            "ifsa?b\n",
+        );
+        assert_eval("$x = 1\nmatch 5 { $x if $x > 3 => $x }\n$x\n", "5\n1\n");
+    }
+
+    #[test]
+    fn test_into() {
+        assert_eval(
+            "\"42\" | into int\n\" -0x1f \" | into int\n\"1_000.5\" | into float\n3.9 | into int\n",
+            "42\n-31\n1000.5\n3\n",
+        );
+        assert_eval(
+            "into string 12\nnull | into string | count\n\"Yes\" | into bool\n0 | into bool\n",
+            "12\n0\ntrue\nfalse\n",
+        );
+        assert_eval(
+            "[\"1\", \"2\"] | into int | math sum\n2kb | into int\n90s | into float\n",
+            "3\n2000\n90000000000\n",
+        );
+        assert_eval(
+            "\"2024-01-02\" | into date\n\"02/01/2024\" | into date --format \"%d/%m/%Y\"\n86400 | into date\n",
+            "2024-01-02T00:00:00Z\n2024-01-02T00:00:00Z\n1970-01-02T00:00:00Z\n",
+        );
+        assert_eval(
+            "86400 | into datetime | typeof\n\"2024-01-02\" | into date | typeof\n",
+            "date\ndate\n",
+        );
+        assert_eval("\"/tmp/a/../b/./c\" | into path\n", "/tmp/b/c\n");
+    }
+
+    #[test]
+    fn test_typeof_and_describe() {
+        assert_eval(
+            "typeof 1.5\n\"a\" | typeof\nnull | typeof\n1kb | typeof\n",
+            "float\nstring\nnull\nsize\n",
+        );
+        assert_eval(
+            "[1, 2] | describe\n[1, \"a\"] | describe\n[] | describe\n[[1], [2]] | describe\n",
+            "array<int>\narray<any>\narray\narray<array<int>>\n",
+        );
+    }
+
+    #[test]
+    fn test_sizes_and_durations() {
+        assert_eval(
+            "512b\n1kib\n1.5kb\n10kb\n2MiB\n",
+            "512 B\n1 KiB\n1.5 KiB\n9.8 KiB\n2 MiB\n",
+        );
+        assert_eval(
+            "1h30m\n90s\n500ms + 1s\n2d - 1ns\n0s\n",
+            "1h30m\n1m30s\n1s500ms\n1d23h59m59s999ms999us999ns\n0s\n",
+        );
+        assert_eval(
+            "1kb * 3\n2 * 2min / 4\n1h / 30m\n1h // 7m\n10s % 3s\n-1s\n1.5 * 1s\n",
+            "2.9 KiB\n1m\n2\n8\n1s\n-1s\n1s500ms\n",
+        );
+        assert_eval(
+            "1kb < 1kib\n1min == 60s\n2s > 500ms\n[2s, 500ms, 1m] | math sum\n[1kb, 3kb] | math avg\n",
+            "true\ntrue\ntrue\n1m2s500ms\n2 KiB\n",
+        );
+        assert_eval("[1kb, 2s] | to json --compact\n", "[1000,2000000000]\n");
+        // Commands are given a size in bytes and a duration in seconds.
+        assert_eval(
+            "bash \"-c\" \"test $0 = 1000 -a $1 = 1.5\" 1kb 1500ms\n$?\n",
+            "0\n",
+        );
+        assert_eval("(timeit { || 1 + 1 }) < 1s\n", "true\n");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eval(
+            "\"2024-01-05T09:30:00Z\" | date parse\ndate parse \"2024-01-05 09:30:00.25+05:30\"\ndate parse \"2024-02-29\"\n",
+            "2024-01-05T09:30:00Z\n2024-01-05T09:30:00.250+05:30\n2024-02-29T00:00:00Z\n",
+        );
+        assert_eval(
+            "\"05/Jan/2024:09:30:00 -0800\" | date parse --format \"%d/%b/%Y:%H:%M:%S %z\"\ndate parse \"1700000000\" --format \"%s\"\ndate parse \"Jan 5 2024 9:30 PM\" --format \"%b %e %Y %I:%M %p\"\n",
+            "2024-01-05T09:30:00-08:00\n2023-11-14T22:13:20Z\n2024-01-05T21:30:00Z\n",
+        );
+        assert_eval(
+            "$d = date parse \"2024-01-05T09:30:00Z\"\n$d | date format \"%A %B %e, %Y at %I:%M %p\"\n$d | date format \"%F %T %z %j %s\"\n($d | date parts).weekday\n",
+            "Friday January  5, 2024 at 09:30 AM\n2024-01-05 09:30:00 +0000 005 1704447000\nFriday\n",
+        );
+        assert_eval(
+            "[\"2024-01-05\", \"2024-01-06\"] | date parse | date format \"%d\"\n[date parse \"2024-01-05\"] | to json --compact\n",
+            "[05, 06]\n[\"2024-01-05T00:00:00Z\"]\n",
+        );
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        assert_eval(
+            "$d = date parse \"2024-01-05T09:30:00Z\"\n$d + 1h30m\n$d - 1d\n(date parse \"2024-03-01\") - (date parse \"2024-02-28\")\n",
+            "2024-01-05T11:00:00Z\n2024-01-04T09:30:00Z\n2d\n",
+        );
+        assert_eval(
+            "$d = date parse \"2024-01-05T09:30:00Z\"\n$d < $d + 1ns\n(date parse \"2024-01-05T10:30:00+01:00\") == $d\n",
+            "true\ntrue\n",
+        );
+    }
+
+    #[test]
+    fn test_time_zones() {
+        assert_eval(
+            "$d = date parse \"2024-01-05T09:30:00Z\"\n$d | date to-offset \"+05:30\"\ndate parse \"2024-01-05T10:30:00+01:00\" | date to-utc\n",
+            "2024-01-05T15:00:00+05:30\n2024-01-05T09:30:00Z\n",
+        );
+        // A fixed clock gives the time now and the local offset.
+        assert_eval(
+            "set --now \"2024-06-01T12:00:00-04:00\"\ndate now\ndate parse \"2024-01-05T09:30:00Z\" | date to-local\n",
+            "2024-06-01T12:00:00-04:00\n2024-01-05T05:30:00-04:00\n",
+        );
+        // Bare `date` is `date now`, and `date +<format>` formats it.
+        assert_eval(
+            "set --now \"2024-06-01T12:00:00-04:00\"\ndate | typeof\ndate +%Y-%m-%dT%H:%M\n",
+            "date\n2024-06-01T12:00\n",
+        );
+    }
+
+    #[test]
+    fn test_math_aggregates() {
+        assert_eval(
+            "1..=10 | math sum\n[1, 2.5] | math sum\n[] | math sum\n[1, 2, 3, 4] | math avg\n",
+            "55\n3.5\n0\n2.5\n",
+        );
+        assert_eval(
+            "[3, -1, 2] | math min\n[3, -1, 2.5] | math max\nmath max 3 9 4\nmath min [4, 2] 3\n",
+            "-1\n3\n9\n2\n",
+        );
+        assert_eval(
+            "[3, 1, 2] | math median\n[4, 1, 3, 2] | math median\n",
+            "2\n2.5\n",
+        );
+        assert_eval(
+            "$xs = [2, 4, 4, 4, 5, 5, 7, 9]\n$xs | math stddev\n$xs | math stddev --sample | math round 4\n",
+            "2\n2.1381\n",
+        );
+        let path = temp_file(
+            "oysterlang-test-math.json",
+            "[{\"size\": 10}, {\"size\": 32}]",
+        );
+        assert_eval(&format!("json {} | get size | math sum\n", path), "42\n");
+    }
+}
+
+mod errors {
+
+    use crate::error::{Error, Span};
+    use crate::{eval, report, report_in};
+
+    #[test]
+    fn test_lex_error() {
+        match eval("1 + ^^\n") {
+            Err(Error::Lex(raw, span)) => {
+                assert_eq!(raw, "^^\n");
+                assert_eq!(span, Span::new(4, 7));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_int_literal_out_of_range() {
+        for (input, span) in [
+            ("1 + 9223372036854775808\n", Span::new(4, 23)),
+            ("$x = 0xffff_ffff_ffff_ffff\n", Span::new(5, 26)),
+        ] {
+            match eval(input) {
+                Err(Error::Parse(msg, Some(at))) => {
+                    assert_eq!(msg, "Integer literal out of range.");
+                    assert_eq!(at, span);
+                }
+                other => panic!("{} gave {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        match eval("1 + 2 )\n") {
+            Err(Error::Parse(_, Some(span))) => assert_eq!(span, Span::new(6, 7)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_type_error_has_span() {
+        let input = "$a = 1\n$a + \"x\"\n";
+        match eval(input) {
+            Err(err @ Error::Type(_, Some(_))) => {
+                assert_eq!(
+                    report(&err, input),
+                    "2:1: Cannot add an int and a string. Use `into` to convert one of them, as in `\"42\" | into int`."
+                )
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_report_in_script() {
+        let input = "$a = 1\n$a + \"x\"\n";
+        let err = eval(input).unwrap_err();
+        assert!(report_in("s.oy", &err, input).starts_with("s.oy:2:1: Cannot add"));
+        let input = "\"a\" + 1\n";
+        let err = eval(input).unwrap_err();
+        assert!(report_in("s.oy", &err, input).starts_with("s.oy:1:1: Cannot add"));
+        let input = "$x = [1, 2 // 0]\n";
+        let err = eval(input).unwrap_err();
+        assert!(report_in("s.oy", &err, input).starts_with("s.oy:1:10: Integer division"));
+    }
+
+    #[test]
+    fn test_command_not_found() {
+        match eval("not-a-real-command.exe\n") {
+            Err(Error::CommandNotFound(name, _, Some(span))) => {
+                assert_eq!(name, "not-a-real-command.exe");
+                assert_eq!(span, Span::new(0, 22));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_file_command_safety() {
+        let dir = crate::tests::eval::temp_dir("oysterlang-test-safety");
+        std::fs::create_dir(format!("{}/sub", dir)).unwrap();
+        std::fs::write(format!("{}/a", dir), "a").unwrap();
+        std::fs::write(format!("{}/b", dir), "b").unwrap();
+        for (input, msg) in [
+            (
+                format!("rm \"{}/sub\"", dir),
+                format!(
+                    "{}/sub: is a directory (use --recursive to include what is in it)",
+                    dir
+                ),
+            ),
+            (
+                format!("cp \"{0}/a\" \"{0}/b\"", dir),
+                format!("{}/b: already exists (use --force to replace it)", dir),
+            ),
+            (
+                format!("mv \"{0}/a\" \"{0}/b\"", dir),
+                format!("{}/b: already exists (use --force to replace it)", dir),
+            ),
+            (
+                format!("\"x\" | save \"{}/a\"", dir),
+                format!("{}/a: already exists (use --force to replace it)", dir),
+            ),
+            (
+                format!("cp \"{0}\" \"{0}/sub\" -r", dir),
+                format!("Cannot copy {} into itself.", dir),
+            ),
+            (
+                String::from("rm \"..\" -r"),
+                String::from("Refusing to remove ..."),
+            ),
+            (
+                String::from("rm \"/\" -r -f"),
+                String::from("Refusing to remove /."),
+            ),
+            (
+                String::from("open \"a\" -l"),
+                String::from("usage: open <path> [--raw]"),
+            ),
+        ] {
+            match eval(&format!("{}\n", input)) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+        match eval(&format!(
+            "mv \"{0}/a\" \"{0}/b\" --force\nopen \"{0}/b\"\nls \"{0}\" | count\n",
+            dir
+        )) {
+            Ok(out) => assert_eq!(out, "a\n2\n"),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn test_string_errors() {
+        for (input, msg) in [
+            ("1 | upper\n", "upper works on text, not an int."),
+            (
+                "\"a\" | pad-start 3 \"ab\"\n",
+                "usage: pad-start <width> [character]",
+            ),
+            (
+                "\"a\" | format \"{} {\"\n",
+                "Invalid format string {} {: unclosed {.",
+            ),
+            (
+                "\"a\" | format \"{x}\"\n",
+                "Invalid format string {x}: there is no value for {x}.",
+            ),
+            (
+                "1 | format \"{:>x}\"\n",
+                "Invalid format string {:>x}: cannot read >x.",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_too_long() {
+        for (input, span) in [
+            ("\"ab\" | repeat 9223372036854775807\n", Span::new(14, 33)),
+            ("\"a\" | pad-end 9223372036854775807\n", Span::new(14, 33)),
+        ] {
+            match eval(input) {
+                Err(Error::Arith(msg, Some(at))) => {
+                    assert!(msg.contains("would make a string longer than"));
+                    assert_eq!(at, span);
+                }
+                other => panic!("{} gave {:?}", input, other),
+            }
+        }
+        match eval("1 | format \"{:99999999999}\"\n") {
+            Err(Error::Arg(msg, _)) => assert!(msg.contains("cannot read 99999999999")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        let input = "$ok = 1\n\"a\" =~ r\"(\"\n";
+        match eval(input) {
+            Err(err @ Error::Arg(_, Some(_))) => {
+                assert_eq!(report(&err, input), "2:8: Invalid regex (: unclosed group.")
+            }
+            _ => unreachable!(),
+        }
+        match eval("1 =~ \"1\"\n") {
+            Err(err @ Error::Type(_, _)) => {
+                assert_eq!(format!("{}", err), "Cannot match an int against a pattern.")
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_unit_errors() {
+        for (input, msg) in [
+            ("1kb + 1\n", "Cannot add a size and an int."),
+            ("1s * 1kb\n", "Cannot multiply a duration and a size."),
+            ("1 / 1s\n", "Cannot divide an int and a duration."),
+            ("1s / 0\n", "Division by zero."),
+            ("1s % 0s\n", "Division by zero."),
+            ("1kb < 1s\n", "Cannot compare a size and a duration."),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_null_errors() {
+        for (input, msg) in [
+            ("null + 1\n", "Cannot add null and an int."),
+            (
+                "$x = $missing\n$x * 2\n",
+                "Cannot multiply null and an int.",
+            ),
+            ("2 ** null\n", "Cannot raise an int to null."),
+            ("null < 1\n", "Cannot compare null and an int."),
+            ("1kb - null\n", "Cannot subtract a size and null."),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_assignment_errors() {
+        for (input, msg) in [
+            (
+                "[$a, $b] = [1]\n",
+                "1:1: The pattern on the left does not fit the array [1].",
+            ),
+            ("$x = 1\n$x.a = 2\n", "2:1: Cannot set field a of an int."),
+            (
+                "$xs = [1]\n$xs[3] = 1\n",
+                "2:1: Index 3 is out of range for length 1.",
+            ),
+            ("$s = \"a\"\n$s[0] = \"b\"\n", "2:1: Cannot set an item of a string."),
+            ("$n = \"a\"\n$n -= 1\n", "2:1: Cannot subtract a string and an int. Use `into` to convert one of them, as in `\"42\" | into int`."),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(report(&err, input), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_immutable_errors() {
+        for (input, msg) in [
+            (
+                "let $x = 1\n$x = 2\n",
+                "2:1: Cannot assign to $x, which was bound with `let`. Bind it with `let mut` to change it.",
+            ),
+            ("const $x = 1\n$x += 1\n", "2:1: Cannot assign to the constant $x."),
+            (
+                "const $x = 1\nif true { let $x = 2 }\n",
+                "2:11: Cannot bind $x again, since it is a constant.",
+            ),
+            (
+                "let $p = 1\n$p, $q = 2, 3\n",
+                "2:1: Cannot assign to $p, which was bound with `let`. Bind it with `let mut` to change it.",
+            ),
+            (
+                "let [$a] = [1, 2]\n",
+                "1:1: The pattern on the left does not fit the array [1, 2].",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(report(&err, input), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+        match eval("const $x = 1\ntry { $x = 2 } catch $e { $e.kind }\n") {
+            Ok(out) => assert_eq!(out, "immutable\n"),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = crate::tests::eval::temp_dir("oysterlang-test-import-errors");
+        let write = |name: &str, text: &str| {
+            let path = format!("{}/{}", dir, name);
+            std::fs::write(&path, text).unwrap();
+            path
+        };
+        let a = write("oysterlang-cycle-a.oy", "import \"oysterlang-cycle-b\"\n");
+        let b = write(
+            "oysterlang-cycle-b.oy",
+            "$x = 1\nimport \"./oysterlang-cycle-a.oy\"\n",
+        );
+        let input = format!("import \"{}\"\n", a);
+        match eval(&input) {
+            Err(err) => {
+                assert_eq!(err.kind(), "import");
+                assert_eq!(
+                    report(&err, &input),
+                    format!("1:1: {}:1:1: {}:2:1: Circular import: oysterlang-cycle-a.oy imports oysterlang-cycle-b.oy, which imports oysterlang-cycle-a.oy.", a, b)
+                );
+            }
+            Ok(out) => panic!("gave {}", out),
+        }
+
+        let broken = write("oysterlang-broken.oy", "$y = \"a\"\n$y - 1\n");
+        let input = format!("$x = 1\nuse \"{}\"\n", broken);
+        match eval(&input) {
+            Err(err) => {
+                assert_eq!(err.kind(), "type");
+                let cause = std::error::Error::source(&err).unwrap();
+                assert!(cause.to_string().starts_with("Cannot subtract"));
+                assert_eq!(
+                    report(&err, &input),
+                    format!("2:1: {}:2:1: Cannot subtract a string and an int. Use `into` to convert one of them, as in `\"42\" | into int`.", broken)
+                );
+            }
+            Ok(out) => panic!("gave {}", out),
+        }
+
+        match eval("1\nimport \"oysterlang-missing\"\n") {
+            Err(err) => assert_eq!(
+                report(&err, "1\nimport \"oysterlang-missing\"\n"),
+                "2:1: Cannot find the script \"oysterlang-missing\"."
+            ),
+            Ok(out) => panic!("gave {}", out),
+        }
+
+        let thrower = write("oysterlang-thrower.oy", "throw \"boom\"\n");
+        match eval(&format!(
+            "try {{ import \"{}\" }} catch $e {{ $e.message }}\n",
+            thrower
+        )) {
+            Ok(out) => assert_eq!(out, format!("{}:1:1: boom\n", thrower)),
+            Err(err) => panic!("{}", err),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_match_errors() {
+        for (input, msg) in [
+            (
+                "$y = 1\n$x = match 5 { 1..3 => 1 }\n",
+                "2:6: No arm of the match fits the int 5.",
+            ),
+            (
+                "match \"b\" { \"a\" => 1 }\n",
+                "1:1: No arm of the match fits the string \"b\".",
+            ),
+            (
+                "match null { [] => 1 }\n",
+                "1:1: No arm of the match fits null.",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(report(&err, input), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        for (input, msg) in [
+            ("\"abc\" | into int\n", "Cannot convert \"abc\" into an int."),
+            ("[1, \"x\"] | into float\n", "Cannot convert \"x\" into a float."),
+            ("\"maybe\" | into bool\n", "Cannot convert \"maybe\" into a bool."),
+            ("{ || 1 } | into int\n", "Cannot convert a closure into an int."),
+            ("2.0 ** 70 | into int\n", "Cannot convert a float into an int."),
+            (
+                "\"x\" * 2\n",
+                "Cannot multiply a string and an int. Use `into` to convert one of them, as in `\"42\" | into int`.",
+            ),
+            ("-(true)\n", "Cannot negate a bool."),
+            (
+                "into frob 1\n",
+                "usage: into int|float|string|bool|path, or into date [--format <format>]",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_date_errors() {
+        for (input, msg) in [
+            (
+                "date parse \"2024-02-30\"\n",
+                "Cannot read \"2024-02-30\" as an ISO 8601 date.",
+            ),
+            (
+                "date parse \"5/1\" --format \"%d/%m/%Y\"\n",
+                "Cannot read \"5/1\" as a date in the format %d/%m/%Y.",
+            ),
+            (
+                "date now | date format \"%Q\"\n",
+                "Invalid date format %Q: %Q is not a directive.",
+            ),
+            (
+                "date now | date to-offset \"+25:00\"\n",
+                "Invalid UTC offset +25:00: expected one such as +01:00 or Z.",
+            ),
+            (
+                "5 | date format \"%Y\"\n",
+                "date format works on dates, not an int.",
+            ),
+            ("(date now) + 1\n", "Cannot add a date and an int."),
+            (
+                "set --now \"yesterday\"\n",
+                "Option --now: Cannot read \"yesterday\" as an ISO 8601 date.",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+    }
+
+    #[test]
+    fn test_math_errors() {
+        for (input, msg) in [
+            (
+                "\"x\" | math abs\n",
+                "math abs works on numbers, not a string.",
+            ),
+            (
+                "[1, \"2\"] | math sum\n",
+                "math sum works on numbers, not a string.",
+            ),
+            ("-4 | math sqrt\n", "Cannot take the square root of -4."),
+            ("0 | math log\n", "Cannot take the logarithm of 0."),
+            ("8 | math log 1\n", "Cannot take a logarithm to base 1."),
+            (
+                "2 | math asin\n",
+                "Cannot take the asin of 2, which is outside -1 to 1.",
+            ),
+            ("[] | math avg\n", "math avg needs at least 1 number."),
+            (
+                "[1] | math stddev --sample\n",
+                "math stddev needs at least 2 numbers.",
+            ),
+            ("2 | math pow 64\n", "Integer overflow."),
+            (
+                "10.0 ** 300 | math pow 2 | math floor\n",
+                "inf does not fit in an int.",
+            ),
+        ] {
+            match eval(input) {
+                Err(err) => assert_eq!(format!("{}", err), msg),
+                Ok(out) => panic!("{} gave {}", input, out),
+            }
+        }
+        match eval("math cube 2\n") {
+            Err(Error::Arg(msg, _)) => assert!(msg.starts_with("usage: math abs|")),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_rev_overflow() {
+        match eval("(0..1).step(-9_223_372_036_854_775_807 - 1).rev()\n") {
+            Err(Error::Arith(msg, _)) => assert_eq!(msg, "Integer overflow."),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_in_stream() {
+        match eval("1..5 | map { |$x| $x // 0 } | first\n") {
+            Err(Error::Arith(msg, Some(span))) => {
+                assert_eq!(msg, "Integer division by zero.");
+                assert_eq!(span, Span::new(18, 25));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_json_error_location() {
+        let path = std::env::temp_dir().join("oysterlang-test-bad.json");
+        std::fs::write(&path, "{\"a\": [1, 2],\n  \"b\" 3}").unwrap();
+        match eval(&format!("cat {} | from json\n", path.display())) {
+            Err(Error::Format(msg, Some(_))) => assert_eq!(
+                msg,
+                "Invalid JSON at line 2, column 7: expected ':' after an object key."
+            ),
+            _ => unreachable!(),
+        }
+        match crate::json::parse("[1, 2") {
+            Err(Error::Format(msg, None)) => assert_eq!(
+                msg,
+                "Invalid JSON at line 1, column 6: expected ',' or ']' in an array."
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_data_format_errors() {
+        let cases = [
+            (
+                crate::csv::parse("a,b\n1,\"x\n2,3\n", ',', true),
+                "Invalid CSV at line 2, column 3: unterminated quoted cell.",
+            ),
+            (
+                crate::csv::parse("a,b\n1,2\n3\n", ',', true),
+                "Invalid CSV at line 3, column 1: expected 2 fields but found 1.",
+            ),
+            (
+                crate::toml::parse("[t]\nb = 2\nb = 3\n"),
+                "Invalid TOML at line 3, column 1: b is defined twice.",
+            ),
+            (
+                crate::toml::parse("x = 01\n"),
+                "Invalid TOML at line 1, column 5: expected a value.",
+            ),
+            (
+                crate::yaml::parse("a:\n  b: 1\n   c: 2\n"),
+                "Invalid YAML at line 3, column 4: unexpected indentation.",
+            ),
+            (
+                crate::yaml::parse("a: &anchor 1\n"),
+                "Unsupported YAML feature at line 1, column 4: anchors.",
+            ),
+            (
+                crate::yaml::parse("a: 1\nb:\n  - *a\n"),
+                "Unsupported YAML feature at line 3, column 5: aliases.",
+            ),
+            (
+                crate::yaml::parse("&a key: 1\n"),
+                "Unsupported YAML feature at line 1, column 1: anchors.",
+            ),
+            (
+                crate::yaml::parse("? [a, b]\n: 1\n"),
+                "Unsupported YAML feature at line 1, column 1: complex keys.",
+            ),
+        ];
+        for (result, expected) in cases.iter() {
+            match result {
+                Err(Error::Format(msg, None)) => assert_eq!(msg, expected),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_closure_arity() {
+        match eval("[1] | reduce 0 { |$x| $x }\n") {
+            Err(Error::Arg(msg, _)) => {
+                assert_eq!(msg, "The closure takes 1 arguments but was given 2.")
+            }
+            _ => unreachable!(),
+        }
+        match eval("$f = { |$x| $x }\n$f(1, 2)\n") {
+            Err(Error::Arg(_, Some(span))) => assert_eq!(span, Span::new(17, 25)),
+            _ => unreachable!(),
+        }
+        match eval("$n = 1\n$n(2)\n") {
+            Err(Error::Type(msg, _)) => assert_eq!(msg, "Cannot call an int."),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_index_error() {
+        match eval("$a = [1, 2]\n$a[2]\n") {
+            Err(Error::Index(msg, Some(span))) => {
+                assert_eq!(msg, "Index 2 is out of range for length 2.");
+                assert_eq!(span, Span::new(12, 17));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error as _;
+        let err = eval("not-a-real-command.exe\n").unwrap_err();
+        assert!(err.source().is_some());
+    }
+}
+
+mod check {
+
+    use crate::check;
+    use crate::{parser, tokens};
+
+    fn warnings(input: &str) -> Vec<String> {
+        let (ts, spans) = tokens::tokenize_with_spans(input).unwrap();
+        let prog = parser::parse_with_spans(&ts, &spans).unwrap();
+        check::warnings(&prog)
+            .into_iter()
+            .map(|(span, msg)| {
+                let (line, col) = span.line_col(input);
+                format!("{}:{}: {}", line, col, msg)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_uncovered_bools_and_nulls() {
+        assert_eq!(
+            warnings("$ok = 1 < 2\nmatch $ok { true => 1 }\nmatch $ok { true => 1, false => 0 }\n"),
+            vec!["2:1: This match on a bool does not cover false."]
+        );
+        assert_eq!(
+            warnings("match null { \"a\" => 1 }\nmatch null { null => 1 }\n"),
+            vec!["1:1: This match does not cover null."]
+        );
+    }
+
+    #[test]
+    fn test_uncovered_types() {
+        assert_eq!(
+            warnings("match 3 { 1 => 1, $n if $n > 2 => 2 }\nmatch 3 { int => 1 }\nmatch 3 { $n => 1 }\n"),
+            vec!["1:1: This match does not cover every int; add a `_` arm for the rest."]
+        );
+        assert_eq!(
+            warnings("match [1] { [] => 0, [$x] => 1 }\nmatch [1] { [] => 0, [$x, ..] => 1 }\n"),
+            vec!["1:1: This match does not cover every array; add a `_` arm for the rest."]
+        );
+    }
+
+    #[test]
+    fn test_unknown_types_are_not_checked() {
+        // `$x` may be anything once the block assigns it, and `$n` is only known to the closure.
+        assert!(warnings(
+            "$x = true\nif $y { $x = 5 }\nmatch $x { true => 1 }\nmatch $z { 1 => 1 }\n"
+        )
+        .is_empty());
+        assert_eq!(
+            warnings("$n = 1\n$f = { |$n| match $n { 1 => 1 } }\nmatch $n { 2 => 2 }\n").len(),
+            1
+        );
+    }
+}
+
+mod strict {
+
+    use crate::error::{Error, Span};
+    use crate::interpreter::Options;
+    use crate::{eval, eval_with_options};
+
+    fn strict() -> Options {
+        let mut opts = Options::default();
+        opts.set_strict(true);
+        opts
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        assert_eq!(eval("$nope\n").unwrap(), "");
+        match eval_with_options("$a = 1\n$a + $nope\n", strict()) {
+            Err(Error::Name(name, Some(span))) => {
+                assert_eq!(name, "$nope");
+                assert_eq!(span, Span::new(12, 17));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_set_strict() {
+        match eval("set --strict\n$nope\n") {
+            Err(Error::Name(_, _)) => (),
+            _ => unreachable!(),
+        }
+        assert_eq!(eval("set --strict\nset --no-strict\n$nope\n").unwrap(), "");
+    }
+
+    #[test]
+    fn test_aborts_on_failed_command() {
+        match eval_with_options("bash -c \"exit 4\"\n1\n", strict()) {
+            Err(Error::ExitStatus(name, 4, _)) => assert_eq!(name, "bash"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_condition_may_fail() {
+        let res = eval_with_options("if bash -c \"exit 4\" { 1 } else { 2 }\n", strict());
+        assert_eq!(res.unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_pipeline_stage_failure() {
+        match eval_with_options("bash -c \"exit 3\" | cat\n", strict()) {
+            Err(Error::ExitStatus(name, 3, _)) => assert_eq!(name, "bash"),
+            _ => unreachable!(),
+        }
+    }
+}
+
+mod trace {
+
+    use crate::trace::{quote, timestamp};
+    use crate::{eval, eval_with_options, interpreter::Options};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("ls"), "ls");
+        assert_eq!(quote("./dir/file.txt"), "./dir/file.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("a\nb"), r"$'a\nb'");
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(3_723_045);
+        assert_eq!(timestamp(time), "01:02:03.045");
+    }
+
+    #[test]
+    fn test_trace_to_file() {
+        let path = std::env::temp_dir().join("oysterlang-test-trace.log");
+        let _ = fs::remove_file(&path);
+        let input = format!(
+            "set -x --trace-file {}\n$a = \"x y\"\nbash -c \"exit 0\" $a | cat\n",
+            path.to_str().unwrap()
+        );
+        eval(&input).unwrap();
+        let trace = fs::read_to_string(&path).unwrap();
+        assert_eq!(trace, "+ 3: bash -c 'exit 0' 'x y' | cat\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_trace_timing() {
+        let path = std::env::temp_dir().join("oysterlang-test-trace-timing.log");
+        let _ = fs::remove_file(&path);
+        let opts = Options {
+            xtrace: true,
+            trace_timing: true,
+            trace_file: Some(path.clone()),
+            ..Default::default()
+        };
+        eval_with_options("bash -c \"exit 2\"\n", opts).unwrap();
+        let trace = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("+ ") && lines[0].ends_with(" 1: bash -c 'exit 2'"));
+        assert!(lines[1].starts_with("- ") && lines[1].contains(" 1: exit 2 in "));
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+mod bench {
+    use crate::eval;
+    use test::Bencher;
+
+    /// How long a loop of a million iterations takes, which is only measured.
+    #[bench]
+    fn bench_million_iterations(b: &mut Bencher) {
+        b.iter(|| {
+            match eval("let mut $sum = 0\nfor $i in 0..1000000 {\n  $sum += $i\n}\n$sum\n") {
+                Ok(out) => assert_eq!(out, "499999500000\n"),
+                _ => unreachable!(),
+            }
+        });
+    }
 }
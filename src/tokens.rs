@@ -1,3 +1,4 @@
+use crate::error::{Error, Span};
 use regex::Regex;
 use std::cmp::min;
 
@@ -5,11 +6,34 @@ use std::cmp::min;
 pub enum Token {
     At,
     Amp,
+    AmpAmp,
     Ast,
+    AstAst,
+    Bang,
+    BangEq,
+    BangTilde,
+    EqEq,
+    EqTilde,
+    FatArrow,
+    Underscore,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    PipePipe,
+    QuestionQuestion,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     LParen,
     RParen,
     Plus,
     Minus,
+    PlusEq,
+    MinusEq,
+    AstEq,
     Mod,
     Eq,
     Pipe,
@@ -21,10 +45,36 @@ pub enum Token {
     Comma,
     Semi,
     Slash,
+    DoubleSlash,
+    DotDot,
+    DotDotEq,
+    /// A `.` directly after a value, as in `(1..10).rev()`.
+    Dot,
     NewLine,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    If,
+    Else,
+    For,
+    In,
+    Let,
+    Mut,
+    Const,
+    Match,
+    Import,
+    Use,
+    Source,
+    Export,
+    As,
+    Null,
+    True,
+    False,
     Var(String),
     Num(String),
     Str(String),
+    Regex(String),
     Path(String),
     Param(String),
 }
@@ -36,54 +86,208 @@ pub enum Case {
 
 lazy_static! {
     static ref SPACE_REGEX: Regex = Regex::new(r"^[ \t\r]+").unwrap();
-    static ref VAR_REGEX: Regex = Regex::new(r"^\$[A-z0-9_?]+").unwrap();
-    static ref NUM_REGEX: Regex = Regex::new(r"^\d+(?:\.\d+)?").unwrap();
+    static ref VAR_REGEX: Regex = Regex::new(r"^\$[[:alnum:]_?]+(\.[[:alpha:]_][[:alnum:]_]*)*").unwrap();
+    static ref NUM_REGEX: Regex =
+        Regex::new(&format!(
+            r"^(?:0x[[:xdigit:]][[:xdigit:]_]*|0o[0-7][0-7_]*|0b[01][01_]*|{dec}(?:{size}\b|{dur}(?:{dec}{dur})*\b)?)",
+            // A size or duration unit may follow a decimal number, and a duration may go on with more.
+            dec = r"\d[\d_]*(?:\.\d[\d_]*)?",
+            size = r"(?i:[kmgtp]i?b|b)",
+            dur = r"(?:ns|us|µs|ms|sec|min|hr|day|wk|s|m|h|d)",
+        )).unwrap();
     static ref STR_REGEX: Regex = Regex::new("^\"[^\"]*\"").unwrap();
+    static ref REGEX_REGEX: Regex = Regex::new("^r\"[^\"]*\"").unwrap();
     static ref FILE_PATH_REGEX: Regex =
-        Regex::new(r#"^(((\.?\.?/|~/|[[:alpha:]]:/)?)((\.?[[:print:][^<>:"/\|?*]]+)/?)*(\.[[:alnum:]]+))|^([[:alpha:]][[:alnum:]]*)"#).unwrap();
+        Regex::new(r#"^(((\.?\.?/|~/|[[:alpha:]]:/)?)((\.?[^\s<>:"/|?*{}()\[\];,$=]+)/?)*(\.[[:alnum:]]+))|^([[:alpha:]][[:alnum:]]*(-[[:alnum:]]+)*)"#).unwrap();
     // static ref PATH_REGEX: Regex =
     //     Regex::new(r#"^(((\.\.?|~|[[:alpha:]]:|\\)(\\\.?[[:print:][^<>:"/\|?*]]+)+)|((\.\.?|~)?(/\.?[[:alnum:]]+)+))(\.[[:alnum:]]+)?|(\.\.?|~|/|[[:alpha:]]:\\)"#).unwrap();
     static ref PARAM_REGEX: Regex = Regex::new(r"^--?[[:alpha:]]+(-[[:alpha:]]+)*").unwrap();
+    // A program run in place of the builtin of the same name, as in `^ls -la`.
+    static ref PROGRAM_REGEX: Regex = Regex::new(r"^\^[[:alpha:]][[:alnum:]]*(-[[:alnum:]]+)*").unwrap();
+    static ref BIT_OP_REGEX: Regex = Regex::new(r"^bit-(and|or|xor|shl|shr)\b").unwrap();
     static ref CASES: Vec<Case> = vec![
         Case::Pat(&VAR_REGEX, Token::Var),
         Case::Pat(&NUM_REGEX, Token::Num),
         Case::Pat(&STR_REGEX, Token::Str),
         Case::Pat(&PARAM_REGEX, Token::Param),
-        Case::Pat(&FILE_PATH_REGEX,Token::Path),
+        Case::Pat(&PROGRAM_REGEX, Token::Path),
+        Case::Pat(&BIT_OP_REGEX, bit_op),
+        Case::Pat(&REGEX_REGEX, Token::Regex),
+        Case::Pat(&FILE_PATH_REGEX, path_or_keyword),
         Case::Sym("\n", Token::NewLine),
         Case::Sym("(", Token::LParen),
         Case::Sym(")", Token::RParen),
         Case::Sym("[", Token::LSq),
         Case::Sym("]", Token::RSq),
+        Case::Sym("+=", Token::PlusEq),
         Case::Sym("+", Token::Plus),
+        Case::Sym("-=", Token::MinusEq),
         Case::Sym("-", Token::Minus),
         Case::Sym("%", Token::Mod),
+        Case::Sym("==", Token::EqEq),
+        Case::Sym("=~", Token::EqTilde),
+        Case::Sym("=>", Token::FatArrow),
         Case::Sym("=", Token::Eq),
+        Case::Sym("!=", Token::BangEq),
+        Case::Sym("!~", Token::BangTilde),
+        Case::Sym("!", Token::Bang),
+        Case::Sym("<=", Token::LtEq),
+        Case::Sym("<", Token::Lt),
+        Case::Sym(">=", Token::GtEq),
+        Case::Sym(">", Token::Gt),
+        Case::Sym("||", Token::PipePipe),
+        Case::Sym("??", Token::QuestionQuestion),
         Case::Sym("|", Token::Pipe),
         Case::Sym(":", Token::Colon),
+        Case::Sym("_", Token::Underscore),
         Case::Sym(";", Token::Semi),
         Case::Sym("{", Token::LCurl),
         Case::Sym("}", Token::RCurl),
         Case::Sym(",", Token::Comma),
+        Case::Sym("**", Token::AstAst),
+        Case::Sym("*=", Token::AstEq),
         Case::Sym("*", Token::Ast),
+        Case::Sym("//", Token::DoubleSlash),
         Case::Sym("/", Token::Slash),
         Case::Sym("@", Token::At),
+        Case::Sym("&&", Token::AmpAmp),
         Case::Sym("&", Token::Amp),
     ];
 }
 
+/// Bare words lex as paths (command names) unless they are reserved. `true` and `false` are
+/// values, so the programs of those names run as `^true` and `^false`.
+fn path_or_keyword(s: String) -> Token {
+    match s.as_str() {
+        "try" => Token::Try,
+        "catch" => Token::Catch,
+        "finally" => Token::Finally,
+        "throw" => Token::Throw,
+        "if" => Token::If,
+        "else" => Token::Else,
+        "for" => Token::For,
+        "in" => Token::In,
+        "let" => Token::Let,
+        "mut" => Token::Mut,
+        "const" => Token::Const,
+        "match" => Token::Match,
+        "import" => Token::Import,
+        "use" => Token::Use,
+        "source" => Token::Source,
+        "export" => Token::Export,
+        "as" => Token::As,
+        "null" => Token::Null,
+        "true" => Token::True,
+        "false" => Token::False,
+        _ => Token::Path(s),
+    }
+}
+
+/// The word a keyword was written as, for keywords that stand for nothing but syntax.
+pub fn keyword_word(t: &Token) -> Option<&'static str> {
+    match t {
+        Token::Try => Some("try"),
+        Token::Catch => Some("catch"),
+        Token::Finally => Some("finally"),
+        Token::Throw => Some("throw"),
+        Token::If => Some("if"),
+        Token::Else => Some("else"),
+        Token::For => Some("for"),
+        Token::In => Some("in"),
+        Token::Let => Some("let"),
+        Token::Mut => Some("mut"),
+        Token::Const => Some("const"),
+        Token::Match => Some("match"),
+        Token::Import => Some("import"),
+        Token::Use => Some("use"),
+        Token::Source => Some("source"),
+        Token::Export => Some("export"),
+        Token::As => Some("as"),
+        _ => None,
+    }
+}
+
+/// The text `t` was read from.
+pub fn text(t: &Token) -> String {
+    let sym = CASES.iter().find_map(|case| match case {
+        Case::Sym(s, sym) if sym == t => Some(*s),
+        _ => None,
+    });
+    let word = match t {
+        Token::Var(s)
+        | Token::Num(s)
+        | Token::Str(s)
+        | Token::Regex(s)
+        | Token::Path(s)
+        | Token::Param(s) => return s.clone(),
+        Token::Dot => ".",
+        Token::DotDot => "..",
+        Token::DotDotEq => "..=",
+        Token::Null => "null",
+        Token::True => "true",
+        Token::False => "false",
+        Token::BitAnd => "bit-and",
+        Token::BitOr => "bit-or",
+        Token::BitXor => "bit-xor",
+        Token::Shl => "bit-shl",
+        Token::Shr => "bit-shr",
+        t => keyword_word(t).or(sym).unwrap_or_default(),
+    };
+    String::from(word)
+}
+
+/// Bitwise operators are words, since `|` is taken by pipes.
+fn bit_op(s: String) -> Token {
+    match s.as_str() {
+        "bit-and" => Token::BitAnd,
+        "bit-or" => Token::BitOr,
+        "bit-xor" => Token::BitXor,
+        "bit-shl" => Token::Shl,
+        _ => Token::Shr,
+    }
+}
+
 struct Lexer<'a> {
     pub input: &'a str,
+    source: &'a str,
 }
 
 impl Lexer<'_> {
-    pub fn new(input: &str) -> Lexer {
-        Lexer { input }
+    pub fn new(input: &str) -> Lexer<'_> {
+        Lexer {
+            input,
+            source: input,
+        }
     }
 
-    fn next(&mut self) -> Option<Token> {
+    fn offset(&self) -> usize {
+        self.source.len() - self.input.len()
+    }
+
+    /// The next token, where `glued` says whether it directly follows a value such as `$x` or
+    /// `(...)` with no space between.
+    fn next(&mut self, glued: bool) -> Option<Token> {
         self.skip_whitespace();
 
+        if let Some(some) = self.take_range() {
+            return Some(some);
+        }
+
+        // A `-` before a digit is a minus sign, though `-1.5` would otherwise read as a path.
+        if self.input.starts_with('-') && self.input[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            return self.take_sym("-", Token::Minus);
+        }
+
+        // `.name` after a value is a method or field; elsewhere it starts a path like `.gitignore`.
+        if glued && self.input.starts_with('.') {
+            if let Some(c) = self.input[1..].chars().next() {
+                if c.is_alphabetic() {
+                    return self.take_sym(".", Token::Dot);
+                }
+            }
+        }
+
         for case in CASES.iter() {
             match case {
                 Case::Sym(s, token) => {
@@ -103,6 +307,17 @@ impl Lexer<'_> {
         None
     }
 
+    /// `..` and `..=`, unless they start a relative path such as `../bin`.
+    fn take_range(&mut self) -> Option<Token> {
+        if self.input.starts_with("..") && !self.input[2..].starts_with('/') {
+            if let Some(some) = self.take_sym("..=", Token::DotDotEq) {
+                return Some(some);
+            }
+            return self.take_sym("..", Token::DotDot);
+        }
+        None
+    }
+
     fn take_sym(&mut self, s: &str, token: Token) -> Option<Token> {
         let input_len = self.input.len();
         let s_len = s.len();
@@ -137,28 +352,60 @@ impl Lexer<'_> {
     }
 }
 
-#[derive(Debug)]
-pub struct LexError<'a> {
-    raw: &'a str,
+#[cfg(test)]
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    tokenize_with_spans(input).map(|(ts, _)| ts)
 }
 
-impl std::fmt::Display for LexError<'_> {
-    fn fmt<'a>(&self, f: &mut std::fmt::Formatter<'a>) -> std::fmt::Result {
-        let n = min(10, self.raw.len());
-        write!(f, "Unexpected token {}...", &self.raw[..n])
-    }
+/// Whether a number token fits in an int, if it is an integer literal at all.
+fn int_in_range(num: &str) -> bool {
+    let digits = num.replace('_', "");
+    // `0b` alone is a size of no bytes.
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") if digits.len() > 2 => (16, &digits[2..]),
+        Some("0o") if digits.len() > 2 => (8, &digits[2..]),
+        Some("0b") if digits.len() > 2 => (2, &digits[2..]),
+        _ if digits.bytes().all(|b| b.is_ascii_digit()) => (10, digits.as_str()),
+        _ => return true,
+    };
+    i64::from_str_radix(digits, radix).is_ok()
 }
 
-pub fn tokenize<'a>(input: &'a str) -> Result<Vec<Token>, LexError<'a>> {
-    let mut vec: Vec<Token> = vec![];
+pub fn tokenize_with_spans(input: &str) -> Result<(Vec<Token>, Vec<Span>), Error> {
+    let mut ts: Vec<Token> = vec![];
+    let mut spans: Vec<Span> = vec![];
     let mut lexer = Lexer::new(input);
-    while let Some(some) = lexer.next() {
-        vec.push(some);
+    loop {
+        lexer.skip_whitespace();
+        let start = lexer.offset();
+        let glued = match (ts.last(), spans.last()) {
+            (Some(Token::Var(_) | Token::RParen | Token::RSq), Some(span)) => span.end == start,
+            _ => false,
+        };
+        match lexer.next(glued) {
+            Some(Token::Num(n)) if !int_in_range(&n) => {
+                return Err(Error::Parse(
+                    String::from("Integer literal out of range."),
+                    Some(Span::new(start, lexer.offset())),
+                ));
+            }
+            Some(some) => {
+                ts.push(some);
+                spans.push(Span::new(start, lexer.offset()));
+            }
+            None => break,
+        }
     }
 
     if lexer.input.is_empty() {
-        Ok(vec)
+        Ok((ts, spans))
     } else {
-        Err(LexError { raw: input })
+        let mut n = min(10, lexer.input.len());
+        while !lexer.input.is_char_boundary(n) {
+            n -= 1;
+        }
+        let start = lexer.offset();
+        let raw = String::from(&lexer.input[..n]);
+        Err(Error::Lex(raw, Span::new(start, start + n)))
     }
 }
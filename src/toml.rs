@@ -0,0 +1,651 @@
+use crate::data;
+use crate::error::Error;
+use crate::interpreter::Value;
+use crate::json;
+use regex::Regex;
+
+const MAX_DEPTH: usize = 512;
+
+lazy_static! {
+    static ref DATETIME_REGEX: Regex = Regex::new(
+        r"^(\d{4}-\d{2}-\d{2}([Tt ]\d{2}:\d{2}:\d{2}(\.\d+)?([Zz]|[+-]\d{2}:\d{2})?)?|\d{2}:\d{2}:\d{2}(\.\d+)?)"
+    )
+    .unwrap();
+    static ref BARE_KEY_REGEX: Regex = Regex::new("^[[:alnum:]_-]+$").unwrap();
+}
+
+type Table = Vec<(String, Value)>;
+
+/// Parses a TOML document into a record.
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_trivia();
+        if parser.rest().is_empty() {
+            return Ok(Value::Record(root));
+        }
+        let start = parser.pos;
+        let made = match parser.peek() {
+            Some('[') => match parser.header() {
+                Ok((path, array)) => {
+                    let made = match array {
+                        true => push_table(&mut root, &path),
+                        false => table(&mut root, &path).map(|_| ()),
+                    };
+                    current = path;
+                    made
+                }
+                Err(err) => return Err(err),
+            },
+            _ => match parser.key_value(0) {
+                Ok((path, val)) => match table(&mut root, &current) {
+                    Ok(t) => insert(t, &path, val),
+                    Err(msg) => Err(msg),
+                },
+                Err(err) => return Err(err),
+            },
+        };
+        if let Err(msg) = made {
+            return Err(parser.error(start, &msg));
+        }
+        parser.end_of_line()?;
+    }
+}
+
+/// Writes a record as a TOML document: plain values first, then a `[table]` per nested record and a
+/// `[[table]]` per record in an array of records.
+pub fn to_string(val: &Value) -> Result<String, Error> {
+    match val {
+        Value::Record(fields) => {
+            let mut out = String::new();
+            match write_table(&mut out, &[], fields) {
+                Ok(()) => Ok(String::from(out.trim_end_matches('\n'))),
+                Err(err) => Err(err),
+            }
+        }
+        _ => Err(Error::Type(
+            format!("Cannot write {} as TOML.", val.a_type_name()),
+            None,
+        )),
+    }
+}
+
+/// The table at `path` under `root`, made if it is missing.
+fn table<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut t = root;
+    for key in path {
+        let i = match t.iter().position(|(k, _)| k == key) {
+            Some(i) => i,
+            None => {
+                t.push((key.clone(), Value::Record(Table::new())));
+                t.len() - 1
+            }
+        };
+        t = match &mut t[i].1 {
+            Value::Record(fields) => fields,
+            Value::Arr(vals) => match vals.last_mut() {
+                Some(Value::Record(fields)) => fields,
+                _ => return Err(format!("{} is not a table", key)),
+            },
+            _ => return Err(format!("{} is not a table", key)),
+        };
+    }
+    Ok(t)
+}
+
+fn push_table(root: &mut Table, path: &[String]) -> Result<(), String> {
+    let (key, parent) = match path.split_last() {
+        Some((key, parent)) => (key, parent),
+        None => return Err(String::from("expected a key")),
+    };
+    let t = table(root, parent)?;
+    match t.iter_mut().find(|(k, _)| k == key) {
+        Some((_, Value::Arr(vals))) => vals.push(Value::Record(Table::new())),
+        Some(_) => return Err(format!("{} is not an array of tables", key)),
+        None => t.push((key.clone(), Value::Arr(vec![Value::Record(Table::new())]))),
+    }
+    Ok(())
+}
+
+/// Sets the dotted key `path` in `t` to `val`, making the tables along the way.
+fn insert(t: &mut Table, path: &[String], val: Value) -> Result<(), String> {
+    let (key, parent) = match path.split_last() {
+        Some((key, parent)) => (key, parent),
+        None => return Err(String::from("expected a key")),
+    };
+    let t = table(t, parent)?;
+    match t.iter().any(|(k, _)| k == key) {
+        true => Err(format!("{} is defined twice", key)),
+        false => {
+            t.push((key.clone(), val));
+            Ok(())
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, pos: usize, msg: &str) -> Error {
+        data::error("TOML", self.text, pos, msg)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if let Some('#') = self.peek() {
+            self.pos += self.rest().find('\n').unwrap_or_else(|| self.rest().len());
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            Some('\n') | Some('\r') | None => Ok(()),
+            Some(_) => Err(self.error(self.pos, "expected the end of the line")),
+        }
+    }
+
+    fn keys(&mut self) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"') => self.basic_string(),
+                Some('\'') => self.literal_string(),
+                _ => {
+                    let len = self
+                        .rest()
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                        .unwrap_or_else(|| self.rest().len());
+                    match len {
+                        0 => Err(self.error(self.pos, "expected a key")),
+                        _ => {
+                            self.pos += len;
+                            Ok(String::from(&self.text[self.pos - len..self.pos]))
+                        }
+                    }
+                }
+            };
+            keys.push(key?);
+            self.skip_spaces();
+            match self.peek() {
+                Some('.') => self.pos += 1,
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    fn header(&mut self) -> Result<(Vec<String>, bool), Error> {
+        let array = self.rest().starts_with("[[");
+        let (open, close) = match array {
+            true => (2, "]]"),
+            false => (1, "]"),
+        };
+        self.pos += open;
+        let path = self.keys()?;
+        match self.rest().starts_with(close) {
+            true => {
+                self.pos += close.len();
+                Ok((path, array))
+            }
+            false => Err(self.error(self.pos, &format!("expected '{}'", close))),
+        }
+    }
+
+    fn key_value(&mut self, depth: usize) -> Result<(Vec<String>, Value), Error> {
+        let path = self.keys()?;
+        match self.peek() {
+            Some('=') => self.pos += 1,
+            _ => return Err(self.error(self.pos, "expected '=' after a key")),
+        }
+        self.skip_spaces();
+        match self.value(depth) {
+            Ok(val) => Ok((path, val)),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(self.pos, "nested too deeply"));
+        }
+        let rest = self.rest();
+        match self.peek() {
+            Some('"') if rest.starts_with("\"\"\"") => {
+                self.multiline_basic_string().map(Value::Str)
+            }
+            Some('"') => self.basic_string().map(Value::Str),
+            Some('\'') if rest.starts_with("'''") => {
+                self.multiline_literal_string().map(Value::Str)
+            }
+            Some('\'') => self.literal_string().map(Value::Str),
+            Some('[') => self.array(depth),
+            Some('{') => self.inline_table(depth),
+            _ if rest.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            _ if rest.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            _ => match DATETIME_REGEX.find(rest) {
+                Some(m) => {
+                    self.pos += m.end();
+                    match data::date(m.as_str()) {
+                        Some(date) => Ok(date),
+                        None => Ok(Value::Str(String::from(m.as_str()))),
+                    }
+                }
+                None => self.number(),
+            },
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut vals = Vec::new();
+        loop {
+            self.skip_trivia();
+            if let Some(']') = self.peek() {
+                self.pos += 1;
+                return Ok(Value::Arr(vals));
+            }
+            vals.push(self.value(depth + 1)?);
+            self.skip_trivia();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error(self.pos, "expected ',' or ']' in an array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut fields = Table::new();
+        self.skip_spaces();
+        if let Some('}') = self.peek() {
+            self.pos += 1;
+            return Ok(Value::Record(fields));
+        }
+        loop {
+            let start = self.pos;
+            match self.key_value(depth + 1) {
+                Ok((path, val)) => {
+                    if let Err(msg) = insert(&mut fields, &path, val) {
+                        return Err(self.error(start, &msg));
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+            self.skip_spaces();
+            match self.peek() {
+                Some(',') => {
+                    self.pos += 1;
+                    self.skip_spaces();
+                }
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Record(fields));
+                }
+                _ => return Err(self.error(self.pos, "expected ',' or '}' in an inline table")),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    let c = self.escape()?;
+                    s.push(c)
+                }
+                Some('\n') | None => return Err(self.error(start, "unterminated string")),
+                Some(c) => {
+                    s.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn multiline_basic_string(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        self.pos += 3;
+        self.skip_newline();
+        let mut s = String::new();
+        loop {
+            let rest = self.rest();
+            // Up to two quotes may come right before the closing three.
+            if rest.starts_with("\"\"\"") && !rest.starts_with("\"\"\"\"") {
+                self.pos += 3;
+                return Ok(s);
+            }
+            match self.peek() {
+                // A backslash at the end of a line joins it to the next, without the whitespace.
+                Some('\\')
+                    if rest[1..]
+                        .trim_start_matches([' ', '\t'])
+                        .starts_with(['\n', '\r']) =>
+                {
+                    self.pos += 1;
+                    while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+                        self.pos += 1;
+                    }
+                }
+                Some('\\') => {
+                    let c = self.escape()?;
+                    s.push(c)
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += c.len_utf8();
+                }
+                None => return Err(self.error(start, "unterminated string")),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        match self.rest().find(['\'', '\n']) {
+            Some(i) if self.rest()[i..].starts_with('\'') => {
+                let s = String::from(&self.rest()[..i]);
+                self.pos += i + 1;
+                Ok(s)
+            }
+            _ => Err(self.error(start, "unterminated string")),
+        }
+    }
+
+    fn multiline_literal_string(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        self.pos += 3;
+        self.skip_newline();
+        match self.rest().find("'''") {
+            Some(i) => {
+                // As with quotes, up to two apostrophes may come right before the closing three.
+                let extra = self.rest()[i + 3..]
+                    .chars()
+                    .take_while(|c| *c == '\'')
+                    .count();
+                let s = String::from(&self.rest()[..i + extra.min(2)]);
+                self.pos += i + extra.min(2) + 3;
+                Ok(s)
+            }
+            None => Err(self.error(start, "unterminated string")),
+        }
+    }
+
+    /// Skips the line break right after the opening quotes of a multi-line string.
+    fn skip_newline(&mut self) {
+        if self.rest().starts_with("\r\n") {
+            self.pos += 2;
+        } else if self.rest().starts_with('\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let c = match self.peek() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ 'u') | Some(u @ 'U') => {
+                let len = match u {
+                    'u' => 4,
+                    _ => 8,
+                };
+                let code = self
+                    .text
+                    .get(self.pos + 1..self.pos + 1 + len)
+                    .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                    .and_then(std::char::from_u32);
+                match code {
+                    Some(c) => {
+                        self.pos += len + 1;
+                        return Ok(c);
+                    }
+                    None => return Err(self.error(start, "invalid unicode escape")),
+                }
+            }
+            _ => return Err(self.error(start, "invalid escape in a string")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_+-.".contains(c)))
+            .unwrap_or_else(|| self.rest().len());
+        let text = &self.rest()[..len];
+        let bad = || self.error(start, "expected a value");
+        let unsigned = text.trim_start_matches(['+', '-']);
+        match unsigned {
+            "inf" | "nan" => {
+                self.pos += len;
+                let n = match unsigned {
+                    "inf" => f64::INFINITY,
+                    _ => f64::NAN,
+                };
+                return match text.starts_with('-') {
+                    true => Ok(Value::Num(-n)),
+                    false => Ok(Value::Num(n)),
+                };
+            }
+            _ => {}
+        }
+        // Each `_` needs a digit on both sides.
+        let bytes = text.as_bytes();
+        for (i, b) in bytes.iter().enumerate() {
+            let digit = |j: usize| bytes.get(j).is_some_and(|b| b.is_ascii_hexdigit());
+            if *b == b'_' && (i == 0 || !digit(i - 1) || !digit(i + 1)) {
+                return Err(bad());
+            }
+        }
+        let digits = text.replace('_', "");
+        let radix = match digits.get(..2) {
+            Some("0x") => 16,
+            Some("0o") => 8,
+            Some("0b") => 2,
+            _ => 10,
+        };
+        let val = match radix {
+            10 if digits.contains(['.', 'e', 'E']) => {
+                // A point needs digits on both sides, which Rust's parser doesn't insist on.
+                let point_ok = match digits.find('.') {
+                    Some(i) => {
+                        i > 0
+                            && digits.as_bytes()[i - 1].is_ascii_digit()
+                            && digits.as_bytes().get(i + 1).is_some_and(u8::is_ascii_digit)
+                    }
+                    None => true,
+                };
+                match (point_ok, digits.parse::<f64>()) {
+                    (true, Ok(n)) => Some(Value::Num(n)),
+                    _ => None,
+                }
+            }
+            10 => {
+                let leading_zero = unsigned.len() > 1 && unsigned.starts_with('0');
+                match (leading_zero, digits.parse::<i64>()) {
+                    (false, Ok(n)) => Some(Value::Int(n)),
+                    _ => None,
+                }
+            }
+            _ => i64::from_str_radix(&digits[2..], radix)
+                .ok()
+                .map(Value::Int),
+        };
+        match val {
+            Some(val) => {
+                self.pos += len;
+                Ok(val)
+            }
+            None => Err(bad()),
+        }
+    }
+}
+
+fn write_table(out: &mut String, path: &[String], fields: &[(String, Value)]) -> Result<(), Error> {
+    let is_table_array = |val: &Value| match val {
+        Value::Arr(vals) => !vals.is_empty() && vals.iter().all(|v| matches!(v, Value::Record(_))),
+        _ => false,
+    };
+    for (k, v) in fields {
+        match v {
+            Value::Record(_) | Value::Void => {}
+            v if is_table_array(v) => {}
+            v => {
+                out.push_str(&key(k));
+                out.push_str(" = ");
+                write_value(out, v)?;
+                out.push('\n');
+            }
+        }
+    }
+    for (k, v) in fields {
+        let mut sub = path.to_vec();
+        sub.push(k.clone());
+        let header: Vec<String> = sub.iter().map(|k| key(k)).collect();
+        let tables: Vec<(String, &Table)> = match v {
+            Value::Record(fields) => vec![(format!("[{}]", header.join(".")), fields)],
+            Value::Arr(vals) if is_table_array(v) => vals
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Record(fields) => Some((format!("[[{}]]", header.join(".")), fields)),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        for (header, fields) in tables {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&header);
+            out.push('\n');
+            write_table(out, &sub, fields)?;
+        }
+    }
+    Ok(())
+}
+
+/// `k` as written before `=` or in a table header, quoted unless it is a bare key.
+fn key(k: &str) -> String {
+    match BARE_KEY_REGEX.is_match(k) {
+        true => String::from(k),
+        false => {
+            let mut out = String::new();
+            json::write_string(&mut out, k);
+            out
+        }
+    }
+}
+
+/// Writes `val` where it fits on one line: after `=`, in an array or in an inline table.
+fn write_value(out: &mut String, val: &Value) -> Result<(), Error> {
+    match val {
+        Value::Str(s) => json::write_string(out, s),
+        Value::Date(_) => out.push_str(&format!("{}", val)),
+        Value::Pipeline(_) | Value::Regex(_) => json::write_string(out, &format!("{}", val)),
+        Value::Bool(b) => out.push_str(&format!("{}", b)),
+        Value::Num(n) if n.is_nan() => out.push_str("nan"),
+        Value::Int(_) | Value::Num(_) | Value::Size(_) | Value::Duration(_) => {
+            match data::number_text(val) {
+                Some(text) => out.push_str(&text),
+                None => out.push_str(&format!("{}", val)),
+            }
+        }
+        Value::Arr(vals) => {
+            out.push('[');
+            for (i, v) in vals.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(out, v)?;
+            }
+            out.push(']');
+        }
+        Value::Range(range) => {
+            let vals: Vec<Value> = range.iter().map(Value::Int).collect();
+            return write_value(out, &Value::Arr(vals));
+        }
+        Value::Record(fields) => {
+            out.push('{');
+            let fields: Vec<&(String, Value)> = fields
+                .iter()
+                .filter(|(_, v)| !matches!(v, Value::Void))
+                .collect();
+            for (i, (k, v)) in fields.iter().enumerate() {
+                match i {
+                    0 => out.push(' '),
+                    _ => out.push_str(", "),
+                }
+                out.push_str(&key(k));
+                out.push_str(" = ");
+                write_value(out, v)?;
+            }
+            if !fields.is_empty() {
+                out.push(' ');
+            }
+            out.push('}');
+        }
+        Value::Void | Value::Closure(_) => {
+            return Err(Error::Type(
+                format!("Cannot write {} as TOML.", val.a_type_name()),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::interpreter::Options;
+use regex::Regex;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    static ref PLAIN_REGEX: Regex = Regex::new(r"^[[:alnum:]_@%+=:,./-]+$").unwrap();
+}
+
+/// Quotes `arg` so it reads back as exactly one shell word.
+pub fn quote(arg: &str) -> String {
+    if PLAIN_REGEX.is_match(arg) {
+        return String::from(arg);
+    }
+
+    if arg.chars().any(char::is_control) {
+        // ANSI-C quoting so that newlines and other control characters stay visible.
+        let mut quoted = String::from("$'");
+        for c in arg.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\\' => quoted.push_str("\\\\"),
+                '\'' => quoted.push_str("\\'"),
+                c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+pub fn command_line(program: &str, args: &[String]) -> String {
+    let mut words = vec![quote(program)];
+    words.extend(args.iter().map(|arg| quote(arg)));
+    words.join(" ")
+}
+
+/// Writes `+ <line>: <cmd>` before a command runs.
+pub fn before(opts: &Options, line: Option<usize>, cmd: &str) -> io::Result<()> {
+    write(opts, '+', line, cmd)
+}
+
+/// Writes `- <line>: exit <code> in <duration>` after a command runs, when timing is on.
+pub fn after(
+    opts: &Options,
+    line: Option<usize>,
+    code: Option<i32>,
+    took: Duration,
+) -> io::Result<()> {
+    if !opts.trace_timing {
+        return Ok(());
+    }
+    let status = match code {
+        Some(code) => format!("exit {}", code),
+        None => String::from("killed"),
+    };
+    let ms = took.as_secs_f64() * 1000.0;
+    write(opts, '-', line, &format!("{} in {:.3}ms", status, ms))
+}
+
+fn write(opts: &Options, marker: char, line: Option<usize>, msg: &str) -> io::Result<()> {
+    let mut text = format!("{} ", marker);
+    if opts.trace_timing {
+        text.push_str(&timestamp(SystemTime::now()));
+        text.push(' ');
+    }
+    if let Some(line) = line {
+        text.push_str(&format!("{}: ", line));
+    }
+    text.push_str(msg);
+    text.push('\n');
+
+    match &opts.trace_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(text.as_bytes())),
+        None => io::stderr().write_all(text.as_bytes()),
+    }
+}
+
+pub fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since.subsec_millis()
+    )
+}
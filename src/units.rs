@@ -0,0 +1,223 @@
+use crate::datetime::DateTime;
+use crate::error::Error;
+use crate::interpreter::{operand_error, Value};
+use crate::parser::BinOp;
+
+/// Size units in bytes.
+const SIZE_UNITS: &[(&str, i64)] = &[
+    ("b", 1),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("pb", 1_000_000_000_000_000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+    ("pib", 1 << 50),
+];
+
+/// Duration units in nanoseconds, largest first.
+const DURATION_UNITS: &[(&str, i64)] = &[
+    ("wk", 604_800_000_000_000),
+    ("d", 86_400_000_000_000),
+    ("day", 86_400_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("hr", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("min", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("sec", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ns", 1),
+];
+
+const DURATION_DISPLAY: &[&str] = &["d", "h", "m", "s", "ms", "us", "ns"];
+
+/// The bytes in a size literal such as `10kb`, `2MiB` or `1.5gb`.
+pub fn size(text: &str) -> Option<i64> {
+    match parts(text).as_slice() {
+        [(n, unit)] => {
+            let unit = unit.to_ascii_lowercase();
+            let (_, bytes) = SIZE_UNITS.iter().find(|(name, _)| *name == unit)?;
+            scale(n, *bytes)
+        }
+        _ => None,
+    }
+}
+
+/// The nanoseconds in a duration literal such as `500ms`, `2min` or `1h30m`.
+pub fn duration(text: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    for (n, unit) in parts(text) {
+        let (_, nanos) = DURATION_UNITS.iter().find(|(name, _)| *name == unit)?;
+        total = total.checked_add(scale(n, *nanos)?)?;
+    }
+    Some(total)
+}
+
+/// Splits `1h30m` into `("1", "h")` and `("30", "m")`.
+fn parts(text: &str) -> Vec<(&str, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let n = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let unit = rest[n..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| n + i);
+        parts.push((&rest[..n], &rest[n..unit]));
+        rest = &rest[unit..];
+    }
+    parts
+}
+
+fn scale(n: &str, size: i64) -> Option<i64> {
+    if !n.contains('.') {
+        return n.parse::<i64>().ok()?.checked_mul(size);
+    }
+    let x = n.parse::<f64>().ok()? * size as f64;
+    match x.is_finite() && x.abs() < i64::MAX as f64 {
+        true => Some(x.round() as i64),
+        false => None,
+    }
+}
+
+/// A size in the largest binary unit it reaches, to a tenth, as in `1.5 KiB`.
+pub fn format_size(bytes: i64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    let mut x = bytes as f64;
+    let mut unit = 0;
+    while x.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        x /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => {
+            let text = format!("{:.1}", x);
+            format!("{} {}", text.trim_end_matches(".0"), UNITS[unit])
+        }
+    }
+}
+
+/// A duration as each unit it spans, as in `1h30m` or `2s500ms`, which also reads back as a
+/// literal.
+pub fn format_duration(nanos: i64) -> String {
+    if nanos == 0 {
+        return String::from("0s");
+    }
+    let mut text = String::from(if nanos < 0 { "-" } else { "" });
+    let mut rest = nanos.unsigned_abs();
+    for unit in DURATION_DISPLAY {
+        let size = match DURATION_UNITS.iter().find(|(name, _)| name == unit) {
+            Some((_, size)) => *size as u64,
+            None => continue,
+        };
+        if rest >= size {
+            text.push_str(&format!("{}{}", rest / size, unit));
+            rest %= size;
+        }
+    }
+    text
+}
+
+/// A size or duration as an external command reads it: a count of bytes, or of seconds.
+pub fn argument(val: &Value) -> Option<String> {
+    match val {
+        Value::Size(bytes) => Some(format!("{}", bytes)),
+        Value::Duration(nanos) => Some(format!("{}", *nanos as f64 / 1e9)),
+        _ => None,
+    }
+}
+
+/// Whether the arithmetic of `lval` and `rval` is done here, which it is when either is a size,
+/// a duration or a date.
+pub fn applies(lval: &Value, rval: &Value) -> bool {
+    [lval, rval]
+        .iter()
+        .any(|val| matches!(val, Value::Size(_) | Value::Duration(_) | Value::Date(_)))
+}
+
+/// Arithmetic on sizes, durations and dates. Adding, subtracting and taking the remainder need two
+/// of the same kind, multiplying and dividing scale one by a number, and dividing one by another
+/// gives how many times it goes in. A date moves by a duration, and two dates are a duration
+/// apart.
+pub fn arith(op: BinOp, lval: &Value, rval: &Value) -> Result<Value, Error> {
+    let result = match (lval, rval) {
+        (Value::Date(date), Value::Duration(n)) | (Value::Duration(n), Value::Date(date))
+            if matches!(op, BinOp::Add) =>
+        {
+            Some(move_date(*date, Some(*n)))
+        }
+        (Value::Date(date), Value::Duration(n)) if matches!(op, BinOp::Sub) => {
+            Some(move_date(*date, n.checked_neg()))
+        }
+        (Value::Date(l), Value::Date(r)) if matches!(op, BinOp::Sub) => Some(
+            l.nanos
+                .checked_sub(r.nanos)
+                .map(Value::Duration)
+                .ok_or_else(overflow),
+        ),
+        (Value::Size(l), Value::Size(r)) => between(op, *l, *r, Value::Size),
+        (Value::Duration(l), Value::Duration(r)) => between(op, *l, *r, Value::Duration),
+        (Value::Size(n), k) => scale_by(op, *n, k, Value::Size),
+        (Value::Duration(n), k) => scale_by(op, *n, k, Value::Duration),
+        (k, Value::Size(n)) if matches!(op, BinOp::Mul) => scale_by(op, *n, k, Value::Size),
+        (k, Value::Duration(n)) if matches!(op, BinOp::Mul) => scale_by(op, *n, k, Value::Duration),
+        _ => None,
+    };
+    match result {
+        Some(result) => result,
+        None => Err(operand_error(op, lval, rval)),
+    }
+}
+
+fn move_date(date: DateTime, by: Option<i64>) -> Result<Value, Error> {
+    match by.and_then(|by| date.nanos.checked_add(by)) {
+        Some(nanos) => Ok(Value::Date(DateTime { nanos, ..date })),
+        None => Err(overflow()),
+    }
+}
+
+fn overflow() -> Error {
+    Error::Arith(String::from("Integer overflow."), None)
+}
+
+fn by_zero() -> Error {
+    Error::Arith(String::from("Division by zero."), None)
+}
+
+fn between(op: BinOp, l: i64, r: i64, wrap: fn(i64) -> Value) -> Option<Result<Value, Error>> {
+    match op {
+        BinOp::Add => Some(l.checked_add(r).map(wrap).ok_or_else(overflow)),
+        BinOp::Sub => Some(l.checked_sub(r).map(wrap).ok_or_else(overflow)),
+        BinOp::Mod | BinOp::Div | BinOp::IntDiv if r == 0 => Some(Err(by_zero())),
+        BinOp::Mod => Some(Ok(wrap(l.rem_euclid(r)))),
+        BinOp::Div => Some(Ok(Value::Num(l as f64 / r as f64))),
+        BinOp::IntDiv => Some(Ok(Value::Int(l.div_euclid(r)))),
+        _ => None,
+    }
+}
+
+fn scale_by(op: BinOp, n: i64, k: &Value, wrap: fn(i64) -> Value) -> Option<Result<Value, Error>> {
+    let scaled = |x: f64| match x.is_finite() && x.abs() < i64::MAX as f64 {
+        true => Ok(wrap(x.round() as i64)),
+        false => Err(overflow()),
+    };
+    match (op, k) {
+        (BinOp::Mul, Value::Int(k)) => Some(n.checked_mul(*k).map(wrap).ok_or_else(overflow)),
+        (BinOp::Mul, Value::Num(k)) => Some(scaled(n as f64 * k)),
+        (BinOp::Div, Value::Int(0)) | (BinOp::IntDiv, Value::Int(0)) => Some(Err(by_zero())),
+        (BinOp::Div, Value::Num(k)) if *k == 0.0 => Some(Err(by_zero())),
+        (BinOp::Div, Value::Int(k)) | (BinOp::IntDiv, Value::Int(k)) => {
+            Some(Ok(wrap(n.div_euclid(*k))))
+        }
+        (BinOp::Div, Value::Num(k)) => Some(scaled(n as f64 / k)),
+        _ => None,
+    }
+}
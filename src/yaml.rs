@@ -0,0 +1,684 @@
+use crate::data;
+use crate::error::{Error, Span};
+use crate::interpreter::Value;
+use crate::json;
+
+const MAX_DEPTH: usize = 512;
+
+/// Parses a YAML document into nested records and arrays. A single document is read, with block
+/// and flow collections, plain and quoted scalars, `|` and `>` block scalars and comments.
+/// Anchors, aliases, tags and `?` keys are reported as unsupported where they appear.
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    for raw in text.split('\n') {
+        let line = raw.trim_end_matches('\r');
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if line[indent..].starts_with('\t') && !line.trim().is_empty() {
+            return Err(data::error(
+                "YAML",
+                text,
+                pos + indent,
+                "tabs are not allowed in indentation",
+            ));
+        }
+        lines.push(Line {
+            pos: pos + indent,
+            indent,
+            text: &line[indent..],
+        });
+        pos += raw.len() + 1;
+    }
+    let mut parser = Parser { text, lines, i: 0 };
+
+    parser.skip_marker("---");
+    let val = match parser.lines.get(parser.i) {
+        Some(line) => parser.block(line.indent, 0)?,
+        None => Value::Void,
+    };
+    parser.skip_marker("...");
+    match parser.lines.get(parser.i) {
+        Some(line) => Err(data::error(
+            "YAML",
+            text,
+            line.pos,
+            "expected the end of the document",
+        )),
+        None => Ok(val),
+    }
+}
+
+pub fn to_string(val: &Value) -> Result<String, Error> {
+    let mut out = String::new();
+    match write_block(&mut out, val, 0) {
+        Ok(()) => Ok(String::from(out.trim_end_matches('\n'))),
+        Err(err) => Err(err),
+    }
+}
+
+fn resolve(s: &str) -> Value {
+    match s {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Void,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        ".inf" | "+.inf" | ".Inf" | "+.Inf" => Value::Num(f64::INFINITY),
+        "-.inf" | "-.Inf" => Value::Num(f64::NEG_INFINITY),
+        ".nan" | ".NaN" => Value::Num(f64::NAN),
+        _ => {
+            let radix = match s.get(..2) {
+                Some("0x") => Some(16),
+                Some("0o") => Some(8),
+                _ => None,
+            };
+            match radix.and_then(|radix| i64::from_str_radix(&s[2..], radix).ok()) {
+                Some(n) => Value::Int(n),
+                None => data::number(s)
+                    .or_else(|| data::date(s))
+                    .unwrap_or_else(|| Value::Str(String::from(s))),
+            }
+        }
+    }
+}
+
+fn unsupported(text: &str, pos: usize, feature: &str) -> Error {
+    let (line, col) = Span::new(pos, pos).line_col(text);
+    Error::Format(
+        format!(
+            "Unsupported YAML feature at line {}, column {}: {}.",
+            line, col, feature
+        ),
+        None,
+    )
+}
+
+/// The unsupported feature the node starting with `text` uses, if any.
+fn feature(text: &str) -> Option<&'static str> {
+    match text.chars().next() {
+        Some('&') => Some("anchors"),
+        Some('*') => Some("aliases"),
+        Some('!') => Some("tags"),
+        Some('?') if matches!(text[1..].chars().next(), None | Some(' ' | '\n' | '\r')) => {
+            Some("complex keys")
+        }
+        _ => None,
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text.starts_with("- ") || text.trim_end() == "-"
+}
+
+fn split_key(text: &str) -> Option<(String, &str)> {
+    let (key, end) = match text.chars().next() {
+        Some('"') | Some('\'') => {
+            let mut flow = Flow { text, pos: 0 };
+            match flow.quoted() {
+                Ok(key) => {
+                    flow.skip_spaces();
+                    (key, flow.pos)
+                }
+                Err(_) => return None,
+            }
+        }
+        Some('[') | Some('{') | Some('#') | None => return None,
+        _ => {
+            let end = colon(text)?;
+            (String::from(text[..end].trim_end()), end)
+        }
+    };
+    match colon(&text[end..]) {
+        Some(0) => Some((key, &text[end + 1..])),
+        _ => None,
+    }
+}
+
+/// Where the first colon followed by a space or the end of the line is, before any comment.
+fn colon(text: &str) -> Option<usize> {
+    let comment = text.find(" #").unwrap_or(text.len());
+    text[..comment]
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|i| matches!(text[i + 1..].chars().next(), None | Some(' ')))
+}
+
+struct Line<'a> {
+    pos: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    lines: Vec<Line<'a>>,
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, pos: usize, msg: &str) -> Error {
+        data::error("YAML", self.text, pos, msg)
+    }
+
+    fn skip(&mut self) {
+        while let Some(line) = self.lines.get(self.i) {
+            match line.text.trim_end() {
+                text if text.is_empty() || text.starts_with('#') => self.i += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_marker(&mut self, marker: &str) {
+        self.skip();
+        if let Some(line) = self.lines.get(self.i) {
+            if line.indent == 0 && line.text.trim_end() == marker {
+                self.i += 1;
+                self.skip();
+            }
+        }
+    }
+
+    /// The node starting on the next line, which is indented by `indent`.
+    fn block(&mut self, indent: usize, depth: usize) -> Result<Value, Error> {
+        let line = &self.lines[self.i];
+        if depth > MAX_DEPTH {
+            return Err(self.error(line.pos, "nested too deeply"));
+        }
+        if let Some(feature) = feature(line.text) {
+            return Err(unsupported(self.text, line.pos, feature));
+        }
+        match (is_item(line.text), split_key(line.text)) {
+            (true, _) => self.sequence(indent, depth),
+            (false, Some(_)) => self.mapping(indent, depth),
+            (false, None) => self.inline(line.pos, depth),
+        }
+    }
+
+    /// The block below a key or `-` with nothing after it on its line, or null if there is none.
+    fn nested(&mut self, indent: usize, depth: usize) -> Result<Value, Error> {
+        self.skip();
+        match self.lines.get(self.i) {
+            Some(line) if line.indent > indent => self.block(line.indent, depth),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize, depth: usize) -> Result<Value, Error> {
+        let mut items = Vec::new();
+        loop {
+            self.skip();
+            let line = match self.lines.get(self.i) {
+                Some(line) if line.indent == indent && is_item(line.text) => line,
+                Some(line) if line.indent > indent => {
+                    return Err(self.error(line.pos, "unexpected indentation"))
+                }
+                _ => return Ok(Value::Arr(items)),
+            };
+            let rest = line.text[1..].trim_start_matches(' ');
+            let item = match rest.trim_end().is_empty() || rest.starts_with('#') {
+                true => {
+                    self.i += 1;
+                    self.nested(indent, depth + 1)
+                }
+                false if rest.starts_with('|') || rest.starts_with('>') => {
+                    self.block_scalar(indent, rest)
+                }
+                // What follows `- ` is read as if it started a line of its own, so a mapping can
+                // carry on below it at the same column.
+                false => {
+                    let shift = line.text.len() - rest.len();
+                    self.lines[self.i] = Line {
+                        pos: line.pos + shift,
+                        indent: indent + shift,
+                        text: rest,
+                    };
+                    self.block(indent + shift, depth + 1)
+                }
+            };
+            items.push(item?);
+        }
+    }
+
+    fn mapping(&mut self, indent: usize, depth: usize) -> Result<Value, Error> {
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip();
+            let line = match self.lines.get(self.i) {
+                Some(line) if line.indent == indent => line,
+                Some(line) if line.indent > indent => {
+                    return Err(self.error(line.pos, "unexpected indentation"))
+                }
+                _ => return Ok(Value::Record(fields)),
+            };
+            let (pos, text) = (line.pos, line.text);
+            if let Some(feature) = feature(text) {
+                return Err(unsupported(self.text, pos, feature));
+            }
+            let (key, rest) = match split_key(text) {
+                Some(pair) => pair,
+                None => return Err(self.error(pos, "expected a key")),
+            };
+            if fields.iter().any(|(k, _)| *k == key) {
+                return Err(self.error(pos, &format!("{} is defined twice", key)));
+            }
+            let rest = rest.trim_start_matches(' ');
+            let val = match rest.trim_end() {
+                "" => {
+                    self.i += 1;
+                    self.skip();
+                    match self.lines.get(self.i) {
+                        // A sequence may sit at the same indentation as its key.
+                        Some(next) if next.indent == indent && is_item(next.text) => {
+                            self.sequence(indent, depth + 1)
+                        }
+                        _ => self.nested(indent, depth + 1),
+                    }
+                }
+                rest if rest.starts_with('#') => {
+                    self.i += 1;
+                    self.nested(indent, depth + 1)
+                }
+                rest if rest.starts_with('|') || rest.starts_with('>') => {
+                    self.block_scalar(indent, rest)
+                }
+                _ => self.inline(pos + text.len() - rest.len(), depth + 1),
+            };
+            fields.push((key, val?));
+        }
+    }
+
+    /// A `|` or `>` scalar: the more indented lines that follow, kept as they are or folded into
+    /// one, ending in a line break unless the header ends in `-`, or all of them with `+`.
+    fn block_scalar(&mut self, indent: usize, header: &str) -> Result<Value, Error> {
+        let folded = header.starts_with('>');
+        let chomp = match header[1..].find(" #") {
+            Some(i) => header[1..i + 1].trim_end(),
+            None => header[1..].trim_end(),
+        };
+        if !matches!(chomp, "" | "-" | "+") {
+            let pos = self.lines[self.i].pos;
+            return Err(self.error(pos, "expected '-' or '+' after '|' or '>'"));
+        }
+        self.i += 1;
+
+        let mut body: Vec<&str> = Vec::new();
+        let mut content = None;
+        while let Some(line) = self.lines.get(self.i) {
+            let start = line.pos - line.indent;
+            let end = line.pos + line.text.len();
+            match content {
+                _ if line.text.trim_end().is_empty() => body.push(""),
+                None if line.indent > indent => {
+                    content = Some(line.indent);
+                    body.push(line.text);
+                }
+                Some(content) if line.indent >= content => {
+                    body.push(&self.text[start + content..end])
+                }
+                _ => break,
+            }
+            self.i += 1;
+        }
+        // Blank lines at the end belong to the document rather than the scalar, unless kept.
+        let trailing = body.iter().rev().take_while(|line| line.is_empty()).count();
+        body.truncate(body.len() - trailing);
+
+        let mut s = String::new();
+        for (n, line) in body.iter().enumerate() {
+            if n > 0 {
+                match folded && !line.is_empty() && !body[n - 1].is_empty() {
+                    true => s.push(' '),
+                    false => s.push('\n'),
+                }
+            }
+            s.push_str(line);
+        }
+        match chomp {
+            _ if body.is_empty() => {}
+            "-" => {}
+            "+" => s.push_str(&"\n".repeat(trailing + 1)),
+            _ => s.push('\n'),
+        }
+        Ok(Value::Str(s))
+    }
+
+    /// A scalar or flow collection starting at byte `pos`, skipping the lines it covers.
+    fn inline(&mut self, pos: usize, depth: usize) -> Result<Value, Error> {
+        let mut flow = Flow {
+            text: self.text,
+            pos,
+        };
+        let val = flow.value(false, depth)?;
+        flow.skip_spaces();
+        match flow.peek() {
+            None | Some('\n') | Some('\r') | Some('#') => {}
+            Some(_) => return Err(flow.error("expected the end of the line")),
+        }
+        while self.i < self.lines.len() && self.lines[self.i].pos <= flow.pos {
+            self.i += 1;
+        }
+        Ok(val)
+    }
+}
+
+/// Reads scalars and flow collections, `[a, b]` and `{k: v}`, which may span lines.
+struct Flow<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Flow<'_> {
+    fn error(&self, msg: &str) -> Error {
+        data::error("YAML", self.text, self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('\n') | Some('\r') => self.pos += 1,
+                Some('#') => {
+                    let rest = &self.text[self.pos..];
+                    self.pos += rest.find('\n').unwrap_or(rest.len());
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn value(&mut self, in_flow: bool, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.peek() {
+            Some('[') => self.sequence(depth),
+            Some('{') => self.mapping(depth),
+            Some('"') | Some('\'') => self.quoted().map(Value::Str),
+            _ => match feature(&self.text[self.pos..]) {
+                Some(feature) => Err(unsupported(self.text, self.pos, feature)),
+                None => Ok(resolve(self.plain(in_flow))),
+            },
+        }
+    }
+
+    /// Unquoted text up to the end of the line or a comment, and inside a flow collection up to
+    /// its punctuation.
+    fn plain(&mut self, in_flow: bool) -> &str {
+        let rest = &self.text[self.pos..];
+        let mut end = rest.find(['\n', '\r']).unwrap_or(rest.len());
+        if let Some(i) = rest[..end].find(" #") {
+            end = i;
+        }
+        if in_flow {
+            let stop = rest[..end].char_indices().find(|(i, c)| {
+                ",[]{}".contains(*c)
+                    || (*c == ':'
+                        && matches!(
+                            rest[i + 1..].chars().next(),
+                            None | Some(' ') | Some(',') | Some(']') | Some('}') | Some('\n')
+                        ))
+            });
+            if let Some((i, _)) = stop {
+                end = i;
+            }
+        }
+        self.pos += end;
+        rest[..end].trim_end()
+    }
+
+    fn quoted(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        let quote = self.peek();
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    self.pos = start;
+                    return Err(self.error("unterminated string"));
+                }
+            };
+            self.pos += c.len_utf8();
+            match (quote, c) {
+                (Some('\''), '\'') if self.peek() == Some('\'') => {
+                    self.pos += 1;
+                    s.push('\'');
+                }
+                (Some(q), c) if q == c => return Ok(s),
+                (Some('"'), '\\') => {
+                    let c = self.escape()?;
+                    s.push(c)
+                }
+                // A line break inside quotes folds into a space, like the lines of a paragraph.
+                (_, '\n') => {
+                    s = String::from(s.trim_end_matches([' ', '\r']));
+                    s.push(' ');
+                    self.skip_spaces();
+                }
+                (_, c) => s.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, Error> {
+        let start = self.pos - 1;
+        let c = self.peek();
+        self.pos += 1;
+        let len = match c {
+            Some('x') => 2,
+            Some('u') => 4,
+            Some('U') => 8,
+            _ => 0,
+        };
+        let c = match c {
+            Some('0') => '\0',
+            Some('a') => '\u{7}',
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('v') => '\u{b}',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('e') => '\u{1b}',
+            Some(c @ ' ') | Some(c @ '"') | Some(c @ '/') | Some(c @ '\\') => c,
+            Some(_) if len > 0 => {
+                let code = self
+                    .text
+                    .get(self.pos..self.pos + len)
+                    .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                    .and_then(std::char::from_u32);
+                match code {
+                    Some(c) => {
+                        self.pos += len;
+                        c
+                    }
+                    None => {
+                        self.pos = start;
+                        return Err(self.error("invalid escape in a string"));
+                    }
+                }
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error("invalid escape in a string"));
+            }
+        };
+        Ok(c)
+    }
+
+    fn sequence(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Arr(items));
+                }
+                None => return Err(self.error("unterminated flow sequence")),
+                _ => {}
+            }
+            items.push(self.value(true, depth + 1)?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']' in a flow sequence")),
+            }
+        }
+    }
+
+    fn mapping(&mut self, depth: usize) -> Result<Value, Error> {
+        self.pos += 1;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip_blank();
+            let start = self.pos;
+            let key = match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Record(fields));
+                }
+                None => return Err(self.error("unterminated flow mapping")),
+                Some('"') | Some('\'') => self.quoted()?,
+                _ => String::from(self.plain(true)),
+            };
+            self.skip_blank();
+            match self.peek() {
+                Some(':') => self.pos += 1,
+                _ => return Err(self.error("expected ':' after a key")),
+            }
+            self.skip_blank();
+            let val = match self.peek() {
+                Some(',') | Some('}') => Value::Void,
+                _ => self.value(true, depth + 1)?,
+            };
+            if fields.iter().any(|(k, _)| *k == key) {
+                self.pos = start;
+                return Err(self.error(&format!("{} is defined twice", key)));
+            }
+            fields.push((key, val));
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err(self.error("expected ',' or '}' in a flow mapping")),
+            }
+        }
+    }
+}
+
+/// Whether `val` is written over lines of its own rather than after a key or `-`.
+fn is_block(val: &Value) -> bool {
+    match val {
+        Value::Record(fields) => !fields.is_empty(),
+        Value::Arr(vals) => !vals.is_empty(),
+        Value::Range(range) => !range.is_empty(),
+        _ => false,
+    }
+}
+
+fn write_block(out: &mut String, val: &Value, indent: usize) -> Result<(), Error> {
+    let pad = " ".repeat(indent);
+    match val {
+        Value::Record(fields) if is_block(val) => {
+            for (k, v) in fields {
+                out.push_str(&pad);
+                write_str(out, k);
+                out.push(':');
+                let written = match is_block(v) {
+                    true => {
+                        out.push('\n');
+                        write_block(out, v, indent + 2)
+                    }
+                    false => {
+                        out.push(' ');
+                        write_block(out, v, 0)
+                    }
+                };
+                written?;
+            }
+        }
+        Value::Arr(vals) if is_block(val) => {
+            for v in vals {
+                out.push_str(&pad);
+                out.push_str("- ");
+                // A nested block starts on the line of its `-`, lined up with the rest of it.
+                let mut item = String::new();
+                write_block(&mut item, v, indent + 2)?;
+                out.push_str(item.trim_start_matches(' '));
+            }
+        }
+        Value::Range(range) if is_block(val) => {
+            let vals: Vec<Value> = range.iter().map(Value::Int).collect();
+            return write_block(out, &Value::Arr(vals), indent);
+        }
+        Value::Record(_) => out.push_str("{}\n"),
+        Value::Arr(_) | Value::Range(_) => out.push_str("[]\n"),
+        Value::Void => out.push_str("null\n"),
+        Value::Bool(b) => out.push_str(&format!("{}\n", b)),
+        Value::Num(n) if !n.is_finite() => {
+            let text = match (n.is_nan(), *n > 0.0) {
+                (true, _) => ".nan\n",
+                (false, true) => ".inf\n",
+                (false, false) => "-.inf\n",
+            };
+            out.push_str(text);
+        }
+        Value::Int(_) | Value::Num(_) | Value::Size(_) | Value::Duration(_) => {
+            out.push_str(&data::number_text(val).unwrap_or_default());
+            out.push('\n');
+        }
+        Value::Str(s) => {
+            write_str(out, s);
+            out.push('\n');
+        }
+        Value::Date(_) => out.push_str(&format!("{}\n", val)),
+        Value::Pipeline(_) | Value::Regex(_) => {
+            write_str(out, &format!("{}", val));
+            out.push('\n');
+        }
+        Value::Closure(_) => {
+            return Err(Error::Type(
+                format!("Cannot write {} as YAML.", val.a_type_name()),
+                None,
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Writes `s` plain when it would read back as the same string, and in quotes otherwise. Older
+/// readers take more words as bools and more text as numbers than `resolve` does, so those are
+/// quoted too.
+fn write_str(out: &mut String, s: &str) {
+    let word = s.to_ascii_lowercase();
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && matches!(resolve(s), Value::Str(_))
+        && !matches!(word.as_str(), "y" | "n" | "yes" | "no" | "on" | "off")
+        && !s
+            .trim_start_matches(['+', '-'])
+            .starts_with(|c: char| c.is_ascii_digit() || c == '.')
+        && !s.starts_with(|c| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && !s.contains(char::is_control);
+    match plain {
+        true => out.push_str(s),
+        false => json::write_string(out, s),
+    }
+}